@@ -1,7 +1,7 @@
-use std::{convert::TryFrom, error::Error};
+use std::{convert::TryFrom, error::Error, path::Path};
 
 use ethers::{
-    abi::{Address, Tokenize},
+    abi::Tokenize,
     contract::Contract,
     prelude::{rand::thread_rng, ContractFactory, SignerMiddleware},
     providers::{Http, Middleware, Provider},
@@ -11,22 +11,40 @@ use ethers::{
 };
 use k256::ecdsa::SigningKey;
 
+use crate::week_1::{
+    digital_signatures::{Address, AddressError},
+    keystore::Keystore,
+};
+
 pub type ClientWithSigner = SignerMiddleware<Provider<Http>, Wallet<SigningKey>>;
 
 pub const DEFAULT_ACCOUNT_PRIVATE_KEY: &str =
     "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
 
-pub const DEFAULT_ACCOUNT_ADDRESS: &str = "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266";
+pub const DEFAULT_ACCOUNT_ADDRESS: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
 
 pub const ALTERNATIVE_ACCOUNT_PRIVATE_KEY: &str =
     "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
 
-pub const ALTERNATIVE_ACCOUNT_ADDRESS: &str = "0x70997970c51812dc3a010c7d01b50e0d17dc79c8";
+pub const ALTERNATIVE_ACCOUNT_ADDRESS: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
 
 pub const THIRD_ACCOUNT_PRIVATE_KEY: &str =
     "5de4111afa1a4b94908f83103eb1f1706367c2e68ca870fc3fb9a804cdab365a";
 
-pub const THIRD_ACCOUNT_ADDRESS: &str = "0x3c44cdddb6a900fa2b585dd299e03d12fa4293bc";
+pub const THIRD_ACCOUNT_ADDRESS: &str = "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC";
+
+// Parses an address, failing if it is mixed case and doesn't match its EIP-55 checksum
+#[allow(dead_code)]
+pub(crate) fn parse_address(address: &str) -> Result<H160, AddressError> {
+    address
+        .parse::<Address>()
+        .map(|address| H160(address.to_bytes()))
+}
+
+#[allow(dead_code)]
+pub(crate) fn checksum_address(address: H160) -> Address {
+    Address::from(address.0)
+}
 
 #[allow(dead_code)]
 pub fn get_provider() -> Provider<Http> {
@@ -35,12 +53,58 @@ pub fn get_provider() -> Provider<Http> {
     Provider::<Http>::try_from(url).expect("Could not create rpc provider")
 }
 
+// Where the password of a keystore comes from, so it doesn't have to live in the source
+#[allow(dead_code)]
+pub enum PasswordSource<'a> {
+    Plain(&'a str),
+    // Name of the environment variable holding it
+    Env(&'a str),
+    // File holding it, a trailing newline is ignored
+    File(&'a Path),
+}
+
+impl PasswordSource<'_> {
+    pub fn read(&self) -> Result<String, Box<dyn Error>> {
+        match self {
+            PasswordSource::Plain(password) => Ok(password.to_string()),
+            PasswordSource::Env(name) => Ok(std::env::var(name)?),
+            PasswordSource::File(path) => {
+                let password = std::fs::read_to_string(path)?;
+
+                Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub enum WalletSource<'a> {
+    PrivateKey(&'a str),
+    // Web3 Secret Storage v3 file
+    Keystore {
+        path: &'a Path,
+        password: PasswordSource<'a>,
+    },
+}
+
 #[allow(dead_code)]
-pub fn get_wallet(private_key: Option<&str>) -> Wallet<SigningKey> {
-    private_key
-        .unwrap_or(DEFAULT_ACCOUNT_PRIVATE_KEY)
-        .parse::<LocalWallet>()
-        .expect("Could not create wallet with given private key")
+pub fn get_wallet(source: Option<WalletSource>) -> Wallet<SigningKey> {
+    match source.unwrap_or(WalletSource::PrivateKey(DEFAULT_ACCOUNT_PRIVATE_KEY)) {
+        WalletSource::PrivateKey(private_key) => private_key
+            .parse::<LocalWallet>()
+            .expect("Could not create wallet with given private key"),
+        WalletSource::Keystore { path, password } => {
+            let password = password
+                .read()
+                .expect("Could not read the keystore password");
+            let keypair = Keystore::read(path)
+                .expect("Could not read the keystore")
+                .decrypt(&password)
+                .expect("Could not decrypt the keystore");
+
+            LocalWallet::from(keypair.signing_key().clone())
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -49,7 +113,7 @@ pub fn get_provider_with_signer(
     chain_id: Option<u64>,
 ) -> ClientWithSigner {
     let provider = get_provider();
-    let wallet = get_wallet(private_key);
+    let wallet = get_wallet(private_key.map(WalletSource::PrivateKey));
 
     let chain_id = chain_id.unwrap_or(31337_u64);
 
@@ -62,7 +126,7 @@ pub async fn send_ether(
     amount: i128,
     to: Option<&str>,
 ) -> Result<(), Box<dyn Error>> {
-    let to = to.unwrap_or(DEFAULT_ACCOUNT_ADDRESS).parse::<Address>()?;
+    let to = parse_address(to.unwrap_or(DEFAULT_ACCOUNT_ADDRESS))?;
 
     let nonce = client
         .get_transaction_count(
@@ -1,4 +1,17 @@
+pub mod attack_simulator;
+pub mod block_tree;
 pub mod blockchain_network;
 pub mod digital_signatures;
+pub mod encoding;
+pub mod ecies;
+pub mod fee_market;
+pub mod hd_wallet;
+pub mod keystore;
+pub mod ledger;
+pub mod network_simulator;
+pub mod persistence;
+pub mod proof_of_stake;
 pub mod proof_of_work;
 pub mod the_first_primitives;
+pub mod transaction_pool;
+pub mod typed_data;
@@ -0,0 +1,426 @@
+use k256::ecdsa::SigningKey;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{
+    ledger::LedgerConfig,
+    proof_of_work::{signer_address, DifficultyConfig, Miner, Transaction, TRANSFER_GAS},
+    transaction_pool::PoolConfig,
+};
+
+// Attack Simulator
+//
+// The honest miners are modeled as a single miner holding the rest of the hashpower, and blocks
+// propagate instantly. Every step one of the two finds a block, the adversary with a probability
+// equal to its share. Blocks are really mined, and the honest miner only follows the adversary
+// when `replace_chain` accepts its chain, so ties go to the block it saw first.
+
+// Receives the payment the adversary tries to double spend
+pub const MERCHANT_ADDRESS: &str = "merchant";
+const HONEST_ADDRESS: &str = "honest";
+const PAYMENT: u128 = 1_000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AttackStrategy {
+    // Mines a fork from the genesis block in private and publishes it once it is longer than the
+    // public chain, succeeding if that reverts at least `depth` honest blocks
+    PrivateChain { depth: usize },
+    // Pays the merchant on the public chain while mining a conflicting payment in private. It
+    // succeeds if the private chain wins after the payment got `confirmations` blocks.
+    DoubleSpend { confirmations: usize },
+    // Withholds blocks and releases them to waste honest work, as described by Eyal and Sirer.
+    // It succeeds if the adversary ends up with more blocks than its share of the hashpower.
+    SelfishMining,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AttackConfig {
+    pub seed: u64,
+    pub runs: usize,
+    // Fraction of the hashpower held by the adversary, between 0 and 1
+    pub adversary_share: f64,
+    pub strategy: AttackStrategy,
+    // Blocks mined in a run before the attack is given up
+    pub max_blocks: usize,
+    // The adversary gives up once the public chain is this many blocks ahead of its own
+    pub max_deficit: usize,
+}
+
+impl AttackConfig {
+    pub fn new(seed: u64, adversary_share: f64, strategy: AttackStrategy) -> Self {
+        Self {
+            seed,
+            runs: 100,
+            adversary_share,
+            strategy,
+            max_blocks: 100,
+            max_deficit: 10,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AttackReport {
+    pub runs: usize,
+    pub successes: usize,
+    pub success_probability: f64,
+    // Share of the final chain's blocks mined by the adversary, averaged over the runs
+    pub adversary_block_share: f64,
+    // Honest blocks the adversary's chains pushed out of the public chain, over all runs
+    pub reverted_blocks: usize,
+}
+
+struct RunOutcome {
+    success: bool,
+    adversary_block_share: f64,
+    reverted_blocks: usize,
+}
+
+// Both sides of a single run
+struct Race {
+    honest: Miner,
+    adversary: Miner,
+    adversary_key: SigningKey,
+    step: u64,
+    reverted_blocks: usize,
+}
+
+impl Race {
+    fn new(adversary_key: SigningKey) -> Self {
+        // The difficulty stays fixed, so the longest chain is also the one with the most work
+        let difficulty = DifficultyConfig {
+            retarget_interval: usize::MAX,
+            ..DifficultyConfig::new()
+        };
+
+        // Just enough for one payment at a gas price of 1, so the two payments conflict
+        let mut ledger_config = LedgerConfig::new();
+        ledger_config.allocations.insert(
+            signer_address(&adversary_key),
+            PAYMENT + TRANSFER_GAS as u128,
+        );
+
+        let mut honest = Miner::with_config(difficulty, PoolConfig::new(), ledger_config.clone());
+        let mut adversary = Miner::with_config(difficulty, PoolConfig::new(), ledger_config);
+
+        honest.set_beneficiary(String::from(HONEST_ADDRESS));
+        adversary.set_beneficiary(signer_address(&adversary_key));
+
+        honest.mine_at(0);
+        adversary
+            .import_block(honest.get_blocks()[0].clone())
+            .unwrap();
+
+        Self {
+            honest,
+            adversary,
+            adversary_key,
+            step: 0,
+            reverted_blocks: 0,
+        }
+    }
+
+    // Mines the next block on one of the two chains, returning true if the adversary found it
+    fn mine(&mut self, rng: &mut StdRng, adversary_share: f64) -> bool {
+        self.step += 1;
+
+        let timestamp = self.step * DifficultyConfig::new().target_block_time;
+        let is_adversary = rng.gen_bool(adversary_share.clamp(0.0, 1.0));
+
+        if is_adversary {
+            self.adversary.mine_at(timestamp);
+        } else {
+            self.honest.mine_at(timestamp);
+        }
+
+        is_adversary
+    }
+
+    // Publishes the private chain, returning true if the honest miner switched to it
+    fn publish(&mut self) -> bool {
+        match self
+            .honest
+            .replace_chain(self.adversary.get_blocks().to_vec())
+        {
+            Ok(reorg) => {
+                self.reverted_blocks += reorg.reverted.len();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    // Drops the private chain and mines on top of the public one again
+    fn adopt_public_chain(&mut self) {
+        let public_blocks = self.honest.get_blocks();
+        let height = self.adversary.get_block_height();
+
+        let is_prefix = public_blocks.get(height - 1).map(|block| block.hash())
+            == self.adversary.get_blocks().last().map(|block| block.hash());
+
+        // Without withheld blocks the new public blocks are simply imported, which is much
+        // cheaper than validating the whole chain again
+        if !is_prefix {
+            let _ = self.adversary.replace_chain(public_blocks.to_vec());
+            return;
+        }
+
+        for block in public_blocks[height..].iter() {
+            let _ = self.adversary.import_block(block.clone());
+        }
+    }
+
+    // Number of blocks the private chain is ahead of the public one, negative when behind
+    fn lead(&self) -> i64 {
+        self.adversary.get_block_height() as i64 - self.honest.get_block_height() as i64
+    }
+
+    fn adversary_block_share(&self) -> f64 {
+        let adversary = signer_address(&self.adversary_key);
+        let blocks = &self.honest.get_blocks()[1..];
+
+        if blocks.is_empty() {
+            return 0.0;
+        }
+
+        let mined = blocks
+            .iter()
+            .filter(|block| block.beneficiary() == adversary)
+            .count();
+
+        mined as f64 / blocks.len() as f64
+    }
+}
+
+pub struct AttackSimulator {
+    config: AttackConfig,
+    rng: StdRng,
+}
+
+impl AttackSimulator {
+    pub fn new(config: AttackConfig) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+        }
+    }
+
+    pub fn run(mut self) -> AttackReport {
+        let outcomes: Vec<RunOutcome> = (0..self.config.runs).map(|_| self.run_once()).collect();
+
+        let successes = outcomes.iter().filter(|outcome| outcome.success).count();
+        let runs = outcomes.len().max(1) as f64;
+
+        AttackReport {
+            runs: outcomes.len(),
+            successes,
+            success_probability: successes as f64 / runs,
+            adversary_block_share: outcomes
+                .iter()
+                .map(|outcome| outcome.adversary_block_share)
+                .sum::<f64>()
+                / runs,
+            reverted_blocks: outcomes.iter().map(|outcome| outcome.reverted_blocks).sum(),
+        }
+    }
+
+    fn run_once(&mut self) -> RunOutcome {
+        let mut race = Race::new(SigningKey::random(&mut self.rng));
+
+        let success = match self.config.strategy {
+            AttackStrategy::PrivateChain { depth } => self.private_chain(&mut race, depth),
+            AttackStrategy::DoubleSpend { confirmations } => {
+                self.double_spend(&mut race, confirmations)
+            }
+            AttackStrategy::SelfishMining => self.selfish_mining(&mut race),
+        };
+
+        RunOutcome {
+            success,
+            adversary_block_share: race.adversary_block_share(),
+            reverted_blocks: race.reverted_blocks,
+        }
+    }
+
+    // Mines in private until the fork is ahead of a public chain that satisfies `is_target`,
+    // then publishes it. Gives up when falling too far behind or running out of blocks.
+    fn race_to(&mut self, race: &mut Race, is_target: impl Fn(&Miner) -> bool) -> bool {
+        for _ in 0..self.config.max_blocks {
+            race.mine(&mut self.rng, self.config.adversary_share);
+
+            if race.lead() > 0 && is_target(&race.honest) {
+                return race.publish();
+            }
+
+            if -race.lead() >= self.config.max_deficit as i64 {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    fn private_chain(&mut self, race: &mut Race, depth: usize) -> bool {
+        let reverted = self.race_to(race, |honest| honest.get_block_height() > depth);
+
+        reverted && race.reverted_blocks >= depth
+    }
+
+    fn double_spend(&mut self, race: &mut Race, confirmations: usize) -> bool {
+        let merchant_payment = Transaction::sign(
+            &race.adversary_key,
+            String::from(MERCHANT_ADDRESS),
+            PAYMENT,
+            1,
+            0,
+        );
+        let conflicting_payment = Transaction::sign(
+            &race.adversary_key,
+            signer_address(&race.adversary_key),
+            PAYMENT,
+            1,
+            0,
+        );
+
+        race.honest.add_transaction(merchant_payment).unwrap();
+        race.adversary.add_transaction(conflicting_payment).unwrap();
+
+        // The merchant hands the goods over once the payment is buried deep enough
+        let is_confirmed = |honest: &Miner| {
+            honest
+                .get_blocks()
+                .iter()
+                .position(|block| {
+                    block
+                        .transactions()
+                        .iter()
+                        .any(|transaction| transaction.to() == MERCHANT_ADDRESS)
+                })
+                .is_some_and(|idx| honest.get_block_height() - idx >= confirmations)
+        };
+
+        self.race_to(race, is_confirmed) && race.honest.get_balance(MERCHANT_ADDRESS) == 0
+    }
+
+    fn selfish_mining(&mut self, race: &mut Race) -> bool {
+        // A published private block ties with an honest one and the honest miner sticks to its own
+        let mut is_racing = false;
+
+        for _ in 0..self.config.max_blocks {
+            let lead_before = race.lead();
+
+            if race.mine(&mut self.rng, self.config.adversary_share) {
+                // Winning the tie race settles it in the adversary's favour
+                if is_racing {
+                    race.publish();
+                    is_racing = false;
+                }
+
+                continue;
+            }
+
+            if is_racing {
+                race.adopt_public_chain();
+                is_racing = false;
+                continue;
+            }
+
+            match lead_before {
+                // Nothing withheld, the adversary follows the honest chain
+                ..=0 => race.adopt_public_chain(),
+                // The withheld block is released to tie with the honest one
+                1 => {
+                    race.publish();
+                    is_racing = true;
+                }
+                // Releasing everything orphans the honest block while the lead is about to vanish
+                2 => {
+                    race.publish();
+                }
+                // Still safely ahead, the honest block will be orphaned later
+                _ => {}
+            }
+        }
+
+        if race.lead() > 0 {
+            race.publish();
+        }
+
+        race.adversary_block_share() > self.config.adversary_share
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod run {
+        use crate::week_1::attack_simulator::{AttackConfig, AttackSimulator, AttackStrategy};
+
+        fn double_spend(seed: u64, adversary_share: f64, confirmations: usize) -> f64 {
+            let config = AttackConfig::new(
+                seed,
+                adversary_share,
+                AttackStrategy::DoubleSpend { confirmations },
+            );
+
+            AttackSimulator::new(config).run().success_probability
+        }
+
+        #[test]
+        fn should_be_reproducible_with_the_same_seed() {
+            let mut config =
+                AttackConfig::new(7, 0.3, AttackStrategy::DoubleSpend { confirmations: 2 });
+            config.runs = 20;
+
+            let first = AttackSimulator::new(config).run();
+            let second = AttackSimulator::new(config).run();
+
+            assert_eq!(first, second);
+        }
+
+        #[test]
+        fn should_almost_always_double_spend_with_a_majority() {
+            assert!(double_spend(1, 0.6, 6) > 0.9);
+        }
+
+        #[test]
+        fn should_rarely_double_spend_a_deeply_confirmed_payment() {
+            assert!(double_spend(2, 0.1, 6) < 0.05);
+        }
+
+        #[test]
+        fn should_be_harder_to_double_spend_with_more_confirmations() {
+            assert!(double_spend(3, 0.3, 1) > double_spend(3, 0.3, 6) + 0.2);
+        }
+
+        #[test]
+        fn should_revert_honest_blocks_with_a_private_chain() {
+            let config = AttackConfig::new(4, 0.6, AttackStrategy::PrivateChain { depth: 3 });
+
+            let report = AttackSimulator::new(config).run();
+
+            assert!(report.success_probability > 0.9);
+            assert!(report.reverted_blocks >= report.successes * 3);
+        }
+
+        #[test]
+        fn should_earn_more_than_its_share_with_selfish_mining() {
+            let mut config = AttackConfig::new(5, 0.4, AttackStrategy::SelfishMining);
+            config.runs = 30;
+
+            let report = AttackSimulator::new(config).run();
+
+            assert!(report.adversary_block_share > 0.42);
+            assert!(report.reverted_blocks > 0);
+        }
+
+        #[test]
+        fn should_lose_revenue_with_selfish_mining_below_a_third() {
+            let mut config = AttackConfig::new(6, 0.1, AttackStrategy::SelfishMining);
+            config.runs = 30;
+
+            let report = AttackSimulator::new(config).run();
+
+            assert!(report.adversary_block_share < 0.1);
+        }
+    }
+}
@@ -0,0 +1,615 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+    hash::Hash,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+// Block Tree
+//
+// Keeps every valid block instead of only the canonical chain, so that competing blocks at the
+// same height are remembered. The canonical head is the block with the most cumulative work.
+// Every block keeps the state the validator computed for it, so a child is checked against its
+// parent's state instead of replaying the branch.
+
+// Blocks kept while waiting on the same unknown parent
+pub const MAX_ORPHANS_PER_PARENT: usize = 8;
+// Unknown parents the tree waits on, the oldest one is forgotten first
+pub const MAX_ORPHAN_PARENTS: usize = 100;
+
+// What the tree needs to know about a block to place it
+pub trait TreeBlock: Clone {
+    type Hash: Clone + Eq + Hash + Debug;
+
+    fn hash(&self) -> Self::Hash;
+
+    fn parent_hash(&self) -> Self::Hash;
+
+    fn work(&self) -> u128;
+}
+
+#[derive(PartialEq, Debug)]
+pub enum TreeError<E> {
+    AlreadyKnown,
+    InvalidBlock(E),
+}
+
+// Blocks that left the canonical chain and the blocks that replaced them, both in chain order
+#[derive(Clone, Debug)]
+pub struct Reorg<B> {
+    pub reverted: Vec<B>,
+    pub applied: Vec<B>,
+}
+
+#[derive(Debug)]
+pub enum InsertOutcome<B> {
+    // The parent isn't known yet, the block waits until it arrives
+    Orphaned,
+    // The block and the orphans waiting on it joined the tree
+    Connected {
+        blocks: usize,
+        reorg: Option<Reorg<B>>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct TreeStats {
+    pub blocks: usize,
+    pub height: u64,
+    // Valid blocks that aren't part of the canonical chain
+    pub stale_blocks: usize,
+    // Stale blocks whose parent is canonical, the ones Ethereum would reward as uncles
+    pub uncles: usize,
+    pub orphans: usize,
+    // Head changes that reverted at least one block
+    pub reorgs: usize,
+    pub deepest_reorg: usize,
+}
+
+struct TreeNode<B: TreeBlock, S> {
+    block: B,
+    height: u64,
+    cumulative_work: u128,
+    state: S,
+}
+
+// The blocks a new block builds on, from the genesis block up to its parent
+pub struct Branch<'a, B: TreeBlock, S = ()> {
+    tree: &'a BlockTree<B, S>,
+    tip: &'a TreeNode<B, S>,
+}
+
+impl<'a, B: TreeBlock, S> Branch<'a, B, S> {
+    pub fn height(&self) -> u64 {
+        self.tip.height
+    }
+
+    pub fn tip(&self) -> &'a B {
+        &self.tip.block
+    }
+
+    // State the validator computed for the tip
+    pub fn state(&self) -> &'a S {
+        &self.tip.state
+    }
+
+    // Walks back from the tip, so blocks close to it are the cheap ones to reach
+    pub fn get(&self, height: u64) -> Option<&'a B> {
+        let mut node = self.tip;
+
+        while node.height > height {
+            node = &self.tree.nodes[&node.block.parent_hash()];
+        }
+
+        (node.height == height).then_some(&node.block)
+    }
+}
+
+pub struct BlockTree<B: TreeBlock, S = ()> {
+    nodes: HashMap<B::Hash, TreeNode<B, S>>,
+    // Hashes of the canonical chain, indexed by height
+    canonical: Vec<B::Hash>,
+    // Blocks whose parent is unknown, grouped by that parent
+    orphans: HashMap<B::Hash, VecDeque<B>>,
+    // Parents the orphans wait on, oldest first
+    orphan_parents: VecDeque<B::Hash>,
+    reorgs: usize,
+    deepest_reorg: usize,
+    subscribers: Vec<Sender<Reorg<B>>>,
+}
+
+impl<B: TreeBlock> BlockTree<B> {
+    pub fn new(genesis: B) -> Self {
+        Self::with_state(genesis, ())
+    }
+}
+
+impl<B: TreeBlock, S> BlockTree<B, S> {
+    pub fn with_state(genesis: B, state: S) -> Self {
+        let hash = genesis.hash();
+        let node = TreeNode {
+            cumulative_work: genesis.work(),
+            block: genesis,
+            height: 0,
+            state,
+        };
+
+        Self {
+            nodes: HashMap::from([(hash.clone(), node)]),
+            canonical: vec![hash],
+            orphans: HashMap::new(),
+            orphan_parents: VecDeque::new(),
+            reorgs: 0,
+            deepest_reorg: 0,
+            subscribers: Vec::new(),
+        }
+    }
+
+    // Every head change is sent to the receiver, until it is dropped
+    pub fn subscribe(&mut self) -> Receiver<Reorg<B>> {
+        let (sender, receiver) = channel();
+
+        self.subscribers.push(sender);
+
+        receiver
+    }
+
+    pub fn head(&self) -> &B {
+        self.get(self.canonical.last().unwrap()).unwrap()
+    }
+
+    pub fn height(&self) -> u64 {
+        self.canonical.len() as u64 - 1
+    }
+
+    pub fn cumulative_work(&self) -> u128 {
+        self.nodes[self.canonical.last().unwrap()].cumulative_work
+    }
+
+    pub fn get(&self, hash: &B::Hash) -> Option<&B> {
+        self.nodes.get(hash).map(|node| &node.block)
+    }
+
+    pub fn contains(&self, hash: &B::Hash) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    pub fn is_canonical(&self, hash: &B::Hash) -> bool {
+        self.nodes
+            .get(hash)
+            .and_then(|node| self.canonical.get(node.height as usize))
+            == Some(hash)
+    }
+
+    pub fn canonical_chain(&self) -> Vec<B> {
+        self.canonical
+            .iter()
+            .map(|hash| self.nodes[hash].block.clone())
+            .collect()
+    }
+
+    // Blocks from the genesis block up to the given one
+    pub fn branch(&self, hash: &B::Hash) -> Option<Branch<'_, B, S>> {
+        self.nodes.get(hash).map(|tip| Branch { tree: self, tip })
+    }
+
+    pub fn uncles(&self) -> Vec<&B> {
+        self.nodes
+            .values()
+            .filter(|node| {
+                node.height > 0
+                    && !self.is_canonical(&node.block.hash())
+                    && self.is_canonical(&node.block.parent_hash())
+            })
+            .map(|node| &node.block)
+            .collect()
+    }
+
+    pub fn stats(&self) -> TreeStats {
+        TreeStats {
+            blocks: self.nodes.len(),
+            height: self.height(),
+            stale_blocks: self.nodes.len() - self.canonical.len(),
+            uncles: self.uncles().len(),
+            orphans: self.orphans.values().map(VecDeque::len).sum(),
+            reorgs: self.reorgs,
+            deepest_reorg: self.deepest_reorg,
+        }
+    }
+
+    // Adds the block once `validate` accepts it on top of its branch, returning the block's state,
+    // then connects the orphans that were waiting on it. Orphans that turn out to be invalid are
+    // dropped. The head moves when a new block carries strictly more cumulative work than the
+    // current one.
+    pub fn insert<E>(
+        &mut self,
+        block: B,
+        mut validate: impl FnMut(&Branch<B, S>, &B) -> Result<S, E>,
+    ) -> Result<InsertOutcome<B>, TreeError<E>> {
+        let hash = block.hash();
+
+        if self.contains(&hash) || self.is_orphan(&block) {
+            return Err(TreeError::AlreadyKnown);
+        }
+
+        let Some(branch) = self.branch(&block.parent_hash()) else {
+            self.add_orphan(block);
+
+            return Ok(InsertOutcome::Orphaned);
+        };
+
+        let state = validate(&branch, &block).map_err(TreeError::InvalidBlock)?;
+
+        let mut connected = vec![hash.clone()];
+        let mut parents = VecDeque::from([hash]);
+
+        self.attach(block, state);
+
+        while let Some(parent_hash) = parents.pop_front() {
+            let Some(children) = self.orphans.remove(&parent_hash) else {
+                continue;
+            };
+
+            self.orphan_parents.retain(|hash| *hash != parent_hash);
+
+            for child in children {
+                let branch = self.branch(&parent_hash).unwrap();
+
+                let Ok(state) = validate(&branch, &child) else {
+                    continue;
+                };
+
+                connected.push(child.hash());
+                parents.push_back(child.hash());
+                self.attach(child, state);
+            }
+        }
+
+        let best = connected
+            .iter()
+            .fold(None, |best: Option<&B::Hash>, hash| match best {
+                Some(best)
+                    if self.nodes[best].cumulative_work >= self.nodes[hash].cumulative_work =>
+                {
+                    Some(best)
+                }
+                _ => Some(hash),
+            })
+            .unwrap()
+            .clone();
+
+        let reorg = if self.nodes[&best].cumulative_work > self.cumulative_work() {
+            Some(self.set_head(best))
+        } else {
+            None
+        };
+
+        Ok(InsertOutcome::Connected {
+            blocks: connected.len(),
+            reorg,
+        })
+    }
+
+    fn is_orphan(&self, block: &B) -> bool {
+        let hash = block.hash();
+
+        self.orphans
+            .get(&block.parent_hash())
+            .is_some_and(|siblings| siblings.iter().any(|orphan| orphan.hash() == hash))
+    }
+
+    // Keeps at most a few blocks per unknown parent, so a peer sending many blocks on one missing
+    // parent can't push out the ones waiting on others
+    fn add_orphan(&mut self, block: B) {
+        let parent_hash = block.parent_hash();
+
+        if !self.orphans.contains_key(&parent_hash) {
+            if self.orphan_parents.len() == MAX_ORPHAN_PARENTS {
+                if let Some(oldest) = self.orphan_parents.pop_front() {
+                    self.orphans.remove(&oldest);
+                }
+            }
+
+            self.orphan_parents.push_back(parent_hash.clone());
+        }
+
+        let siblings = self.orphans.entry(parent_hash).or_default();
+
+        siblings.push_back(block);
+
+        if siblings.len() > MAX_ORPHANS_PER_PARENT {
+            siblings.pop_front();
+        }
+    }
+
+    fn attach(&mut self, block: B, state: S) {
+        let parent = &self.nodes[&block.parent_hash()];
+        let node = TreeNode {
+            height: parent.height + 1,
+            cumulative_work: parent.cumulative_work.saturating_add(block.work()),
+            block,
+            state,
+        };
+
+        self.nodes.insert(node.block.hash(), node);
+    }
+
+    fn set_head(&mut self, hash: B::Hash) -> Reorg<B> {
+        let mut applied = Vec::new();
+        let mut current = hash;
+
+        // Walks back from the new head until it meets the canonical chain
+        while !self.is_canonical(&current) {
+            let node = &self.nodes[&current];
+
+            applied.push(node.block.clone());
+            current = node.block.parent_hash();
+        }
+
+        applied.reverse();
+
+        let fork_height = self.nodes[&current].height as usize;
+        let reverted: Vec<B> = self
+            .canonical
+            .split_off(fork_height + 1)
+            .iter()
+            .map(|hash| self.nodes[hash].block.clone())
+            .collect();
+
+        self.canonical
+            .extend(applied.iter().map(|block| block.hash()));
+
+        if !reverted.is_empty() {
+            self.reorgs += 1;
+            self.deepest_reorg = self.deepest_reorg.max(reverted.len());
+        }
+
+        let reorg = Reorg { reverted, applied };
+
+        self.subscribers
+            .retain(|subscriber| subscriber.send(reorg.clone()).is_ok());
+
+        reorg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::U256;
+
+    use crate::week_1::{
+        block_tree::Branch,
+        proof_of_work::{target_to_bits, Block},
+    };
+
+    // Every test block carries the same work, so heavier branches are simply longer ones
+    fn bits() -> u32 {
+        target_to_bits(U256::ONE.shl_vartime(240))
+    }
+
+    pub fn genesis() -> Block {
+        Block::new(
+            U256::ZERO,
+            0,
+            U256::ZERO,
+            U256::ZERO,
+            0,
+            bits(),
+            String::new(),
+            Vec::new(),
+        )
+    }
+
+    // Unmined child of the parent, the tag tells siblings apart
+    pub fn child(parent: &Block, tag: u64) -> Block {
+        Block::new(
+            U256::from(tag),
+            0,
+            parent.id().wrapping_add(&U256::ONE),
+            parent.hash(),
+            0,
+            bits(),
+            String::new(),
+            Vec::new(),
+        )
+    }
+
+    fn accept(_: &Branch<Block, ()>, _: &Block) -> Result<(), ()> {
+        Ok(())
+    }
+
+    mod insert {
+        use k256::U256;
+
+        use crate::week_1::{
+            block_tree::{
+                tests::{accept, child, genesis},
+                BlockTree, InsertOutcome, TreeError, MAX_ORPHANS_PER_PARENT,
+            },
+            proof_of_work::Block,
+        };
+
+        #[test]
+        fn should_extend_the_head() {
+            let genesis = genesis();
+            let block = child(&genesis, 1);
+            let mut tree = BlockTree::new(genesis);
+
+            let outcome = tree.insert(block.clone(), accept).unwrap();
+
+            let InsertOutcome::Connected {
+                blocks: 1,
+                reorg: Some(reorg),
+            } = outcome
+            else {
+                panic!("the block should have become the head");
+            };
+
+            assert!(reorg.reverted.is_empty());
+            assert_eq!(reorg.applied[0].hash(), block.hash());
+            assert_eq!(tree.head().hash(), block.hash());
+            assert_eq!(tree.height(), 1);
+        }
+
+        #[test]
+        fn should_keep_the_first_block_seen_on_a_tie() {
+            let genesis = genesis();
+            let first = child(&genesis, 1);
+            let second = child(&genesis, 2);
+            let mut tree = BlockTree::new(genesis);
+
+            tree.insert(first.clone(), accept).unwrap();
+            let outcome = tree.insert(second.clone(), accept).unwrap();
+
+            assert!(matches!(
+                outcome,
+                InsertOutcome::Connected {
+                    blocks: 1,
+                    reorg: None
+                }
+            ));
+            assert_eq!(tree.head().hash(), first.hash());
+            assert!(tree.contains(&second.hash()));
+            assert!(!tree.is_canonical(&second.hash()));
+        }
+
+        #[test]
+        fn should_reorg_onto_a_heavier_branch() {
+            let genesis = genesis();
+            let a = child(&genesis, 1);
+            let b = child(&a, 1);
+            let b2 = child(&a, 2);
+            let c2 = child(&b2, 2);
+            let mut tree = BlockTree::new(genesis);
+            let notifications = tree.subscribe();
+
+            for block in [a, b.clone(), b2.clone()] {
+                tree.insert(block, accept).unwrap();
+            }
+
+            let InsertOutcome::Connected {
+                reorg: Some(reorg), ..
+            } = tree.insert(c2.clone(), accept).unwrap()
+            else {
+                panic!("the heavier branch should have become canonical");
+            };
+
+            let hashes = |blocks: &[Block]| blocks.iter().map(Block::hash).collect::<Vec<U256>>();
+
+            assert_eq!(hashes(&reorg.reverted), vec![b.hash()]);
+            assert_eq!(hashes(&reorg.applied), vec![b2.hash(), c2.hash()]);
+            assert_eq!(tree.head().hash(), c2.hash());
+
+            let last = notifications.try_iter().last().unwrap();
+
+            assert_eq!(hashes(&last.applied), hashes(&reorg.applied));
+        }
+
+        #[test]
+        fn should_connect_orphans_once_their_parent_arrives() {
+            let genesis = genesis();
+            let a = child(&genesis, 1);
+            let b = child(&a, 1);
+            let c = child(&b, 1);
+            let mut tree = BlockTree::new(genesis);
+
+            assert!(matches!(
+                tree.insert(c.clone(), accept),
+                Ok(InsertOutcome::Orphaned)
+            ));
+            assert!(matches!(
+                tree.insert(b, accept),
+                Ok(InsertOutcome::Orphaned)
+            ));
+            assert_eq!(tree.stats().orphans, 2);
+
+            let outcome = tree.insert(a, accept).unwrap();
+
+            assert!(matches!(
+                outcome,
+                InsertOutcome::Connected { blocks: 3, .. }
+            ));
+            assert_eq!(tree.head().hash(), c.hash());
+            assert_eq!(tree.stats().orphans, 0);
+        }
+
+        #[test]
+        fn should_bound_the_orphans_waiting_on_one_parent() {
+            let genesis = genesis();
+            let missing = child(&genesis, 1);
+            let other = child(&genesis, 2);
+            let mut tree = BlockTree::new(genesis);
+
+            tree.insert(child(&other, 1), accept).unwrap();
+
+            for tag in 0..MAX_ORPHANS_PER_PARENT as u64 + 5 {
+                tree.insert(child(&missing, tag), accept).unwrap();
+            }
+
+            assert_eq!(tree.stats().orphans, MAX_ORPHANS_PER_PARENT + 1);
+
+            let outcome = tree.insert(other, accept).unwrap();
+
+            assert!(matches!(
+                outcome,
+                InsertOutcome::Connected { blocks: 2, .. }
+            ));
+            assert_eq!(tree.stats().orphans, MAX_ORPHANS_PER_PARENT);
+        }
+
+        #[test]
+        fn should_reject_known_and_invalid_blocks() {
+            let genesis = genesis();
+            let a = child(&genesis, 1);
+            let mut tree = BlockTree::new(genesis);
+
+            tree.insert(a.clone(), accept).unwrap();
+
+            assert!(matches!(
+                tree.insert(a.clone(), accept),
+                Err(TreeError::AlreadyKnown)
+            ));
+            assert!(matches!(
+                tree.insert(child(&a, 1), |_, _| Err("invalid")),
+                Err(TreeError::InvalidBlock("invalid"))
+            ));
+            assert_eq!(tree.height(), 1);
+        }
+    }
+
+    mod stats {
+        use crate::week_1::block_tree::{
+            tests::{accept, child, genesis},
+            BlockTree, TreeStats,
+        };
+
+        #[test]
+        fn should_count_stale_blocks_and_uncles() {
+            let genesis = genesis();
+            let a = child(&genesis, 1);
+            let b = child(&a, 1);
+            let c = child(&b, 1);
+            let uncle = child(&a, 2);
+            let nephew_of_uncle = child(&uncle, 2);
+            let mut tree = BlockTree::new(genesis);
+
+            for block in [a, b, c, uncle.clone(), nephew_of_uncle] {
+                tree.insert(block, accept).unwrap();
+            }
+
+            assert_eq!(
+                tree.stats(),
+                TreeStats {
+                    blocks: 6,
+                    height: 3,
+                    stale_blocks: 2,
+                    uncles: 1,
+                    orphans: 0,
+                    reorgs: 0,
+                    deepest_reorg: 0,
+                }
+            );
+            assert_eq!(tree.uncles()[0].hash(), uncle.hash());
+        }
+    }
+}
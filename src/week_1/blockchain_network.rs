@@ -1,22 +1,141 @@
+use k256::ecdsa::SigningKey;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    block_tree::TreeBlock,
+    digital_signatures::sign_message_with_key,
+    persistence::{read_snapshot, write_snapshot, SnapshotError, SnapshotFormat},
+    proof_of_work::recover_signer,
+};
 
 //  Blockchain Data Structure
+#[derive(Clone, Serialize, Deserialize)]
 struct Block {
+    index: u64,
+    timestamp: u64,
     data: String,
     previous_hash: String,
+    hash: String,
+    // Only blocks of a proof of authority chain are sealed
+    #[serde(default)]
+    seal: Option<Seal>,
+}
+
+// Proposal to add a signer to the authority set or to drop one from it
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+struct Vote {
+    address: String,
+    authorize: bool,
+}
+
+// Signature of an authorized signer over the block and the vote it casts
+#[derive(Clone, Serialize, Deserialize)]
+struct Seal {
+    vote: Option<Vote>,
+    signature: String,
+}
+
+#[derive(PartialEq, Debug)]
+enum InvalidBlockReason {
+    UnexpectedIndex { expected: u64, found: u64 },
+    HashMismatch,
+    PreviousHashMismatch,
+    TimestampBeforeParent,
+    MissingSeal,
+    UnexpectedSeal,
+    InvalidSeal,
+    UnauthorizedSigner { found: String },
+    OutOfTurn { expected: String, found: String },
+    TimestampBeforePeriod { earliest: u64, found: u64 },
+    InvalidVote,
+}
+
+#[derive(PartialEq, Debug)]
+struct InvalidBlock {
+    index: usize,
+    reason: InvalidBlockReason,
+}
+
+#[derive(PartialEq, Debug)]
+enum ChainReplacementError {
+    EmptyCandidate,
+    InvalidCandidate(InvalidBlock),
+    GenesisMismatch,
+    UnknownParent,
+    InsufficientWork { current: u128, candidate: u128 },
+}
+
+// Blocks that left the canonical chain and the blocks that replaced them, both in chain order
+struct ChainReorg {
+    reverted: Vec<Block>,
+    adopted: Vec<Block>,
+}
+
+fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
 }
 
 impl Block {
-    pub fn new(data: String, previous_hash: String) -> Self {
-        Block {
+    pub fn new(index: u64, timestamp: u64, data: String, previous_hash: String) -> Self {
+        let mut block = Block {
+            index,
+            timestamp,
             data,
             previous_hash,
-        }
+            hash: String::default(),
+            seal: None,
+        };
+
+        block.hash = block.to_hash();
+
+        block
     }
 
+    // The header fields are hashed in a fixed order. The previous hash is length prefixed
+    // so that it can't bleed into the data that follows it.
     pub fn to_hash(&self) -> String {
+        self.hash_header(true)
+    }
+
+    // Hash the signer signs, it covers the vote but not the signature itself
+    fn seal_hash(&self) -> String {
+        self.hash_header(false)
+    }
+
+    // Unsealed blocks hash exactly as they did before sealing existed
+    fn hash_header(&self, with_signature: bool) -> String {
         let mut hasher = Sha256::new();
 
+        hasher.update(self.index.to_be_bytes());
+        hasher.update(self.timestamp.to_be_bytes());
+        hasher.update((self.previous_hash.len() as u64).to_be_bytes());
+        hasher.update(&self.previous_hash);
+
+        if let Some(seal) = &self.seal {
+            match &seal.vote {
+                None => hasher.update([0]),
+                Some(vote) => {
+                    hasher.update([if vote.authorize { 1 } else { 2 }]);
+                    hasher.update((vote.address.len() as u64).to_be_bytes());
+                    hasher.update(&vote.address);
+                }
+            }
+
+            if with_signature {
+                hasher.update((seal.signature.len() as u64).to_be_bytes());
+                hasher.update(&seal.signature);
+            }
+        }
+
         hasher.update(&self.data);
 
         let hash = hasher.finalize();
@@ -24,61 +143,448 @@ impl Block {
         hex::encode(hash)
     }
 
+    pub fn seal(&mut self, signing_key: &SigningKey, vote: Option<Vote>) {
+        self.seal = Some(Seal {
+            vote,
+            signature: String::new(),
+        });
+
+        let signature = sign_message_with_key(signing_key, self.seal_hash().as_bytes());
+
+        if let Some(seal) = self.seal.as_mut() {
+            seal.signature = hex::encode(signature.as_ref());
+        }
+
+        self.hash = self.to_hash();
+    }
+
+    // Address that sealed the block, if it carries a valid seal
+    pub fn signer(&self) -> Option<String> {
+        let seal = self.seal.as_ref()?;
+
+        recover_signer(self.seal_hash().as_bytes(), &seal.signature)
+    }
+
     pub fn set_previous_hash(&mut self, hash: String) {
-        self.previous_hash = hash
+        self.previous_hash = hash;
+        self.hash = self.to_hash();
+    }
+
+    pub fn work(&self) -> u128 {
+        1
+    }
+}
+
+// Proof of authority settings, in the style of Clique
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+struct AuthorityConfig {
+    // Signers allowed to seal blocks from the genesis block on
+    signers: Vec<String>,
+    // Minimum number of seconds between two blocks
+    period: u64,
+}
+
+impl AuthorityConfig {
+    pub fn new(signers: Vec<String>) -> Self {
+        Self {
+            signers,
+            period: 15,
+        }
+    }
+}
+
+// Signer set at some height, along with the votes cast to change it
+#[derive(Clone, PartialEq, Debug)]
+struct Authorities {
+    period: u64,
+    signers: BTreeSet<String>,
+    // Signers backing each pending vote
+    tally: BTreeMap<Vote, BTreeSet<String>>,
+}
+
+impl Authorities {
+    pub fn new(config: &AuthorityConfig) -> Self {
+        Self {
+            period: config.period,
+            signers: config.signers.iter().cloned().collect(),
+            tally: BTreeMap::new(),
+        }
+    }
+
+    // Signers take turns in the order of their addresses
+    pub fn in_turn(&self, index: u64) -> Option<&String> {
+        if self.signers.is_empty() {
+            return None;
+        }
+
+        self.signers
+            .iter()
+            .nth((index % self.signers.len() as u64) as usize)
+    }
+
+    // Checks the block's seal against the current signers and counts its vote. A vote backed by
+    // more than half of the signers takes effect right away.
+    pub fn apply(&mut self, block: &Block) -> Result<(), InvalidBlockReason> {
+        let seal = block.seal.as_ref().ok_or(InvalidBlockReason::MissingSeal)?;
+        let signer = block.signer().ok_or(InvalidBlockReason::InvalidSeal)?;
+
+        if !self.signers.contains(&signer) {
+            return Err(InvalidBlockReason::UnauthorizedSigner { found: signer });
+        }
+
+        let expected = self.in_turn(block.index).unwrap();
+
+        if *expected != signer {
+            return Err(InvalidBlockReason::OutOfTurn {
+                expected: expected.clone(),
+                found: signer,
+            });
+        }
+
+        let Some(vote) = &seal.vote else {
+            return Ok(());
+        };
+
+        let is_signer = self.signers.contains(&vote.address);
+
+        // Only votes that would change the set count, and the last signer can't be dropped
+        if vote.authorize == is_signer || (is_signer && self.signers.len() == 1) {
+            return Err(InvalidBlockReason::InvalidVote);
+        }
+
+        let voters = self.tally.entry(vote.clone()).or_default();
+        voters.insert(signer);
+
+        if voters.len() * 2 <= self.signers.len() {
+            return Ok(());
+        }
+
+        self.tally
+            .retain(|pending, _| pending.address != vote.address);
+
+        if vote.authorize {
+            self.signers.insert(vote.address.clone());
+        } else {
+            self.signers.remove(&vote.address);
+
+            for voters in self.tally.values_mut() {
+                voters.remove(&vote.address);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_block(
+    block: &Block,
+    previous_block: &Block,
+    authorities: Option<&mut Authorities>,
+) -> Result<(), InvalidBlockReason> {
+    if previous_block.hash != block.previous_hash {
+        return Err(InvalidBlockReason::PreviousHashMismatch);
+    }
+
+    if block.timestamp < previous_block.timestamp {
+        return Err(InvalidBlockReason::TimestampBeforeParent);
+    }
+
+    let Some(authorities) = authorities else {
+        return match block.seal {
+            Some(_) => Err(InvalidBlockReason::UnexpectedSeal),
+            None => Ok(()),
+        };
+    };
+
+    let earliest = previous_block.timestamp + authorities.period;
+
+    if block.timestamp < earliest {
+        return Err(InvalidBlockReason::TimestampBeforePeriod {
+            earliest,
+            found: block.timestamp,
+        });
+    }
+
+    authorities.apply(block)
+}
+
+impl TreeBlock for Block {
+    type Hash = String;
+
+    fn hash(&self) -> String {
+        self.hash.clone()
+    }
+
+    fn parent_hash(&self) -> String {
+        self.previous_hash.clone()
+    }
+
+    fn work(&self) -> u128 {
+        Block::work(self)
+    }
+}
+
+// Walks the chain from the genesis block and reports the first block that breaks it. On a proof
+// of authority chain it also returns the signers the chain ends with.
+fn validate_blocks(
+    chain: &[Block],
+    authority: Option<&AuthorityConfig>,
+) -> Result<Option<Authorities>, InvalidBlock> {
+    let mut authorities = authority.map(Authorities::new);
+
+    for (idx, current_block) in chain.iter().enumerate() {
+        let invalid_block = |reason| InvalidBlock { index: idx, reason };
+
+        if current_block.index != idx as u64 {
+            return Err(invalid_block(InvalidBlockReason::UnexpectedIndex {
+                expected: idx as u64,
+                found: current_block.index,
+            }));
+        }
+
+        if current_block.hash != current_block.to_hash() {
+            return Err(invalid_block(InvalidBlockReason::HashMismatch));
+        }
+
+        if idx == 0 {
+            continue;
+        }
+
+        let previous_block = chain.get(idx - 1).unwrap();
+
+        validate_block(current_block, previous_block, authorities.as_mut())
+            .map_err(invalid_block)?;
     }
+
+    Ok(authorities)
+}
+
+// Blocks aren't mined on this chain, so every block adds the same amount of work
+fn cumulative_work(chain: &[Block]) -> u128 {
+    chain.iter().map(Block::work).sum()
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 struct BlockChain {
     chain: Vec<Block>,
+    // Set on proof of authority chains, whose blocks have to be sealed by a signer in turn
+    #[serde(default)]
+    authority: Option<AuthorityConfig>,
+    // Signers at the tip, updated as sealed blocks are added and rebuilt when the chain is
+    // replaced or loaded
+    #[serde(skip)]
+    authorities: Option<Authorities>,
 }
 
 impl BlockChain {
     pub fn new() -> BlockChain {
-        let chain = vec![Block::new(String::from("Genesis Block"), String::from(""))];
+        let chain = vec![Block::new(
+            0,
+            0,
+            String::from("Genesis Block"),
+            String::from(""),
+        )];
 
-        BlockChain { chain }
+        BlockChain {
+            chain,
+            authority: None,
+            authorities: None,
+        }
     }
 
-    pub fn add_block(&mut self, new_block_data: String) {
+    pub fn with_authority(authority: AuthorityConfig) -> BlockChain {
+        BlockChain {
+            authorities: Some(Authorities::new(&authority)),
+            authority: Some(authority),
+            ..BlockChain::new()
+        }
+    }
+
+    // Blocks of a proof of authority chain have to be sealed, they are added with
+    // add_sealed_block instead
+    pub fn add_block(&mut self, new_block_data: String) -> Result<(), InvalidBlock> {
         let chain_length = self.chain.len();
 
+        if self.authority.is_some() {
+            return Err(InvalidBlock {
+                index: chain_length,
+                reason: InvalidBlockReason::MissingSeal,
+            });
+        }
+
         let block_to_add: Block;
 
         if chain_length == 0 {
-            block_to_add = Block::new(new_block_data, String::default());
+            block_to_add = Block::new(0, current_timestamp(), new_block_data, String::default());
 
             self.chain.push(block_to_add);
-            return;
+            return Ok(());
         }
 
         let previous_block = self.chain.get(chain_length - 1).unwrap();
 
-        block_to_add = Block::new(new_block_data, previous_block.to_hash());
+        block_to_add = Block::new(
+            chain_length as u64,
+            current_timestamp().max(previous_block.timestamp),
+            new_block_data,
+            previous_block.hash.clone(),
+        );
+
+        self.chain.push(block_to_add);
 
-        self.chain.push(block_to_add)
+        Ok(())
+    }
+
+    // Seals a block with the key, which has to belong to the signer in turn. The block is only
+    // added if it is valid, votes included.
+    pub fn add_sealed_block(
+        &mut self,
+        new_block_data: String,
+        signing_key: &SigningKey,
+        vote: Option<Vote>,
+    ) -> Result<(), InvalidBlock> {
+        let mut authorities = self.authorities.clone();
+        let previous_block = self.chain.last().unwrap();
+        let period = authorities
+            .as_ref()
+            .map_or(0, |authorities| authorities.period);
+
+        let mut block_to_add = Block::new(
+            self.chain.len() as u64,
+            current_timestamp().max(previous_block.timestamp + period),
+            new_block_data,
+            previous_block.hash.clone(),
+        );
+
+        block_to_add.seal(signing_key, vote);
+
+        validate_block(&block_to_add, previous_block, authorities.as_mut()).map_err(|reason| {
+            InvalidBlock {
+                index: self.chain.len(),
+                reason,
+            }
+        })?;
+
+        self.chain.push(block_to_add);
+        self.authorities = authorities;
+
+        Ok(())
+    }
+
+    // Signers allowed to seal the next block, empty if the chain isn't a proof of authority one
+    pub fn signers(&self) -> Vec<String> {
+        self.authorities
+            .as_ref()
+            .map(|authorities| authorities.signers.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn in_turn_signer(&self) -> Option<String> {
+        let authorities = self.authorities.as_ref()?;
+
+        authorities.in_turn(self.chain.len() as u64).cloned()
+    }
+
+    pub fn validate(&self) -> Result<(), InvalidBlock> {
+        validate_blocks(&self.chain, self.authority.as_ref()).map(|_| ())
     }
 
     pub fn is_valid(&self) -> bool {
-        let chain_len = self.chain.len();
+        self.validate().is_ok()
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        format: SnapshotFormat,
+    ) -> Result<(), SnapshotError<InvalidBlock>> {
+        write_snapshot(path, format, self)
+    }
+
+    pub fn load(
+        path: impl AsRef<Path>,
+        format: SnapshotFormat,
+    ) -> Result<BlockChain, SnapshotError<InvalidBlock>> {
+        let mut blockchain: BlockChain = read_snapshot(path, format)?;
 
-        if chain_len <= 1 {
-            return true;
+        blockchain.authorities = validate_blocks(&blockchain.chain, blockchain.authority.as_ref())
+            .map_err(SnapshotError::InvalidBlock)?;
+
+        Ok(blockchain)
+    }
+
+    pub fn cumulative_work(&self) -> u128 {
+        cumulative_work(&self.chain)
+    }
+
+    // Adopts the candidate chain if it is valid, shares our genesis block and carries more work.
+    // Ties are resolved in favour of the chain we already have.
+    pub fn replace_chain(
+        &mut self,
+        candidate: Vec<Block>,
+    ) -> Result<ChainReorg, ChainReplacementError> {
+        let candidate_genesis = candidate
+            .first()
+            .ok_or(ChainReplacementError::EmptyCandidate)?;
+
+        let authorities = validate_blocks(&candidate, self.authority.as_ref())
+            .map_err(ChainReplacementError::InvalidCandidate)?;
+
+        if self.chain.first().map(|block| &block.hash) != Some(&candidate_genesis.hash) {
+            return Err(ChainReplacementError::GenesisMismatch);
         }
 
-        for idx in 1..chain_len {
-            let previous_block = self.chain.get(idx - 1).unwrap();
-            let current_block = self.chain.get(idx).unwrap();
+        let current_work = self.cumulative_work();
+        let candidate_work = cumulative_work(&candidate);
 
-            if previous_block.to_hash() != current_block.previous_hash {
-                return false;
-            }
+        if candidate_work <= current_work {
+            return Err(ChainReplacementError::InsufficientWork {
+                current: current_work,
+                candidate: candidate_work,
+            });
         }
 
-        true
+        let fork_point = self
+            .chain
+            .iter()
+            .zip(candidate.iter())
+            .take_while(|(current, candidate)| current.hash == candidate.hash)
+            .count();
+
+        let adopted = candidate[fork_point..].to_vec();
+        let reverted = self.chain.split_off(fork_point);
+
+        self.chain = candidate;
+        self.authorities = authorities;
+
+        Ok(ChainReorg { reverted, adopted })
+    }
+
+    // Accepts a run of blocks from a peer. The first block must build on a block we already know,
+    // which may be below our tip when the peer is on a fork.
+    pub fn receive_blocks(
+        &mut self,
+        blocks: Vec<Block>,
+    ) -> Result<ChainReorg, ChainReplacementError> {
+        let first_block = blocks
+            .first()
+            .ok_or(ChainReplacementError::EmptyCandidate)?;
+
+        let parent_idx = self
+            .chain
+            .iter()
+            .position(|block| block.hash == first_block.previous_hash)
+            .ok_or(ChainReplacementError::UnknownParent)?;
+
+        let mut candidate = self.chain[..=parent_idx].to_vec();
+
+        candidate.extend(blocks);
+
+        self.replace_chain(candidate)
     }
 }
 
+#[cfg(test)]
 mod tests {
 
     mod block {
@@ -97,30 +603,48 @@ mod tests {
 
             let hex_hash = hex::encode(hash);
 
-            let block = Block::new(data.clone(), hex_hash);
+            let block = Block::new(1, 0, data.clone(), hex_hash);
 
             assert_eq!(block.data, data);
         }
 
         #[test]
-        fn should_hash_the_given_value() {
+        fn should_hash_the_block_header() {
             let data = String::from("Some data");
+            let previous_hash = String::from("previous hash");
             let mut hasher = Sha256::new();
 
+            hasher.update(1_u64.to_be_bytes());
+            hasher.update(2_u64.to_be_bytes());
+            hasher.update((previous_hash.len() as u64).to_be_bytes());
+            hasher.update(previous_hash.clone());
             hasher.update(data.clone());
 
             let hash = hasher.finalize();
 
             let hex_hash = hex::encode(hash);
 
-            let block = Block::new(data, hex_hash.clone());
+            let block = Block::new(1, 2, data, previous_hash);
 
             assert_eq!(block.to_hash(), hex_hash);
+            assert_eq!(block.hash, hex_hash);
+        }
+
+        #[test]
+        fn should_change_the_hash_if_the_previous_hash_changes() {
+            let mut block = Block::new(1, 0, String::from("Some data"), String::from("a"));
+            let original_hash = block.hash.clone();
+
+            block.set_previous_hash(String::from("b"));
+
+            assert_ne!(block.hash, original_hash);
         }
     }
 
     mod blockchain {
-        use crate::week_1::blockchain_network::{Block, BlockChain};
+        use crate::week_1::blockchain_network::{
+            Block, BlockChain, InvalidBlock, InvalidBlockReason,
+        };
 
         #[test]
         fn should_have_a_genesis_block() {
@@ -135,8 +659,8 @@ mod tests {
             let data = String::from("Some data");
             let some_other_data = String::from("Some Other Data");
 
-            blockchain.add_block(data);
-            blockchain.add_block(some_other_data);
+            blockchain.add_block(data).unwrap();
+            blockchain.add_block(some_other_data).unwrap();
 
             assert_eq!(blockchain.chain.len(), 3);
         }
@@ -147,8 +671,8 @@ mod tests {
             let data = String::from("Some data");
             let some_other_data = String::from("Some Other Data");
 
-            blockchain.add_block(data.clone());
-            blockchain.add_block(some_other_data.clone());
+            blockchain.add_block(data.clone()).unwrap();
+            blockchain.add_block(some_other_data.clone()).unwrap();
 
             let block_1 = blockchain.chain.get(1).unwrap();
             let block_2 = blockchain.chain.get(2).unwrap();
@@ -163,10 +687,11 @@ mod tests {
             let data = String::from("Some data");
             let some_other_data = String::from("Some Other Data");
 
-            blockchain.add_block(data);
-            blockchain.add_block(some_other_data);
+            blockchain.add_block(data).unwrap();
+            blockchain.add_block(some_other_data).unwrap();
 
             assert!(blockchain.is_valid());
+            assert_eq!(blockchain.validate(), Ok(()));
         }
 
         #[test]
@@ -176,14 +701,677 @@ mod tests {
             let some_other_data = String::from("Some Other Data");
             let some_invalid_block_data = String::from("Some Block Data");
 
-            let invalid_block = Block::new(some_invalid_block_data, String::from("invalid hash"));
+            let invalid_block =
+                Block::new(1, 0, some_invalid_block_data, String::from("invalid hash"));
 
-            blockchain.add_block(data);
-            blockchain.add_block(some_other_data);
+            blockchain.add_block(data).unwrap();
+            blockchain.add_block(some_other_data).unwrap();
 
             blockchain.chain[1] = invalid_block;
 
             assert!(!blockchain.is_valid());
         }
+
+        #[test]
+        fn should_report_a_tampered_previous_hash() {
+            let mut blockchain = BlockChain::new();
+
+            blockchain.add_block(String::from("Some data")).unwrap();
+            blockchain
+                .add_block(String::from("Some Other Data"))
+                .unwrap();
+
+            blockchain.chain[2].previous_hash = String::from("tampered hash");
+
+            assert_eq!(
+                blockchain.validate(),
+                Err(InvalidBlock {
+                    index: 2,
+                    reason: InvalidBlockReason::HashMismatch
+                })
+            );
+        }
+
+        #[test]
+        fn should_report_a_rehashed_block_with_a_wrong_parent() {
+            let mut blockchain = BlockChain::new();
+
+            blockchain.add_block(String::from("Some data")).unwrap();
+            blockchain
+                .add_block(String::from("Some Other Data"))
+                .unwrap();
+
+            blockchain.chain[2].set_previous_hash(String::from("tampered hash"));
+
+            assert_eq!(
+                blockchain.validate(),
+                Err(InvalidBlock {
+                    index: 2,
+                    reason: InvalidBlockReason::PreviousHashMismatch
+                })
+            );
+        }
+
+        #[test]
+        fn should_report_the_first_tampered_block() {
+            let mut blockchain = BlockChain::new();
+
+            blockchain.add_block(String::from("Some data")).unwrap();
+            blockchain
+                .add_block(String::from("Some Other Data"))
+                .unwrap();
+            blockchain
+                .add_block(String::from("Even More Data"))
+                .unwrap();
+
+            blockchain.chain[1].data = String::from("Tampered data");
+            blockchain.chain[3].data = String::from("Tampered data");
+
+            assert_eq!(
+                blockchain.validate(),
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::HashMismatch
+                })
+            );
+        }
+
+        #[test]
+        fn should_report_reordered_blocks() {
+            let mut blockchain = BlockChain::new();
+
+            blockchain.add_block(String::from("Some data")).unwrap();
+            blockchain
+                .add_block(String::from("Some Other Data"))
+                .unwrap();
+
+            blockchain.chain.swap(1, 2);
+
+            assert_eq!(
+                blockchain.validate(),
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::UnexpectedIndex {
+                        expected: 1,
+                        found: 2
+                    }
+                })
+            );
+        }
+
+        #[test]
+        fn should_report_a_block_older_than_its_parent() {
+            let mut blockchain = BlockChain::new();
+
+            blockchain.add_block(String::from("Some data")).unwrap();
+
+            let previous_hash = blockchain.chain[1].hash.clone();
+            let timestamp = blockchain.chain[1].timestamp - 1;
+
+            blockchain.chain.push(Block::new(
+                2,
+                timestamp,
+                String::from("Some Other Data"),
+                previous_hash,
+            ));
+
+            assert_eq!(
+                blockchain.validate(),
+                Err(InvalidBlock {
+                    index: 2,
+                    reason: InvalidBlockReason::TimestampBeforeParent
+                })
+            );
+        }
+    }
+
+    mod persistence {
+        use std::fs;
+
+        use crate::week_1::{
+            blockchain_network::{BlockChain, InvalidBlock, InvalidBlockReason},
+            persistence::{SnapshotError, SnapshotFormat},
+        };
+
+        fn snapshot_path(name: &str) -> std::path::PathBuf {
+            std::env::temp_dir().join(format!("blockchain-{}-{}", std::process::id(), name))
+        }
+
+        #[test]
+        fn should_reload_a_saved_chain() {
+            for format in [SnapshotFormat::Json, SnapshotFormat::Binary] {
+                let path = snapshot_path(&format!("reload-{:?}", format));
+                let mut blockchain = BlockChain::new();
+
+                blockchain.add_block(String::from("Some data")).unwrap();
+                blockchain
+                    .add_block(String::from("Some Other Data"))
+                    .unwrap();
+
+                blockchain.save(&path, format).unwrap();
+
+                let mut loaded = BlockChain::load(&path, format).unwrap();
+
+                assert_eq!(loaded.chain.len(), 3);
+                assert_eq!(loaded.chain[2].hash, blockchain.chain[2].hash);
+
+                loaded.add_block(String::from("Even More Data")).unwrap();
+
+                assert!(loaded.is_valid());
+
+                fs::remove_file(path).unwrap();
+            }
+        }
+
+        #[test]
+        fn should_name_the_corrupted_block() {
+            let path = snapshot_path("corrupted.json");
+            let mut blockchain = BlockChain::new();
+
+            blockchain.add_block(String::from("Some data")).unwrap();
+            blockchain
+                .add_block(String::from("Some Other Data"))
+                .unwrap();
+
+            blockchain.save(&path, SnapshotFormat::Json).unwrap();
+
+            let corrupted = fs::read_to_string(&path)
+                .unwrap()
+                .replace("Some Other Data", "Some Forged Data");
+
+            fs::write(&path, corrupted).unwrap();
+
+            let result = BlockChain::load(&path, SnapshotFormat::Json);
+
+            assert!(matches!(
+                result,
+                Err(SnapshotError::InvalidBlock(InvalidBlock {
+                    index: 2,
+                    reason: InvalidBlockReason::HashMismatch
+                }))
+            ));
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    mod fork_choice {
+        use crate::week_1::blockchain_network::{
+            Block, BlockChain, ChainReplacementError, InvalidBlock, InvalidBlockReason,
+        };
+
+        fn extend(blockchain: &BlockChain, data: &[&str]) -> BlockChain {
+            let mut fork = blockchain.clone();
+
+            for block_data in data {
+                fork.add_block(block_data.to_string()).unwrap();
+            }
+
+            fork
+        }
+
+        #[test]
+        fn should_adopt_a_longer_fork() {
+            let mut blockchain = BlockChain::new();
+            blockchain.add_block(String::from("A")).unwrap();
+
+            let fork = extend(&blockchain, &["B'", "C'"]);
+
+            blockchain.add_block(String::from("B")).unwrap();
+
+            let reorg = blockchain.replace_chain(fork.chain.clone()).unwrap();
+
+            assert_eq!(reorg.reverted.len(), 1);
+            assert_eq!(reorg.reverted[0].data, "B");
+            assert_eq!(
+                reorg
+                    .adopted
+                    .iter()
+                    .map(|block| block.data.as_str())
+                    .collect::<Vec<&str>>(),
+                vec!["B'", "C'"]
+            );
+            assert_eq!(blockchain.chain.len(), 4);
+            assert_eq!(blockchain.chain[3].hash, fork.chain[3].hash);
+            assert!(blockchain.is_valid());
+        }
+
+        #[test]
+        fn should_keep_the_current_chain_on_a_tie() {
+            let mut blockchain = BlockChain::new();
+            let fork = extend(&blockchain, &["B'"]);
+
+            blockchain.add_block(String::from("B")).unwrap();
+
+            let result = blockchain.replace_chain(fork.chain);
+
+            assert_eq!(
+                result.err(),
+                Some(ChainReplacementError::InsufficientWork {
+                    current: 2,
+                    candidate: 2
+                })
+            );
+            assert_eq!(blockchain.chain[1].data, "B");
+        }
+
+        #[test]
+        fn should_reject_an_invalid_candidate() {
+            let mut blockchain = BlockChain::new();
+            let mut fork = extend(&blockchain, &["A", "B", "C"]);
+
+            fork.chain[2].data = String::from("Forged");
+
+            let result = blockchain.replace_chain(fork.chain);
+
+            assert_eq!(
+                result.err(),
+                Some(ChainReplacementError::InvalidCandidate(InvalidBlock {
+                    index: 2,
+                    reason: InvalidBlockReason::HashMismatch
+                }))
+            );
+            assert_eq!(blockchain.chain.len(), 1);
+        }
+
+        #[test]
+        fn should_reject_a_chain_with_another_genesis() {
+            let mut blockchain = BlockChain::new();
+            let mut other = BlockChain {
+                chain: vec![Block::new(
+                    0,
+                    1,
+                    String::from("Other Genesis"),
+                    String::new(),
+                )],
+                authority: None,
+                authorities: None,
+            };
+
+            other.add_block(String::from("A")).unwrap();
+
+            let result = blockchain.replace_chain(other.chain);
+
+            assert_eq!(result.err(), Some(ChainReplacementError::GenesisMismatch));
+        }
+
+        #[test]
+        fn should_extend_the_tip_with_received_blocks() {
+            let mut blockchain = BlockChain::new();
+            blockchain.add_block(String::from("A")).unwrap();
+
+            let peer = extend(&blockchain, &["B", "C"]);
+
+            let reorg = blockchain.receive_blocks(peer.chain[2..].to_vec()).unwrap();
+
+            assert!(reorg.reverted.is_empty());
+            assert_eq!(reorg.adopted.len(), 2);
+            assert_eq!(blockchain.chain.len(), 4);
+        }
+
+        #[test]
+        fn should_reorg_onto_received_fork_blocks() {
+            let mut blockchain = BlockChain::new();
+            blockchain.add_block(String::from("A")).unwrap();
+
+            let peer = extend(&blockchain, &["B'", "C'", "D'"]);
+
+            blockchain.add_block(String::from("B")).unwrap();
+            blockchain.add_block(String::from("C")).unwrap();
+
+            let reorg = blockchain.receive_blocks(peer.chain[2..].to_vec()).unwrap();
+
+            assert_eq!(
+                reorg
+                    .reverted
+                    .iter()
+                    .map(|block| block.data.as_str())
+                    .collect::<Vec<&str>>(),
+                vec!["B", "C"]
+            );
+            assert_eq!(reorg.adopted.len(), 3);
+            assert_eq!(blockchain.chain[4].data, "D'");
+        }
+
+        #[test]
+        fn should_reject_blocks_with_an_unknown_parent() {
+            let mut blockchain = BlockChain::new();
+            let orphan = Block::new(5, 0, String::from("Orphan"), String::from("unknown"));
+
+            let result = blockchain.receive_blocks(vec![orphan]);
+
+            assert_eq!(result.err(), Some(ChainReplacementError::UnknownParent));
+        }
+    }
+
+    mod proof_of_authority {
+        use k256::ecdsa::SigningKey;
+
+        use crate::week_1::{
+            blockchain_network::{
+                AuthorityConfig, Block, BlockChain, InvalidBlock, InvalidBlockReason, Vote,
+            },
+            persistence::SnapshotFormat,
+            proof_of_work::signer_address,
+        };
+
+        fn signer_key(seed: u8) -> SigningKey {
+            SigningKey::from_bytes(&[seed; 32]).unwrap()
+        }
+
+        // Chain sealed by the keys seeded with 1 to 3
+        fn authority_chain() -> BlockChain {
+            let signers = (1..=3)
+                .map(|seed| signer_address(&signer_key(seed)))
+                .collect();
+
+            BlockChain::with_authority(AuthorityConfig::new(signers))
+        }
+
+        fn in_turn_key(blockchain: &BlockChain) -> SigningKey {
+            let signer = blockchain.in_turn_signer().unwrap();
+
+            (1..=4)
+                .map(signer_key)
+                .find(|key| signer_address(key) == signer)
+                .unwrap()
+        }
+
+        fn seal_in_turn(blockchain: &mut BlockChain, vote: Option<Vote>) {
+            let key = in_turn_key(blockchain);
+
+            blockchain
+                .add_sealed_block(String::from("Some data"), &key, vote)
+                .unwrap();
+        }
+
+        #[test]
+        fn should_take_turns_sealing_blocks() {
+            let mut blockchain = authority_chain();
+
+            for _ in 0..6 {
+                seal_in_turn(&mut blockchain, None);
+            }
+
+            let signers = blockchain.signers();
+
+            for block in blockchain.chain[1..].iter() {
+                let expected = &signers[block.index as usize % signers.len()];
+
+                assert_eq!(block.signer().as_ref(), Some(expected));
+            }
+            assert_eq!(blockchain.validate(), Ok(()));
+        }
+
+        #[test]
+        fn should_reject_a_signer_out_of_turn() {
+            let mut blockchain = authority_chain();
+            let expected = blockchain.in_turn_signer().unwrap();
+            let key = (1..=3)
+                .map(signer_key)
+                .find(|key| signer_address(key) != expected)
+                .unwrap();
+
+            let result = blockchain.add_sealed_block(String::from("Some data"), &key, None);
+
+            assert_eq!(
+                result,
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::OutOfTurn {
+                        expected,
+                        found: signer_address(&key)
+                    }
+                })
+            );
+            assert_eq!(blockchain.chain.len(), 1);
+        }
+
+        #[test]
+        fn should_reject_an_unauthorized_signer() {
+            let mut blockchain = authority_chain();
+            let key = signer_key(4);
+
+            let result = blockchain.add_sealed_block(String::from("Some data"), &key, None);
+
+            assert_eq!(
+                result,
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::UnauthorizedSigner {
+                        found: signer_address(&key)
+                    }
+                })
+            );
+        }
+
+        #[test]
+        fn should_refuse_to_add_an_unsealed_block() {
+            let mut blockchain = authority_chain();
+
+            let result = blockchain.add_block(String::from("Some data"));
+
+            assert_eq!(
+                result,
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::MissingSeal
+                })
+            );
+            assert_eq!(blockchain.chain.len(), 1);
+        }
+
+        #[test]
+        fn should_reject_unsealed_blocks() {
+            let mut blockchain = authority_chain();
+            let previous_hash = blockchain.chain[0].hash.clone();
+
+            blockchain
+                .chain
+                .push(Block::new(1, 15, String::from("Some data"), previous_hash));
+
+            assert_eq!(
+                blockchain.validate(),
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::MissingSeal
+                })
+            );
+        }
+
+        #[test]
+        fn should_reject_sealed_blocks_without_an_authority() {
+            let mut blockchain = BlockChain::new();
+            let previous_hash = blockchain.chain[0].hash.clone();
+            let mut block = Block::new(1, 0, String::from("Some data"), previous_hash);
+
+            block.seal(&signer_key(1), None);
+            blockchain.chain.push(block);
+
+            assert_eq!(
+                blockchain.validate(),
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::UnexpectedSeal
+                })
+            );
+        }
+
+        #[test]
+        fn should_detect_a_resealed_block() {
+            let mut blockchain = authority_chain();
+            seal_in_turn(&mut blockchain, None);
+
+            // The data is changed and the hash recomputed, but the seal is the original one
+            blockchain.chain[1].data = String::from("Forged data");
+            blockchain.chain[1].hash = blockchain.chain[1].to_hash();
+
+            assert!(matches!(
+                blockchain.validate(),
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::UnauthorizedSigner { .. }
+                })
+            ));
+        }
+
+        #[test]
+        fn should_enforce_the_block_period() {
+            let mut blockchain = authority_chain();
+            let previous_hash = blockchain.chain[0].hash.clone();
+            let mut block = Block::new(1, 14, String::from("Some data"), previous_hash);
+
+            block.seal(&in_turn_key(&blockchain), None);
+            blockchain.chain.push(block);
+
+            assert_eq!(
+                blockchain.validate(),
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::TimestampBeforePeriod {
+                        earliest: 15,
+                        found: 14
+                    }
+                })
+            );
+        }
+
+        #[test]
+        fn should_vote_a_signer_in_with_a_majority() {
+            let mut blockchain = authority_chain();
+            let candidate = signer_address(&signer_key(4));
+            let vote = Vote {
+                address: candidate.clone(),
+                authorize: true,
+            };
+
+            seal_in_turn(&mut blockchain, Some(vote.clone()));
+
+            assert!(!blockchain.signers().contains(&candidate));
+
+            seal_in_turn(&mut blockchain, Some(vote));
+
+            assert!(blockchain.signers().contains(&candidate));
+            assert_eq!(blockchain.signers().len(), 4);
+
+            for _ in 0..4 {
+                seal_in_turn(&mut blockchain, None);
+            }
+
+            assert!(blockchain.chain[3..]
+                .iter()
+                .any(|block| block.signer() == Some(candidate.clone())));
+        }
+
+        #[test]
+        fn should_vote_a_signer_out_with_a_majority() {
+            let mut blockchain = authority_chain();
+            let signers = blockchain.signers();
+            let vote = Vote {
+                address: signers[2].clone(),
+                authorize: false,
+            };
+
+            // The signer being voted out takes its turn without voting
+            while blockchain.signers().len() == 3 {
+                let key = in_turn_key(&blockchain);
+                let vote = (signer_address(&key) != vote.address).then(|| vote.clone());
+
+                blockchain
+                    .add_sealed_block(String::from("Some data"), &key, vote)
+                    .unwrap();
+            }
+
+            assert_eq!(blockchain.signers(), signers[..2]);
+            assert_eq!(blockchain.validate(), Ok(()));
+        }
+
+        #[test]
+        fn should_reject_a_vote_that_changes_nothing() {
+            let mut blockchain = authority_chain();
+            let key = in_turn_key(&blockchain);
+            let vote = Vote {
+                address: signer_address(&key),
+                authorize: true,
+            };
+
+            let result = blockchain.add_sealed_block(String::from("Some data"), &key, Some(vote));
+
+            assert_eq!(
+                result,
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::InvalidVote
+                })
+            );
+        }
+
+        #[test]
+        fn should_follow_the_signers_of_an_adopted_chain() {
+            let mut blockchain = authority_chain();
+            let mut fork = blockchain.clone();
+            let candidate = signer_address(&signer_key(4));
+            let vote = Vote {
+                address: candidate.clone(),
+                authorize: true,
+            };
+
+            seal_in_turn(&mut fork, Some(vote.clone()));
+            seal_in_turn(&mut fork, Some(vote));
+
+            blockchain.replace_chain(fork.chain.clone()).unwrap();
+
+            assert!(blockchain.signers().contains(&candidate));
+            assert_eq!(blockchain.in_turn_signer(), fork.in_turn_signer());
+        }
+
+        #[test]
+        fn should_reload_a_saved_authority_chain() {
+            let path =
+                std::env::temp_dir().join(format!("blockchain-{}-authority", std::process::id()));
+            let mut blockchain = authority_chain();
+
+            seal_in_turn(&mut blockchain, None);
+            seal_in_turn(&mut blockchain, None);
+
+            blockchain.save(&path, SnapshotFormat::Binary).unwrap();
+
+            let loaded = BlockChain::load(&path, SnapshotFormat::Binary).unwrap();
+
+            assert_eq!(loaded.authority, blockchain.authority);
+            assert_eq!(loaded.in_turn_signer(), blockchain.in_turn_signer());
+
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    mod block_tree {
+        use crate::week_1::{
+            block_tree::{BlockTree, Branch},
+            blockchain_network::{validate_block, Block, BlockChain, InvalidBlockReason},
+        };
+
+        #[test]
+        fn should_keep_competing_blocks_at_the_same_height() {
+            let mut blockchain = BlockChain::new();
+            blockchain.add_block(String::from("A")).unwrap();
+
+            let mut fork = blockchain.clone();
+            fork.add_block(String::from("B'")).unwrap();
+            blockchain.add_block(String::from("B")).unwrap();
+
+            let mut tree = BlockTree::new(blockchain.chain[0].clone());
+            fn check(branch: &Branch<Block>, block: &Block) -> Result<(), InvalidBlockReason> {
+                validate_block(block, branch.tip(), None)
+            }
+
+            for block in blockchain.chain[1..].iter().chain(fork.chain[2..].iter()) {
+                tree.insert(block.clone(), check).unwrap();
+            }
+
+            assert_eq!(tree.head().data, "B");
+            assert_eq!(tree.stats().stale_blocks, 1);
+            assert_eq!(tree.uncles()[0].data, "B'");
+        }
     }
 }
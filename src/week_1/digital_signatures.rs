@@ -1,9 +1,44 @@
-use k256::ecdsa::{recoverable, signature::Signer, Signature, SigningKey, VerifyingKey};
+use k256::{
+    ecdsa::{
+        recoverable,
+        signature::{hazmat::PrehashSigner, Signer},
+        Signature, SigningKey, VerifyingKey,
+    },
+    elliptic_curve::{
+        rand_core::{CryptoRng, RngCore},
+        sec1::ToEncodedPoint,
+    },
+};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sha3::Digest;
-use std::str::FromStr;
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
 
 const PRIVATE_KEY: &str = "6b911fd37cdf5c81d4c0adb1ab7fa822ed253ab0ad9aa18d77257c88b29b718e";
 
+#[derive(PartialEq, Debug)]
+pub enum KeyError {
+    InvalidHex,
+    InvalidLength { expected: usize, found: usize },
+    InvalidPrivateKey,
+    InvalidPublicKey,
+    InvalidSignature,
+    InvalidRecoveryId(u64),
+    RecoveryFailed,
+    SigningFailed,
+    SignerMismatch { expected: Address, found: Address },
+    HighS,
+    VOutOfRange(u64),
+}
+
+// Accepts hex with or without a 0x prefix
+fn decode_hex(value: &str) -> Result<Vec<u8>, KeyError> {
+    hex::decode(value.strip_prefix("0x").unwrap_or(value)).map_err(|_| KeyError::InvalidHex)
+}
+
 // 1: Hash Message
 pub fn hash_message(message: impl AsRef<[u8]>) -> Vec<u8> {
     let mut hasher = sha3::Keccak256::new();
@@ -13,39 +48,617 @@ pub fn hash_message(message: impl AsRef<[u8]>) -> Vec<u8> {
     hasher.finalize().into_iter().collect()
 }
 
+// 20 byte account address, displayed with its EIP-55 checksum
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Address([u8; 20]);
+
+#[derive(PartialEq, Debug)]
+pub enum AddressError {
+    NotHex,
+    WrongLength { expected: usize, found: usize },
+    ChecksumMismatch { expected: String },
+}
+
+impl Address {
+    // What ecrecover returns when it fails
+    pub const ZERO: Address = Address([0; 20]);
+
+    pub fn to_bytes(self) -> [u8; 20] {
+        self.0
+    }
+
+    // Lowercase hex without the 0x prefix, the way the ledgers key their accounts
+    pub fn to_hex(self) -> String {
+        hex::encode(self.0)
+    }
+
+    // Uppercases the letters whose nibble in the hash of the lowercase hex is 8 or more
+    pub fn to_checksum(self) -> String {
+        let lowercase = self.to_hex();
+        let hash = hash_message(&lowercase);
+
+        let checksummed: String = lowercase
+            .chars()
+            .enumerate()
+            .map(|(idx, char)| {
+                let nibble = (hash[idx / 2] >> (4 * (1 - idx % 2))) & 0x0f;
+
+                if nibble >= 8 {
+                    char.to_ascii_uppercase()
+                } else {
+                    char
+                }
+            })
+            .collect();
+
+        format!("0x{}", checksummed)
+    }
+}
+
+impl From<[u8; 20]> for Address {
+    fn from(bytes: [u8; 20]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_checksum())
+    }
+}
+
+// The 0x prefix is optional. Addresses in a single case carry no checksum, mixed case ones have
+// to match theirs exactly.
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let digits = value.strip_prefix("0x").unwrap_or(value);
+        let bytes = hex::decode(digits).map_err(|_| AddressError::NotHex)?;
+
+        let address =
+            Address(
+                bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| AddressError::WrongLength {
+                        expected: 20,
+                        found: bytes.len(),
+                    })?,
+            );
+
+        let is_single_case = digits == digits.to_lowercase() || digits == digits.to_uppercase();
+        let expected = address.to_checksum();
+
+        if !is_single_case && expected[2..] != *digits {
+            return Err(AddressError::ChecksumMismatch { expected });
+        }
+
+        Ok(address)
+    }
+}
+
+// Serialized as its checksummed hex, and parsed as strictly as from a string
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Display for AddressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddressError::NotHex => write!(f, "address is not valid hex"),
+            AddressError::WrongLength { expected, found } => {
+                write!(f, "address is {} bytes long instead of {}", found, expected)
+            }
+            AddressError::ChecksumMismatch { expected } => {
+                write!(f, "address checksum doesn't match, expected {}", expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+// A secp256k1 private key along with the public key and address derived from it
+#[derive(Clone)]
+pub struct Keypair {
+    signing_key: SigningKey,
+}
+
+impl Keypair {
+    pub fn random(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        Self {
+            signing_key: SigningKey::random(rng),
+        }
+    }
+
+    // The same seed always gives the same key, which is only fit for tests and simulations
+    pub fn from_seed(seed: u64) -> Self {
+        Keypair::random(&mut StdRng::seed_from_u64(seed))
+    }
+
+    // Imports a 32 byte big endian private key
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KeyError> {
+        if bytes.len() != 32 {
+            return Err(KeyError::InvalidLength {
+                expected: 32,
+                found: bytes.len(),
+            });
+        }
+
+        SigningKey::from_bytes(bytes)
+            .map(Keypair::from)
+            .map_err(|_| KeyError::InvalidPrivateKey)
+    }
+
+    pub fn from_hex(private_key: &str) -> Result<Self, KeyError> {
+        Keypair::from_bytes(&decode_hex(private_key)?)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.signing_key.to_bytes().into()
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from(self.signing_key.verifying_key())
+    }
+
+    pub fn address(&self) -> Address {
+        self.public_key().address()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> recoverable::Signature {
+        sign_message_with_key(&self.signing_key, message)
+    }
+
+    // Signs an already computed 32 byte hash, returning r || s || v with v being 27 or 28
+    pub fn sign_hash(&self, hash: &[u8; 32]) -> Result<[u8; 65], KeyError> {
+        let signature: recoverable::Signature = self
+            .signing_key
+            .sign_prehash(hash)
+            .map_err(|_| KeyError::SigningFailed)?;
+
+        let mut bytes = [0; 65];
+        bytes[..64].copy_from_slice(&signature.as_ref()[..64]);
+        bytes[64] = u8::from(signature.recovery_id()) + 27;
+
+        Ok(bytes)
+    }
+
+    // Signs the EIP-191 hash of the message the way wallets do for personal_sign
+    pub fn sign_personal_message(&self, message: impl AsRef<[u8]>) -> Result<[u8; 65], KeyError> {
+        self.sign_hash(&hash_personal_message(message))
+    }
+}
+
+impl From<SigningKey> for Keypair {
+    fn from(signing_key: SigningKey) -> Self {
+        Self { signing_key }
+    }
+}
+
+// Only the public half is printed
+impl Debug for Keypair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keypair")
+            .field("address", &self.address())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PublicKey {
+    verifying_key: VerifyingKey,
+}
+
+impl PublicKey {
+    // Imports a SEC1 encoded point, either compressed (33 bytes) or uncompressed (65 bytes)
+    pub fn from_sec1_bytes(bytes: &[u8]) -> Result<Self, KeyError> {
+        VerifyingKey::from_sec1_bytes(bytes)
+            .map(PublicKey::from)
+            .map_err(|_| KeyError::InvalidPublicKey)
+    }
+
+    pub fn from_hex(public_key: &str) -> Result<Self, KeyError> {
+        PublicKey::from_sec1_bytes(&decode_hex(public_key)?)
+    }
+
+    pub fn to_sec1_bytes(&self, compress: bool) -> Vec<u8> {
+        self.verifying_key
+            .to_encoded_point(compress)
+            .as_bytes()
+            .to_vec()
+    }
+
+    pub fn to_hex(&self, compress: bool) -> String {
+        hex::encode(self.to_sec1_bytes(compress))
+    }
+
+    pub fn verifying_key(&self) -> &VerifyingKey {
+        &self.verifying_key
+    }
+
+    // Last 20 bytes of the hash of the uncompressed point, without its 0x04 tag
+    pub fn address(&self) -> Address {
+        let hashed_pub_key = hash_message(&self.to_sec1_bytes(false)[1..]);
+
+        Address(hashed_pub_key[12..].try_into().unwrap())
+    }
+}
+
+impl From<VerifyingKey> for PublicKey {
+    fn from(verifying_key: VerifyingKey) -> Self {
+        Self { verifying_key }
+    }
+}
+
 // 2: Sign Message
 pub fn sign_message(message: &[u8]) -> recoverable::Signature {
-    // Signing
-    let signing_key = SigningKey::from_bytes(&hex::decode(PRIVATE_KEY).unwrap()).unwrap(); // Serialize with `::to_bytes()`
+    Keypair::from_hex(PRIVATE_KEY)
+        .expect("the built-in private key is valid")
+        .sign(message)
+}
 
+pub fn sign_message_with_key(signing_key: &SigningKey, message: &[u8]) -> recoverable::Signature {
     let hash = hash_message(message);
 
     signing_key.sign(&hash)
 }
 
 // 3: Recover Key
-pub fn recover_key(message: &[u8], hex_signature: &str, recovery_id: u8) -> VerifyingKey {
-    let raw_signature = Signature::from_str(hex_signature).unwrap();
-    let parsed_recovery_id = recoverable::Id::new(recovery_id).unwrap();
+pub fn recover_key(
+    message: &[u8],
+    hex_signature: &str,
+    recovery_id: u8,
+) -> Result<VerifyingKey, KeyError> {
+    let raw_signature =
+        Signature::from_str(hex_signature).map_err(|_| KeyError::InvalidSignature)?;
+    let (r, s) = raw_signature.split_bytes();
 
-    let signature = recoverable::Signature::new(&raw_signature, parsed_recovery_id).unwrap();
+    RsvSignature::new(
+        r.into(),
+        s.into(),
+        recovery_id.into(),
+        SignatureMode::Lenient,
+    )?
+    .to_recoverable()
+    .recover_verifying_key(&hash_message(message))
+    .map_err(|_| KeyError::RecoveryFailed)
+}
 
-    signature
+// Recovers the signer of a 65 byte r || s || recovery id signature
+pub fn recover_public_key(message: &[u8], signature: &[u8]) -> Result<PublicKey, KeyError> {
+    if signature.len() != 65 {
+        return Err(KeyError::InvalidLength {
+            expected: 65,
+            found: signature.len(),
+        });
+    }
+
+    parse_recoverable_signature(signature)?
         .recover_verifying_key(&hash_message(message))
-        .unwrap()
+        .map(PublicKey::from)
+        .map_err(|_| KeyError::RecoveryFailed)
 }
 
-// 4: Key to Address
-pub fn get_address(hex_public_key: &str) -> String {
-    let byte_address = hex::decode(hex_public_key).unwrap();
+// Splits a 65 byte r || s || v signature, v being in any of the formats RsvSignature reads
+fn parse_recoverable_signature(signature: &[u8]) -> Result<recoverable::Signature, KeyError> {
+    RsvSignature::from_bytes(signature, SignatureMode::Lenient)
+        .map(|signature| signature.to_recoverable())
+}
+
+// Highest EIP-155 chain id whose v fits in the single byte of the 65 byte form for both recovery
+// ids: 35 + 2 * 109 + 1 = 254. Chain id 110 only fits with recovery id 0, which normalizing s can
+// flip.
+pub const MAX_SINGLE_BYTE_CHAIN_ID: u64 = 109;
+
+// How the recovery id is folded into v
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VFormat {
+    // 0 or 1, as it comes out of the signer
+    RecoveryId,
+    // 27 or 28, what ecrecover, personal_sign and pre EIP-155 transactions use
+    Legacy,
+    // 35 + 2 * chain id + recovery id, from EIP-155 transactions
+    Eip155 { chain_id: u64 },
+}
+
+impl VFormat {
+    pub fn encode(self, recovery_id: u8) -> u64 {
+        let recovery_id = u64::from(recovery_id);
+
+        match self {
+            VFormat::RecoveryId => recovery_id,
+            VFormat::Legacy => 27 + recovery_id,
+            VFormat::Eip155 { chain_id } => 35 + 2 * chain_id + recovery_id,
+        }
+    }
+
+    // Splits v into the recovery id and the format it was written in. Anything from 35 up is a
+    // valid EIP-155 value, so only the gaps below can fail.
+    pub fn decode(v: u64) -> Result<(u8, VFormat), KeyError> {
+        match v {
+            0 | 1 => Ok((v as u8, VFormat::RecoveryId)),
+            27 | 28 => Ok(((v - 27) as u8, VFormat::Legacy)),
+            35.. => Ok((
+                ((v - 35) % 2) as u8,
+                VFormat::Eip155 {
+                    chain_id: (v - 35) / 2,
+                },
+            )),
+            _ => Err(KeyError::InvalidRecoveryId(v)),
+        }
+    }
+}
+
+// For every signature (r, s) there is a second valid one, (r, n - s) with the other recovery id,
+// that anyone can compute without the key. Strict mode only accepts the one with s in the lower
+// half of the curve order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SignatureMode {
+    // Accepts either, like the ecrecover precompile
+    Lenient,
+    // Rejects high s, like transactions since Homestead and OpenZeppelin's ECDSA library
+    Strict,
+}
+
+// An ECDSA signature split into r, s and the recovery id, remembering how its v was written
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RsvSignature {
+    r: [u8; 32],
+    s: [u8; 32],
+    recovery_id: u8,
+    format: VFormat,
+}
+
+impl RsvSignature {
+    // r and s have to be between 1 and n - 1
+    pub fn new(r: [u8; 32], s: [u8; 32], v: u64, mode: SignatureMode) -> Result<Self, KeyError> {
+        let (recovery_id, format) = VFormat::decode(v)?;
+        let signature = Signature::from_scalars(r, s).map_err(|_| KeyError::InvalidSignature)?;
+
+        if mode == SignatureMode::Strict && signature.normalize_s().is_some() {
+            return Err(KeyError::HighS);
+        }
+
+        Ok(Self {
+            r,
+            s,
+            recovery_id,
+            format,
+        })
+    }
+
+    // Reads r || s || v with v as a single byte. EIP-155 values are read up to 255, but only chain
+    // ids up to MAX_SINGLE_BYTE_CHAIN_ID are guaranteed to be written back, larger ones need `new`
+    // with the full v.
+    pub fn from_bytes(bytes: &[u8], mode: SignatureMode) -> Result<Self, KeyError> {
+        if bytes.len() != 65 {
+            return Err(KeyError::InvalidLength {
+                expected: 65,
+                found: bytes.len(),
+            });
+        }
+
+        RsvSignature::new(
+            bytes[..32].try_into().unwrap(),
+            bytes[32..64].try_into().unwrap(),
+            bytes[64].into(),
+            mode,
+        )
+    }
+
+    pub fn from_hex(signature: &str, mode: SignatureMode) -> Result<Self, KeyError> {
+        RsvSignature::from_bytes(&decode_hex(signature)?, mode)
+    }
+
+    // Fails with VOutOfRange when v doesn't fit in a byte, which EIP-155 chain ids up to
+    // MAX_SINGLE_BYTE_CHAIN_ID never hit
+    pub fn to_bytes(self, format: VFormat) -> Result<[u8; 65], KeyError> {
+        let v = self.v(format);
+
+        let mut bytes = [0; 65];
+        bytes[..32].copy_from_slice(&self.r);
+        bytes[32..64].copy_from_slice(&self.s);
+        bytes[64] = u8::try_from(v).map_err(|_| KeyError::VOutOfRange(v))?;
+
+        Ok(bytes)
+    }
+
+    pub fn to_hex(self, format: VFormat) -> Result<String, KeyError> {
+        self.to_bytes(format).map(hex::encode)
+    }
+
+    pub fn r(&self) -> [u8; 32] {
+        self.r
+    }
+
+    pub fn s(&self) -> [u8; 32] {
+        self.s
+    }
+
+    pub fn recovery_id(&self) -> u8 {
+        self.recovery_id
+    }
+
+    // The format v was in when the signature was parsed
+    pub fn format(&self) -> VFormat {
+        self.format
+    }
 
-    let (_, address) = byte_address.split_at(1);
+    pub fn v(&self, format: VFormat) -> u64 {
+        format.encode(self.recovery_id)
+    }
 
-    let hashed_pub_key = hash_message(address);
+    pub fn is_low_s(&self) -> bool {
+        self.to_signature().normalize_s().is_none()
+    }
 
-    let (_, partial_address) = hashed_pub_key.split_at(12);
+    // Swaps a high s for n - s, which also flips the parity of the recovered point
+    pub fn normalize_s(self) -> Self {
+        match self.to_signature().normalize_s() {
+            Some(normalized) => Self {
+                s: normalized.split_bytes().1.into(),
+                recovery_id: self.recovery_id ^ 1,
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    // Public key of whoever signed the given 32 byte hash
+    pub fn recover(&self, hash: &[u8; 32]) -> Result<PublicKey, KeyError> {
+        self.to_recoverable()
+            .recover_verifying_key_from_digest_bytes(hash.into())
+            .map(PublicKey::from)
+            .map_err(|_| KeyError::RecoveryFailed)
+    }
 
-    hex::encode(partial_address)
+    fn to_signature(self) -> Signature {
+        Signature::from_scalars(self.r, self.s).expect("r and s were checked when parsing")
+    }
+
+    fn to_recoverable(self) -> recoverable::Signature {
+        let recovery_id =
+            recoverable::Id::new(self.recovery_id).expect("the recovery id is 0 or 1");
+
+        recoverable::Signature::new(&self.to_signature(), recovery_id)
+            .expect("r and s were checked when parsing")
+    }
+}
+
+// Solidity's ecrecover: only takes a v of 27 or 28, accepts high s, and returns the zero address
+// instead of failing
+pub fn ecrecover(hash: &[u8; 32], v: u8, r: [u8; 32], s: [u8; 32]) -> Address {
+    if v != 27 && v != 28 {
+        return Address::ZERO;
+    }
+
+    RsvSignature::new(r, s, v.into(), SignatureMode::Lenient)
+        .and_then(|signature| signature.recover(hash))
+        .map(|public_key| public_key.address())
+        .unwrap_or(Address::ZERO)
+}
+
+// What `ecrecover(keccak256(message), v, r, s) == signer` checks on chain, minus the classic bug:
+// a broken signature recovers the zero address, so the zero address never verifies
+pub fn verify(address: Address, message: impl AsRef<[u8]>, signature: &[u8]) -> bool {
+    verify_with_mode(address, message, signature, SignatureMode::Lenient)
+}
+
+// Same as verify, but also rejects high s signatures
+pub fn verify_strict(address: Address, message: impl AsRef<[u8]>, signature: &[u8]) -> bool {
+    verify_with_mode(address, message, signature, SignatureMode::Strict)
+}
+
+fn verify_with_mode(
+    address: Address,
+    message: impl AsRef<[u8]>,
+    signature: &[u8],
+    mode: SignatureMode,
+) -> bool {
+    if address == Address::ZERO || signature.len() != 65 {
+        return false;
+    }
+
+    let hash: [u8; 32] = hash_message(message).try_into().unwrap();
+    let (r, s) = (
+        signature[..32].try_into().unwrap(),
+        signature[32..64].try_into().unwrap(),
+    );
+
+    if mode == SignatureMode::Strict && RsvSignature::new(r, s, signature[64].into(), mode).is_err()
+    {
+        return false;
+    }
+
+    ecrecover(&hash, signature[64], r, s) == address
+}
+
+// EIP-191 version 0x45 hash, the message prefixed with its length in decimal
+pub fn hash_personal_message(message: impl AsRef<[u8]>) -> [u8; 32] {
+    let message = message.as_ref();
+
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    prefixed.extend_from_slice(message);
+
+    hash_message(prefixed).try_into().unwrap()
+}
+
+// Public key of whoever signed the given 32 byte hash
+pub fn recover_hash_public_key(hash: &[u8; 32], signature: &[u8]) -> Result<PublicKey, KeyError> {
+    RsvSignature::from_bytes(signature, SignatureMode::Lenient)?.recover(hash)
+}
+
+// Address whose key signed the given 32 byte hash
+pub fn recover_hash_signer(hash: &[u8; 32], signature: &[u8]) -> Result<Address, KeyError> {
+    recover_hash_public_key(hash, signature).map(|public_key| public_key.address())
+}
+
+// Public key behind a personal_sign signature, e.g. to encrypt something for its holder
+pub fn recover_personal_public_key(
+    message: impl AsRef<[u8]>,
+    signature: &[u8],
+) -> Result<PublicKey, KeyError> {
+    recover_hash_public_key(&hash_personal_message(message), signature)
+}
+
+// Address whose key produced the personal_sign signature over the message
+pub fn recover_personal_signer(
+    message: impl AsRef<[u8]>,
+    signature: &[u8],
+) -> Result<Address, KeyError> {
+    recover_hash_signer(&hash_personal_message(message), signature)
+}
+
+// Checks that the signature over the hash comes from the given address
+pub fn verify_hash_signature(
+    hash: &[u8; 32],
+    signature: &[u8],
+    address: Address,
+) -> Result<(), KeyError> {
+    let signer = recover_hash_signer(hash, signature)?;
+
+    if signer != address {
+        return Err(KeyError::SignerMismatch {
+            expected: address,
+            found: signer,
+        });
+    }
+
+    Ok(())
+}
+
+// Checks that the signature over the message comes from the given address
+pub fn verify_personal_signature(
+    message: impl AsRef<[u8]>,
+    signature: &[u8],
+    address: Address,
+) -> Result<(), KeyError> {
+    verify_hash_signature(&hash_personal_message(message), signature, address)
+}
+
+// 4: Key to Address
+pub fn get_address(hex_public_key: &str) -> Result<Address, KeyError> {
+    PublicKey::from_hex(hex_public_key).map(|public_key| public_key.address())
 }
 
 #[cfg(test)]
@@ -111,7 +724,8 @@ mod tests {
                 message.as_bytes(),
                 &format!("{}{}", signature.r(), signature.s()),
                 signature.recovery_id().into(),
-            );
+            )
+            .unwrap();
 
             assert_eq!(recovered_key, pub_key);
         }
@@ -120,7 +734,7 @@ mod tests {
     mod key_to_address {
         use k256::{ecdsa::SigningKey, elliptic_curve::sec1::ToEncodedPoint};
 
-        use crate::week_1::digital_signatures::{get_address, PRIVATE_KEY};
+        use crate::week_1::digital_signatures::{get_address, KeyError, PRIVATE_KEY};
 
         const EXPECTED_ADDRESS: &str = "16bB6031CBF3a12B899aB99D96B64b7bbD719705";
 
@@ -132,9 +746,545 @@ mod tests {
 
             let uncompressed_pub_key = pub_key.to_encoded_point(false);
 
-            let address = get_address(&hex::encode(uncompressed_pub_key));
+            let address = get_address(&hex::encode(uncompressed_pub_key)).unwrap();
+
+            assert_eq!(address.to_string(), format!("0x{}", EXPECTED_ADDRESS));
+        }
+
+        #[test]
+        fn should_reject_a_malformed_public_key() {
+            assert_eq!(get_address("04abcd"), Err(KeyError::InvalidPublicKey));
+            assert_eq!(get_address("not hex"), Err(KeyError::InvalidHex));
+        }
+    }
+
+    mod keypair {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        use crate::week_1::digital_signatures::{KeyError, Keypair, PRIVATE_KEY};
+
+        #[test]
+        fn should_generate_the_same_key_from_the_same_seed() {
+            assert_eq!(
+                Keypair::from_seed(1).to_hex(),
+                Keypair::from_seed(1).to_hex()
+            );
+            assert_ne!(
+                Keypair::from_seed(1).to_hex(),
+                Keypair::from_seed(2).to_hex()
+            );
+        }
+
+        #[test]
+        fn should_generate_distinct_keys_from_one_rng() {
+            let mut rng = StdRng::seed_from_u64(7);
+
+            let first = Keypair::random(&mut rng);
+            let second = Keypair::random(&mut rng);
+
+            assert_ne!(first.address(), second.address());
+        }
+
+        #[test]
+        fn should_round_trip_hex_and_bytes() {
+            let keypair = Keypair::from_hex(&format!("0x{}", PRIVATE_KEY)).unwrap();
+
+            assert_eq!(keypair.to_hex(), PRIVATE_KEY);
+            assert_eq!(
+                Keypair::from_bytes(&keypair.to_bytes())
+                    .unwrap()
+                    .address()
+                    .to_hex(),
+                "16bb6031cbf3a12b899ab99d96b64b7bbd719705"
+            );
+        }
+
+        #[test]
+        fn should_reject_invalid_private_keys() {
+            assert_eq!(
+                Keypair::from_bytes(&[1; 31]).err(),
+                Some(KeyError::InvalidLength {
+                    expected: 32,
+                    found: 31
+                })
+            );
+            assert_eq!(
+                Keypair::from_bytes(&[0; 32]).err(),
+                Some(KeyError::InvalidPrivateKey)
+            );
+            assert_eq!(Keypair::from_hex("zz").err(), Some(KeyError::InvalidHex));
+        }
+
+        #[test]
+        fn should_not_print_the_private_key() {
+            let keypair = Keypair::from_hex(PRIVATE_KEY).unwrap();
+
+            assert!(!format!("{:?}", keypair).contains(PRIVATE_KEY));
+        }
+    }
+
+    mod public_key {
+        use crate::week_1::digital_signatures::{Keypair, PublicKey};
+
+        #[test]
+        fn should_accept_compressed_and_uncompressed_keys() {
+            let public_key = Keypair::from_seed(3).public_key();
+
+            let compressed = PublicKey::from_hex(&public_key.to_hex(true)).unwrap();
+            let uncompressed =
+                PublicKey::from_sec1_bytes(&public_key.to_sec1_bytes(false)).unwrap();
+
+            assert_eq!(public_key.to_sec1_bytes(true).len(), 33);
+            assert_eq!(public_key.to_sec1_bytes(false).len(), 65);
+            assert_eq!(compressed, uncompressed);
+            assert_eq!(compressed.address(), Keypair::from_seed(3).address());
+        }
+    }
+
+    mod recover_public_key {
+        use crate::week_1::digital_signatures::{recover_public_key, KeyError, Keypair};
+
+        #[test]
+        fn should_recover_the_key_of_any_signer() {
+            for seed in 0..3 {
+                let keypair = Keypair::from_seed(seed);
+                let signature = keypair.sign(b"hello world");
+
+                let public_key = recover_public_key(b"hello world", signature.as_ref()).unwrap();
+
+                assert_eq!(public_key, keypair.public_key());
+            }
+        }
+
+        #[test]
+        fn should_reject_a_malformed_signature() {
+            let signature = Keypair::from_seed(0).sign(b"hello world");
+            let mut bytes = signature.as_ref().to_vec();
+
+            assert_eq!(
+                recover_public_key(b"hello world", &bytes[..64]),
+                Err(KeyError::InvalidLength {
+                    expected: 65,
+                    found: 64
+                })
+            );
+
+            bytes[64] = 4;
+
+            assert_eq!(
+                recover_public_key(b"hello world", &bytes),
+                Err(KeyError::InvalidRecoveryId(4))
+            );
+        }
+    }
+
+    mod address {
+        use crate::week_1::digital_signatures::{Address, AddressError};
+
+        // Test vectors from EIP-55
+        const CHECKSUMMED: [&str; 4] = [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ];
+
+        #[test]
+        fn should_encode_the_checksum() {
+            for checksummed in CHECKSUMMED {
+                let address = checksummed.to_lowercase().parse::<Address>().unwrap();
+
+                assert_eq!(address.to_string(), checksummed);
+            }
+        }
+
+        #[test]
+        fn should_accept_a_valid_checksum_and_single_case_addresses() {
+            for checksummed in CHECKSUMMED {
+                let address = checksummed.parse::<Address>().unwrap();
+
+                assert_eq!(checksummed[2..].to_uppercase().parse(), Ok(address));
+                assert_eq!(checksummed.to_lowercase().parse(), Ok(address));
+            }
+        }
+
+        #[test]
+        fn should_reject_a_mistyped_address() {
+            assert_eq!(
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD".parse::<Address>(),
+                Err(AddressError::ChecksumMismatch {
+                    expected: String::from("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed")
+                })
+            );
+            assert_eq!(
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA".parse::<Address>(),
+                Err(AddressError::WrongLength {
+                    expected: 20,
+                    found: 19
+                })
+            );
+            assert_eq!(
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeg".parse::<Address>(),
+                Err(AddressError::NotHex)
+            );
+        }
+    }
+
+    mod personal_sign {
+        use ethers::{
+            signers::{LocalWallet, Signer},
+            utils,
+        };
+
+        use crate::week_1::digital_signatures::{
+            hash_personal_message, recover_personal_signer, verify_personal_signature, KeyError,
+            Keypair, PRIVATE_KEY,
+        };
+
+        const MESSAGE: &str = "Sign in to the bootcamp";
+
+        #[test]
+        fn should_hash_like_ethers() {
+            assert_eq!(
+                hash_personal_message(MESSAGE),
+                utils::hash_message(MESSAGE).to_fixed_bytes()
+            );
+        }
+
+        #[tokio::test]
+        async fn should_sign_like_an_ethers_wallet() {
+            let wallet = PRIVATE_KEY.parse::<LocalWallet>().unwrap();
+            let keypair = Keypair::from_hex(PRIVATE_KEY).unwrap();
+
+            let expected = wallet.sign_message(MESSAGE).await.unwrap();
+            let signature = keypair.sign_personal_message(MESSAGE).unwrap();
+
+            assert_eq!(signature.to_vec(), expected.to_vec());
+            assert_eq!(
+                recover_personal_signer(MESSAGE, &expected.to_vec()),
+                Ok(keypair.address())
+            );
+        }
+
+        #[test]
+        fn should_verify_the_signer() {
+            let keypair = Keypair::from_seed(1);
+            let signature = keypair.sign_personal_message(MESSAGE).unwrap();
+
+            assert_eq!(
+                verify_personal_signature(MESSAGE, &signature, keypair.address()),
+                Ok(())
+            );
+            assert!(
+                verify_personal_signature("Another message", &signature, keypair.address())
+                    .is_err()
+            );
+        }
+
+        #[test]
+        fn should_reject_a_signature_from_another_key() {
+            let keypair = Keypair::from_seed(1);
+            let other = Keypair::from_seed(2);
+            let signature = other.sign_personal_message(MESSAGE).unwrap();
+
+            assert_eq!(
+                verify_personal_signature(MESSAGE, &signature, keypair.address()),
+                Err(KeyError::SignerMismatch {
+                    expected: keypair.address(),
+                    found: other.address()
+                })
+            );
+        }
+    }
+
+    mod rsv_signature {
+        use ethers::types::{RecoveryMessage, Signature as EthersSignature, H256, U256};
+        use k256::{elliptic_curve::PrimeField, Scalar};
+
+        use crate::week_1::digital_signatures::{
+            hash_message, KeyError, Keypair, RsvSignature, SignatureMode, VFormat,
+            MAX_SINGLE_BYTE_CHAIN_ID,
+        };
+
+        fn sign(keypair: &Keypair, message: &str) -> ([u8; 32], [u8; 65]) {
+            let hash = hash_message(message).try_into().unwrap();
+
+            (hash, keypair.sign_hash(&hash).unwrap())
+        }
+
+        // The other valid signature over the same hash, with s replaced by n - s and v swapped
+        // between 27 and 28
+        fn malleate(signature: &[u8; 65]) -> [u8; 65] {
+            let s = Scalar::from_repr(<[u8; 32]>::try_from(&signature[32..64]).unwrap().into())
+                .unwrap();
+
+            let mut high = *signature;
+            high[32..64].copy_from_slice(&(-s).to_bytes());
+            high[64] = 55 - high[64];
+
+            high
+        }
+
+        #[test]
+        fn should_read_every_v_format() {
+            let keypair = Keypair::from_seed(1);
+            let (hash, signature) = sign(&keypair, "hello world");
+            let recovery_id = signature[64] - 27;
+
+            for (v, format) in [
+                (recovery_id, VFormat::RecoveryId),
+                (recovery_id + 27, VFormat::Legacy),
+                (recovery_id + 37, VFormat::Eip155 { chain_id: 1 }),
+                (recovery_id + 35, VFormat::Eip155 { chain_id: 0 }),
+            ] {
+                let mut bytes = signature;
+                bytes[64] = v;
+
+                let parsed = RsvSignature::from_bytes(&bytes, SignatureMode::Strict).unwrap();
+
+                assert_eq!(parsed.recovery_id(), recovery_id);
+                assert_eq!(parsed.format(), format);
+                assert_eq!(parsed.to_bytes(format), Ok(bytes));
+                assert_eq!(parsed.recover(&hash), Ok(keypair.public_key()));
+            }
+        }
+
+        #[test]
+        fn should_encode_chain_ids_that_dont_fit_in_a_byte() {
+            let (_, signature) = sign(&Keypair::from_seed(1), "hello world");
+            let parsed = RsvSignature::from_bytes(&signature, SignatureMode::Strict).unwrap();
+            let sepolia = VFormat::Eip155 { chain_id: 11155111 };
+
+            let v = parsed.v(sepolia);
+
+            assert_eq!(v, 22310257 + u64::from(parsed.recovery_id()));
+            assert_eq!(VFormat::decode(v), Ok((parsed.recovery_id(), sepolia)));
+            assert_eq!(
+                RsvSignature::new(parsed.r(), parsed.s(), v, SignatureMode::Strict)
+                    .unwrap()
+                    .format(),
+                sepolia
+            );
+            assert_eq!(parsed.to_bytes(sepolia), Err(KeyError::VOutOfRange(v)));
+        }
+
+        #[test]
+        fn should_fit_chain_ids_up_to_the_single_byte_limit() {
+            let (_, signature) = sign(&Keypair::from_seed(1), "hello world");
+            let parsed = RsvSignature::from_bytes(&signature, SignatureMode::Lenient).unwrap();
+            let flipped = RsvSignature::new(
+                parsed.r(),
+                parsed.s(),
+                u64::from(parsed.recovery_id() ^ 1),
+                SignatureMode::Lenient,
+            )
+            .unwrap();
+            let highest = VFormat::Eip155 {
+                chain_id: MAX_SINGLE_BYTE_CHAIN_ID,
+            };
+            let above = VFormat::Eip155 {
+                chain_id: MAX_SINGLE_BYTE_CHAIN_ID + 1,
+            };
+
+            for signature in [parsed, flipped] {
+                assert!(signature.to_bytes(highest).is_ok());
+            }
+
+            let odd = [parsed, flipped]
+                .into_iter()
+                .find(|signature| signature.recovery_id() == 1)
+                .unwrap();
+
+            assert_eq!(odd.to_bytes(above), Err(KeyError::VOutOfRange(256)));
+        }
+
+        #[test]
+        fn should_reject_malformed_signatures() {
+            let (_, signature) = sign(&Keypair::from_seed(1), "hello world");
+
+            for v in [2, 26, 29, 34] {
+                let mut bytes = signature;
+                bytes[64] = v;
+
+                assert_eq!(
+                    RsvSignature::from_bytes(&bytes, SignatureMode::Lenient),
+                    Err(KeyError::InvalidRecoveryId(v.into()))
+                );
+            }
+
+            let mut zero_r = signature;
+            zero_r[..32].fill(0);
+            let mut s_above_n = signature;
+            s_above_n[32..64].fill(0xff);
+
+            for bytes in [zero_r, s_above_n] {
+                assert_eq!(
+                    RsvSignature::from_bytes(&bytes, SignatureMode::Lenient),
+                    Err(KeyError::InvalidSignature)
+                );
+            }
+            assert_eq!(
+                RsvSignature::from_bytes(&signature[..64], SignatureMode::Lenient),
+                Err(KeyError::InvalidLength {
+                    expected: 65,
+                    found: 64
+                })
+            );
+        }
+
+        #[test]
+        fn should_only_accept_high_s_when_lenient() {
+            let keypair = Keypair::from_seed(2);
+            let (hash, signature) = sign(&keypair, "hello world");
+            let high = malleate(&signature);
+
+            assert_eq!(
+                RsvSignature::from_bytes(&high, SignatureMode::Strict),
+                Err(KeyError::HighS)
+            );
+
+            let lenient = RsvSignature::from_bytes(&high, SignatureMode::Lenient).unwrap();
+            let normalized = lenient.normalize_s();
+
+            assert!(!lenient.is_low_s());
+            assert_eq!(lenient.recover(&hash), Ok(keypair.public_key()));
+            assert!(normalized.is_low_s());
+            assert_eq!(normalized.to_bytes(VFormat::Legacy), Ok(signature));
+            assert_eq!(normalized.normalize_s(), normalized);
+        }
+
+        #[test]
+        fn should_sign_with_low_s() {
+            for seed in 0..20 {
+                let (_, signature) = sign(&Keypair::from_seed(seed), "hello world");
+
+                assert!(RsvSignature::from_bytes(&signature, SignatureMode::Strict).is_ok());
+            }
+        }
+
+        #[test]
+        fn should_recover_like_ethers() {
+            let keypair = Keypair::from_seed(3);
+            let (hash, signature) = sign(&keypair, "hello world");
+            let parsed = RsvSignature::from_hex(
+                &format!("0x{}", hex::encode(signature)),
+                SignatureMode::Strict,
+            )
+            .unwrap();
+            let chain_id = VFormat::Eip155 { chain_id: 1337 };
+
+            let ethers_signature = EthersSignature {
+                r: U256::from_big_endian(&parsed.r()),
+                s: U256::from_big_endian(&parsed.s()),
+                v: parsed.v(chain_id),
+            };
+            let recovered = ethers_signature
+                .recover(RecoveryMessage::Hash(H256(hash)))
+                .unwrap();
+
+            assert_eq!(recovered.to_fixed_bytes(), keypair.address().to_bytes());
+            assert_eq!(
+                parsed.to_hex(VFormat::Legacy).unwrap(),
+                EthersSignature {
+                    v: parsed.v(VFormat::Legacy),
+                    ..ethers_signature
+                }
+                .to_string()
+            );
+        }
+    }
+
+    mod verify {
+        use k256::{elliptic_curve::PrimeField, Scalar};
+
+        use crate::week_1::digital_signatures::{
+            ecrecover, hash_message, verify, verify_strict, Address, Keypair,
+        };
+
+        const MESSAGE: &str = "Transfer 1 ether to the bootcamp";
+
+        fn sign(keypair: &Keypair) -> [u8; 65] {
+            keypair
+                .sign_hash(&hash_message(MESSAGE).try_into().unwrap())
+                .unwrap()
+        }
+
+        #[test]
+        fn should_verify_the_signer() {
+            let keypair = Keypair::from_seed(1);
+            let signature = sign(&keypair);
+
+            assert!(verify(keypair.address(), MESSAGE, &signature));
+            assert!(verify_strict(keypair.address(), MESSAGE, &signature));
+            assert!(!verify(keypair.address(), "Transfer 2 ether", &signature));
+            assert!(!verify(
+                Keypair::from_seed(2).address(),
+                MESSAGE,
+                &signature
+            ));
+        }
+
+        #[test]
+        fn should_only_take_a_v_of_27_or_28() {
+            let keypair = Keypair::from_seed(1);
+            let mut signature = sign(&keypair);
+
+            signature[64] -= 27;
+
+            assert!(!verify(keypair.address(), MESSAGE, &signature));
+
+            signature[64] += 37;
+
+            assert!(!verify(keypair.address(), MESSAGE, &signature));
+        }
+
+        #[test]
+        fn should_accept_high_s_unless_strict() {
+            let keypair = Keypair::from_seed(1);
+            let mut signature = sign(&keypair);
+
+            let s = Scalar::from_repr(<[u8; 32]>::try_from(&signature[32..64]).unwrap().into())
+                .unwrap();
+            signature[32..64].copy_from_slice(&(-s).to_bytes());
+            signature[64] = 55 - signature[64];
+
+            assert!(verify(keypair.address(), MESSAGE, &signature));
+            assert!(!verify_strict(keypair.address(), MESSAGE, &signature));
+        }
+
+        #[test]
+        fn should_recover_the_zero_address_from_a_broken_signature() {
+            let hash = hash_message(MESSAGE).try_into().unwrap();
+            let signature = sign(&Keypair::from_seed(1));
+            let (r, s) = (
+                signature[..32].try_into().unwrap(),
+                signature[32..64].try_into().unwrap(),
+            );
+
+            assert_ne!(ecrecover(&hash, signature[64], r, s), Address::ZERO);
+            assert_eq!(ecrecover(&hash, 0, r, s), Address::ZERO);
+            assert_eq!(ecrecover(&hash, signature[64], [0; 32], s), Address::ZERO);
+            assert_eq!(
+                ecrecover(&hash, signature[64], r, [0xff; 32]),
+                Address::ZERO
+            );
+        }
+
+        #[test]
+        fn should_never_verify_the_zero_address() {
+            let garbage = [0; 65];
 
-            assert_eq!(address, EXPECTED_ADDRESS.to_lowercase());
+            assert!(!verify(Address::ZERO, MESSAGE, &garbage));
+            assert!(!verify(
+                Address::ZERO,
+                MESSAGE,
+                &sign(&Keypair::from_seed(1))
+            ));
+            assert!(!verify(
+                Keypair::from_seed(1).address(),
+                MESSAGE,
+                &sign(&Keypair::from_seed(1))[..64]
+            ));
         }
     }
 }
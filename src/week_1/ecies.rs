@@ -0,0 +1,286 @@
+use aes::Aes128;
+use ctr::{
+    cipher::{KeyIvInit, StreamCipher},
+    Ctr128BE,
+};
+use hmac::{Hmac, Mac};
+use k256::{
+    elliptic_curve::{
+        rand_core::{CryptoRng, RngCore},
+        sec1::ToEncodedPoint,
+        PrimeField,
+    },
+    Scalar,
+};
+use sha2::{Digest, Sha256};
+
+use super::digital_signatures::{KeyError, Keypair, PublicKey};
+
+// Elliptic Curve Integrated Encryption Scheme, laid out like devp2p's
+//
+// The sender makes up an ephemeral key and agrees with the recipient's public key on a shared
+// secret. A key derivation function turns it into an AES-128-CTR key and an HMAC-SHA256 key. The
+// ciphertext is the ephemeral public key, the IV, the encrypted message and the tag:
+//
+//     R (65 bytes, uncompressed) || iv (16 bytes) || ciphertext || tag (32 bytes)
+
+const EPHEMERAL_KEY_LENGTH: usize = 65;
+const IV_LENGTH: usize = 16;
+const TAG_LENGTH: usize = 32;
+// Bytes a ciphertext has on top of the message it encrypts
+pub const OVERHEAD: usize = EPHEMERAL_KEY_LENGTH + IV_LENGTH + TAG_LENGTH;
+
+#[derive(PartialEq, Debug)]
+pub enum EciesError {
+    TooShort { minimum: usize, found: usize },
+    MacMismatch,
+    Key(KeyError),
+}
+
+impl From<KeyError> for EciesError {
+    fn from(error: KeyError) -> Self {
+        EciesError::Key(error)
+    }
+}
+
+// ECDH, the x coordinate of our private key times their public key. Both sides get the same one.
+pub fn shared_secret(keypair: &Keypair, public_key: &PublicKey) -> [u8; 32] {
+    let scalar: Scalar = Option::from(Scalar::from_repr(keypair.to_bytes().into()))
+        .expect("a private key is a scalar");
+    let point = k256::PublicKey::from(public_key.verifying_key()).to_projective() * scalar;
+
+    point
+        .to_affine()
+        .to_encoded_point(false)
+        .x()
+        .expect("a multiple of a valid public key by a private key is never the identity")
+        .as_slice()
+        .try_into()
+        .unwrap()
+}
+
+// Encrypts the message so only the holder of the public key can read it. The MAC data is
+// authenticated but not encrypted, RLPx puts the size of the packet there.
+pub fn encrypt(
+    public_key: &PublicKey,
+    message: &[u8],
+    mac_data: &[u8],
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Vec<u8> {
+    let ephemeral = Keypair::random(rng);
+    let mut iv = [0; IV_LENGTH];
+    rng.fill_bytes(&mut iv);
+
+    let (encryption_key, mac_key) = derive_keys(&shared_secret(&ephemeral, public_key));
+
+    let mut encrypted = message.to_vec();
+    Ctr128BE::<Aes128>::new(&encryption_key.into(), &iv.into()).apply_keystream(&mut encrypted);
+
+    let mut ciphertext = ephemeral.public_key().to_sec1_bytes(false);
+    ciphertext.extend(iv);
+    ciphertext.extend(encrypted);
+
+    let tag = tag(&mac_key, &ciphertext[EPHEMERAL_KEY_LENGTH..], mac_data)
+        .finalize()
+        .into_bytes();
+    ciphertext.extend(tag);
+
+    ciphertext
+}
+
+// Checks the tag before decrypting, so a tampered ciphertext or the wrong key fails with
+// MacMismatch
+pub fn decrypt(
+    keypair: &Keypair,
+    ciphertext: &[u8],
+    mac_data: &[u8],
+) -> Result<Vec<u8>, EciesError> {
+    if ciphertext.len() < OVERHEAD {
+        return Err(EciesError::TooShort {
+            minimum: OVERHEAD,
+            found: ciphertext.len(),
+        });
+    }
+
+    let (ephemeral_key, rest) = ciphertext.split_at(EPHEMERAL_KEY_LENGTH);
+    let (authenticated, expected_tag) = rest.split_at(rest.len() - TAG_LENGTH);
+
+    let ephemeral_key = PublicKey::from_sec1_bytes(ephemeral_key)?;
+    let (encryption_key, mac_key) = derive_keys(&shared_secret(keypair, &ephemeral_key));
+
+    tag(&mac_key, authenticated, mac_data)
+        .verify_slice(expected_tag)
+        .map_err(|_| EciesError::MacMismatch)?;
+
+    let (iv, encrypted) = authenticated.split_at(IV_LENGTH);
+    let mut message = encrypted.to_vec();
+    Ctr128BE::<Aes128>::new(encryption_key[..].into(), iv.into()).apply_keystream(&mut message);
+
+    Ok(message)
+}
+
+// NIST SP 800-56 concatenation KDF over SHA-256, the rounds being SHA-256(counter || secret)
+// with the counter starting at 1
+pub fn concat_kdf(secret: &[u8], length: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(length);
+    let mut counter: u32 = 1;
+
+    while output.len() < length {
+        let mut hasher = Sha256::new();
+        hasher.update(counter.to_be_bytes());
+        hasher.update(secret);
+
+        output.extend(hasher.finalize());
+        counter += 1;
+    }
+
+    output.truncate(length);
+    output
+}
+
+// The AES key is the first 16 bytes out of the KDF, the MAC key the hash of the next 16
+fn derive_keys(shared_secret: &[u8; 32]) -> ([u8; 16], [u8; 32]) {
+    let keys = concat_kdf(shared_secret, 32);
+
+    (
+        keys[..16].try_into().unwrap(),
+        Sha256::digest(&keys[16..]).into(),
+    )
+}
+
+// HMAC-SHA256 of iv || ciphertext || MAC data
+fn tag(mac_key: &[u8; 32], authenticated: &[u8], mac_data: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(mac_key).expect("HMAC takes keys of any length");
+    mac.update(authenticated);
+    mac.update(mac_data);
+
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    mod shared_secret {
+        use crate::week_1::{
+            digital_signatures::Keypair,
+            ecies::{concat_kdf, shared_secret},
+        };
+
+        #[test]
+        fn should_agree_on_the_same_secret_from_both_sides() {
+            // Static keys of go-ethereum's ECIES tests
+            let alice = Keypair::from_hex(
+                "7ebbc6a8358bc76dd73ebc557056702c8cfc34e5cfcd90eb83af0347575fd2ad",
+            )
+            .unwrap();
+            let bob = Keypair::from_hex(
+                "6a3d6396903245bba5837752b9e0348874e72db0c4e11e9c485a81b4ea4353b9",
+            )
+            .unwrap();
+
+            let secret = shared_secret(&alice, &bob.public_key());
+
+            assert_eq!(secret, shared_secret(&bob, &alice.public_key()));
+            assert_eq!(
+                hex::encode(secret),
+                "167ccc13ac5e8a26b131c3446030c60fbfac6aa8e31149d0869f93626a4cdf62"
+            );
+        }
+
+        #[test]
+        fn should_derive_keys_like_go_ethereum() {
+            assert_eq!(hex::encode(concat_kdf(b"input", 6)), "858b192fa2ed");
+            assert_eq!(
+                hex::encode(concat_kdf(b"input", 48)),
+                "858b192fa2ed4395e2bf88dd8d5770d67dc284ee539f12da8bceaa45d06ebae0700f1ab918a5f0413b8140f9940d6955"
+            );
+        }
+    }
+
+    mod encrypt {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        use crate::week_1::{
+            digital_signatures::{recover_personal_public_key, Keypair},
+            ecies::{decrypt, encrypt, EciesError, OVERHEAD},
+        };
+
+        const MESSAGE: &[u8] = b"Meet at the bootcamp at noon";
+
+        #[test]
+        fn should_decrypt_a_reference_ciphertext() {
+            let recipient = Keypair::from_hex(
+                "6a3d6396903245bba5837752b9e0348874e72db0c4e11e9c485a81b4ea4353b9",
+            )
+            .unwrap();
+
+            // Produced by an independent implementation of the same layout, with an ephemeral key
+            // of 0x01..01 and an IV of 0x02..02
+            let ciphertext = hex::decode(REFERENCE).unwrap();
+
+            assert_eq!(decrypt(&recipient, &ciphertext, b""), Ok(MESSAGE.to_vec()));
+        }
+
+        #[test]
+        fn should_round_trip_with_and_without_mac_data() {
+            let mut rng = StdRng::seed_from_u64(1);
+            let recipient = Keypair::random(&mut rng);
+
+            for mac_data in [&b""[..], &[0x01, 0x94][..]] {
+                let ciphertext = encrypt(&recipient.public_key(), MESSAGE, mac_data, &mut rng);
+
+                assert_eq!(ciphertext.len(), MESSAGE.len() + OVERHEAD);
+                assert_eq!(
+                    decrypt(&recipient, &ciphertext, mac_data),
+                    Ok(MESSAGE.to_vec())
+                );
+                assert_eq!(
+                    decrypt(&recipient, &ciphertext, b"other"),
+                    Err(EciesError::MacMismatch)
+                );
+            }
+        }
+
+        #[test]
+        fn should_only_be_readable_by_the_recipient() {
+            let mut rng = StdRng::seed_from_u64(2);
+            let recipient = Keypair::random(&mut rng);
+            let eavesdropper = Keypair::random(&mut rng);
+
+            let mut ciphertext = encrypt(&recipient.public_key(), MESSAGE, b"", &mut rng);
+
+            assert_eq!(
+                decrypt(&eavesdropper, &ciphertext, b""),
+                Err(EciesError::MacMismatch)
+            );
+
+            ciphertext[OVERHEAD] ^= 1;
+
+            assert_eq!(
+                decrypt(&recipient, &ciphertext, b""),
+                Err(EciesError::MacMismatch)
+            );
+            assert_eq!(
+                decrypt(&recipient, &ciphertext[..OVERHEAD - 1], b""),
+                Err(EciesError::TooShort {
+                    minimum: OVERHEAD,
+                    found: OVERHEAD - 1
+                })
+            );
+        }
+
+        #[test]
+        fn should_encrypt_to_the_key_behind_a_signature() {
+            let mut rng = StdRng::seed_from_u64(3);
+            let recipient = Keypair::random(&mut rng);
+            let signature = recipient.sign_personal_message("I am the owner").unwrap();
+
+            let public_key = recover_personal_public_key("I am the owner", &signature).unwrap();
+            let ciphertext = encrypt(&public_key, MESSAGE, b"", &mut rng);
+
+            assert_eq!(public_key.address(), recipient.address());
+            assert_eq!(decrypt(&recipient, &ciphertext, b""), Ok(MESSAGE.to_vec()));
+        }
+
+        const REFERENCE: &str = "041b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f70beaf8f588b541507fed6a642c5ab42dfdf8120a7f639de5122d47a69a8e8d102020202020202020202020202020202b4143e7abe665ad3e3cd265145c05b357ee1812db00acf9caf89743d2e4d47a8c6c7e535b1a8e86b70b97655ba947fe6c7953bdbd8a278a4e4b36bd2";
+    }
+}
@@ -0,0 +1,202 @@
+// Canonical Encoding
+//
+// Every value has exactly one encoding: integers are big endian with a fixed width and variable
+// length data is prefixed with its length as a u32.
+
+#[derive(PartialEq, Debug)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    InvalidUtf8,
+    InvalidValue,
+    TrailingBytes,
+}
+
+pub trait Encode {
+    fn encode(&self, encoder: &mut Encoder);
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        self.encode(&mut encoder);
+
+        encoder.into_bytes()
+    }
+}
+
+pub trait Decode: Sized {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError>;
+
+    // Decodes a value that has to span the whole input
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut decoder = Decoder::new(bytes);
+        let value = Self::decode(&mut decoder)?;
+
+        if !decoder.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+
+        Ok(value)
+    }
+}
+
+pub struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value)
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_be_bytes())
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_be_bytes())
+    }
+
+    pub fn u128(&mut self, value: u128) {
+        self.bytes.extend_from_slice(&value.to_be_bytes())
+    }
+
+    // Data whose length is implied by its type, written without a prefix
+    pub fn fixed(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes)
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.fixed(bytes);
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.bytes(value.as_bytes())
+    }
+
+    pub fn sequence<T: Encode>(&mut self, items: &[T]) {
+        self.u32(items.len() as u32);
+        items.iter().for_each(|item| item.encode(self));
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn fixed<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let bytes = self.take(N)?;
+
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.fixed::<1>()?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_be_bytes(self.fixed()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_be_bytes(self.fixed()?))
+    }
+
+    pub fn u128(&mut self) -> Result<u128, DecodeError> {
+        Ok(u128::from_be_bytes(self.fixed()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, DecodeError> {
+        let length = self.u32()? as usize;
+
+        Ok(self.take(length)?.to_vec())
+    }
+
+    pub fn string(&mut self) -> Result<String, DecodeError> {
+        String::from_utf8(self.bytes()?).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    pub fn sequence<T: Decode>(&mut self) -> Result<Vec<T>, DecodeError> {
+        let length = self.u32()? as usize;
+
+        // The length comes from the input, so it isn't trusted for the allocation
+        let mut items = Vec::with_capacity(length.min(self.bytes.len()));
+
+        for _ in 0..length {
+            items.push(T::decode(self)?);
+        }
+
+        Ok(items)
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < length {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod decoder {
+        use crate::week_1::encoding::{DecodeError, Decoder, Encoder};
+
+        #[test]
+        fn should_read_back_what_was_written() {
+            let mut encoder = Encoder::new();
+            encoder.u8(7);
+            encoder.u32(1);
+            encoder.u64(2);
+            encoder.u128(3);
+            encoder.string("Niapa");
+            encoder.fixed(&[9, 9]);
+
+            let bytes = encoder.into_bytes();
+            let mut decoder = Decoder::new(&bytes);
+
+            assert_eq!(decoder.u8(), Ok(7));
+            assert_eq!(decoder.u32(), Ok(1));
+            assert_eq!(decoder.u64(), Ok(2));
+            assert_eq!(decoder.u128(), Ok(3));
+            assert_eq!(decoder.string(), Ok(String::from("Niapa")));
+            assert_eq!(decoder.fixed::<2>(), Ok([9, 9]));
+            assert!(decoder.is_empty());
+        }
+
+        #[test]
+        fn should_prefix_variable_length_data_with_its_length() {
+            let mut encoder = Encoder::new();
+            encoder.string("AB");
+
+            assert_eq!(encoder.into_bytes(), vec![0, 0, 0, 2, b'A', b'B']);
+        }
+
+        #[test]
+        fn should_fail_on_truncated_input() {
+            let mut decoder = Decoder::new(&[0, 0, 0, 5, b'A']);
+
+            assert_eq!(decoder.string(), Err(DecodeError::UnexpectedEnd));
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use k256::ecdsa::SigningKey;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f64::consts::PI;
+
+use super::{
+    ledger::LedgerConfig,
+    proof_of_work::{signer_address, Block, DifficultyConfig, Miner, Transaction},
+    transaction_pool::PoolConfig,
+};
+
+// Fee Market Simulator
+//
+// Before every block a demand curve decides how many users show up. Each draws the most it is
+// willing to pay per unit of gas and a tip, and walks away if the base fee is already above it.
+// Everyone else sends a transfer from an idle account, so a transaction stuck below a rising base
+// fee keeps its account busy until the base fee comes back down. Blocks are really mined, and a
+// run only depends on its seed.
+
+// Accounts the users send from, enough that one is always idle under the usual demand
+const ACCOUNTS: usize = 256;
+// Genesis balance of every account, enough that synthetic transactions never overspend
+const ACCOUNT_BALANCE: u128 = 1_000_000_000_000;
+// Receives every synthetic transfer
+const RECIPIENT: &str = "recipient";
+// Seconds between two simulated blocks
+const BLOCK_TIME: u64 = 10;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DemandCurve {
+    // The same number of users before every block
+    Constant {
+        transactions: usize,
+    },
+    // Demand jumps from `before` to `after` users at the given block
+    Step {
+        before: usize,
+        after: usize,
+        at: usize,
+    },
+    // Demand swings around `mean` by up to `amplitude` users, over `period` blocks
+    Sine {
+        mean: usize,
+        amplitude: usize,
+        period: usize,
+    },
+}
+
+impl DemandCurve {
+    // Users showing up before the block with the given number
+    pub fn transactions_at(&self, block: usize) -> usize {
+        match *self {
+            DemandCurve::Constant { transactions } => transactions,
+            DemandCurve::Step { before, after, at } => {
+                if block < at {
+                    before
+                } else {
+                    after
+                }
+            }
+            DemandCurve::Sine {
+                mean,
+                amplitude,
+                period,
+            } => {
+                let phase = 2.0 * PI * block as f64 / period.max(1) as f64;
+
+                (mean as f64 + amplitude as f64 * phase.sin())
+                    .round()
+                    .max(0.0) as usize
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FeeMarketConfig {
+    pub seed: u64,
+    pub blocks: usize,
+    pub demand: DemandCurve,
+    // Users are willing to pay up to a max fee drawn between 1 and this, per unit of gas
+    pub max_fee: u128,
+    // Tips are drawn between 1 and this, and never above the user's max fee
+    pub max_priority_fee: u128,
+    // Gas target, limit and base fee parameters of the chain
+    pub ledger_config: LedgerConfig,
+}
+
+impl FeeMarketConfig {
+    pub fn new(seed: u64, demand: DemandCurve) -> Self {
+        Self {
+            seed,
+            blocks: 100,
+            demand,
+            max_fee: 1_000,
+            max_priority_fee: 10,
+            ledger_config: LedgerConfig {
+                initial_base_fee: 100,
+                ..LedgerConfig::new()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BlockFees {
+    pub base_fee: u128,
+    pub gas_used: u64,
+    pub transactions: usize,
+    pub burned: u128,
+    // Paid to the miner on top of the block reward
+    pub tips: u128,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct FeeMarketReport {
+    pub blocks: Vec<BlockFees>,
+    // Users that walked away because the base fee was above their max fee
+    pub priced_out: usize,
+    // Users that found no idle account to send from
+    pub unserved: usize,
+    // Transactions the pool refused
+    pub rejected: usize,
+    // Transactions still waiting when the run ended
+    pub pending: usize,
+    pub total_burned: u128,
+    pub total_tips: u128,
+    pub mean_base_fee: f64,
+    // Standard deviation of the relative base fee change from one block to the next
+    pub volatility: f64,
+}
+
+struct Account {
+    signing_key: SigningKey,
+    address: String,
+    next_nonce: u64,
+}
+
+pub struct FeeMarketSimulator {
+    config: FeeMarketConfig,
+    rng: StdRng,
+    accounts: Vec<Account>,
+    miner: Miner,
+}
+
+impl FeeMarketSimulator {
+    pub fn new(config: FeeMarketConfig) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+
+        let accounts: Vec<Account> = (0..ACCOUNTS)
+            .map(|_| {
+                let signing_key = SigningKey::random(&mut rng);
+
+                Account {
+                    address: signer_address(&signing_key),
+                    signing_key,
+                    next_nonce: 0,
+                }
+            })
+            .collect();
+
+        let mut ledger_config = config.ledger_config.clone();
+
+        for account in accounts.iter() {
+            ledger_config
+                .allocations
+                .insert(account.address.clone(), ACCOUNT_BALANCE);
+        }
+
+        // The difficulty stays fixed, only the fees are being studied
+        let difficulty = DifficultyConfig {
+            retarget_interval: usize::MAX,
+            ..DifficultyConfig::new()
+        };
+
+        Self {
+            miner: Miner::with_config(difficulty, PoolConfig::new(), ledger_config),
+            config,
+            rng,
+            accounts,
+        }
+    }
+
+    pub fn run(mut self) -> FeeMarketReport {
+        let mut priced_out = 0;
+        let mut unserved = 0;
+        let mut rejected = 0;
+
+        for height in 0..self.config.blocks {
+            let base_fee = self.miner.get_next_base_fee();
+            let mut idle = self.idle_accounts();
+
+            for _ in 0..self.config.demand.transactions_at(height) {
+                let max_fee = self.rng.gen_range(1..=self.config.max_fee.max(1));
+                let priority_fee = self
+                    .rng
+                    .gen_range(1..=self.config.max_priority_fee.max(1))
+                    .min(max_fee);
+
+                if max_fee < base_fee {
+                    priced_out += 1;
+                    continue;
+                }
+
+                if idle.is_empty() {
+                    unserved += 1;
+                    continue;
+                }
+
+                let account =
+                    &mut self.accounts[idle.swap_remove(self.rng.gen_range(0..idle.len()))];
+                let transaction = Transaction::sign_with_fees(
+                    &account.signing_key,
+                    String::from(RECIPIENT),
+                    1,
+                    max_fee,
+                    priority_fee,
+                    account.next_nonce,
+                );
+
+                // The account only moves on to its next nonce once the pool holds this one
+                match self
+                    .miner
+                    .add_transaction_at(transaction, height as u64 * BLOCK_TIME)
+                {
+                    Ok(()) => account.next_nonce += 1,
+                    Err(_) => rejected += 1,
+                }
+            }
+
+            self.miner.mine_at(height as u64 * BLOCK_TIME);
+        }
+
+        let blocks: Vec<BlockFees> = self.miner.get_blocks().iter().map(block_fees).collect();
+
+        FeeMarketReport {
+            priced_out,
+            unserved,
+            rejected,
+            pending: self.miner.get_mempool_size(),
+            total_burned: blocks.iter().map(|block| block.burned).sum(),
+            total_tips: blocks.iter().map(|block| block.tips).sum(),
+            mean_base_fee: blocks
+                .iter()
+                .map(|block| block.base_fee as f64)
+                .sum::<f64>()
+                / blocks.len().max(1) as f64,
+            volatility: volatility(&blocks),
+            blocks,
+        }
+    }
+
+    // Indexes of the accounts without a pending transaction
+    fn idle_accounts(&self) -> Vec<usize> {
+        (0..self.accounts.len())
+            .filter(|idx| {
+                let account = &self.accounts[*idx];
+
+                self.miner.get_nonce(&account.address) == account.next_nonce
+            })
+            .collect()
+    }
+}
+
+fn block_fees(block: &Block) -> BlockFees {
+    let tips = block
+        .transactions()
+        .iter()
+        .map(|transaction| {
+            transaction
+                .effective_tip(block.base_fee())
+                .unwrap_or_default()
+                .saturating_mul(transaction.gas() as u128)
+        })
+        .sum();
+
+    BlockFees {
+        base_fee: block.base_fee(),
+        gas_used: block.gas_used(),
+        transactions: block.transactions().len(),
+        burned: block.base_fee().saturating_mul(block.gas_used() as u128),
+        tips,
+    }
+}
+
+fn volatility(blocks: &[BlockFees]) -> f64 {
+    let changes: Vec<f64> = blocks
+        .windows(2)
+        .filter(|pair| pair[0].base_fee > 0)
+        .map(|pair| (pair[1].base_fee as f64 - pair[0].base_fee as f64) / pair[0].base_fee as f64)
+        .collect();
+
+    if changes.is_empty() {
+        return 0.0;
+    }
+
+    let mean = changes.iter().sum::<f64>() / changes.len() as f64;
+    let variance = changes
+        .iter()
+        .map(|change| (change - mean).powi(2))
+        .sum::<f64>()
+        / changes.len() as f64;
+
+    variance.sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod run {
+        use crate::week_1::fee_market::{
+            DemandCurve, FeeMarketConfig, FeeMarketReport, FeeMarketSimulator,
+        };
+
+        fn simulate(seed: u64, demand: DemandCurve) -> FeeMarketReport {
+            let mut config = FeeMarketConfig::new(seed, demand);
+            config.blocks = 40;
+
+            FeeMarketSimulator::new(config).run()
+        }
+
+        #[test]
+        fn should_be_reproducible_with_the_same_seed() {
+            let demand = DemandCurve::Constant { transactions: 6 };
+
+            assert_eq!(simulate(1, demand), simulate(1, demand));
+        }
+
+        #[test]
+        fn should_raise_the_base_fee_while_demand_is_above_the_target() {
+            let report = simulate(2, DemandCurve::Constant { transactions: 10 });
+
+            assert!(report.blocks.last().unwrap().base_fee > 100);
+            assert!(report.total_burned > 0);
+        }
+
+        #[test]
+        fn should_lower_the_base_fee_while_demand_is_below_the_target() {
+            let report = simulate(3, DemandCurve::Constant { transactions: 2 });
+
+            assert!(report.blocks.last().unwrap().base_fee < 100);
+            assert_eq!(report.pending, 0);
+            assert_eq!(report.rejected, 0);
+        }
+
+        #[test]
+        fn should_price_users_out_until_blocks_are_back_at_the_target() {
+            let report = simulate(4, DemandCurve::Constant { transactions: 20 });
+            let target = 5 * 21_000;
+
+            let settled = &report.blocks[30..];
+            let gas_used = settled.iter().map(|block| block.gas_used).sum::<u64>();
+
+            assert!(report.priced_out > 0);
+            assert!(gas_used.abs_diff(target * settled.len() as u64) < target * 2);
+        }
+
+        #[test]
+        fn should_follow_a_step_in_demand() {
+            let report = simulate(
+                5,
+                DemandCurve::Step {
+                    before: 2,
+                    after: 10,
+                    at: 20,
+                },
+            );
+
+            assert!(report.blocks[39].base_fee > report.blocks[20].base_fee);
+        }
+
+        #[test]
+        fn should_be_more_volatile_under_swinging_demand() {
+            let steady = simulate(6, DemandCurve::Constant { transactions: 5 });
+            let swinging = simulate(
+                6,
+                DemandCurve::Sine {
+                    mean: 5,
+                    amplitude: 5,
+                    period: 10,
+                },
+            );
+
+            assert!(swinging.volatility > steady.volatility);
+        }
+    }
+}
@@ -0,0 +1,875 @@
+use hmac::{Hmac, Mac};
+use k256::{
+    elliptic_curve::{
+        rand_core::{CryptoRng, RngCore},
+        sec1::ToEncodedPoint,
+        PrimeField,
+    },
+    ProjectivePoint, Scalar,
+};
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256, Sha512};
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
+
+use super::digital_signatures::{KeyError, Keypair, PublicKey};
+
+// Hierarchical Deterministic Wallets
+//
+// BIP-39 turns entropy into a phrase of words, the last of which carries a checksum, and stretches
+// the phrase into a 64 byte seed. BIP-32 turns that seed into a tree of keys where every child is
+// derived from its parent's key and chain code. Hardened children need the parent's private key,
+// the others can also be derived from its public key alone. BIP-44 fixes the path Ethereum
+// accounts live at, m/44'/60'/0'/0/index.
+
+const WORDLIST: &str = include_str!("bip39_english.txt");
+const SEED_ROUNDS: u32 = 2048;
+// Children from this index on are hardened, written with a ' in paths
+pub const HARDENED_OFFSET: u32 = 0x8000_0000;
+// Version bytes of mainnet extended keys, giving them their xprv and xpub prefixes
+const XPRV_VERSION: [u8; 4] = [0x04, 0x88, 0xad, 0xe4];
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+#[derive(PartialEq, Debug)]
+pub enum HdError {
+    InvalidEntropyLength(usize),
+    InvalidWordCount(usize),
+    UnknownWord(String),
+    InvalidChecksum,
+    InvalidPath(String),
+    HardenedFromPublicKey,
+    // Serialized keys store the depth in a byte, so a key at depth 255 has no children
+    DepthExceeded,
+    InvalidExtendedKey,
+    // The derived key is out of range, BIP-32 says to skip to the next index
+    InvalidChildKey(u32),
+    Key(KeyError),
+}
+
+impl From<KeyError> for HdError {
+    fn from(error: KeyError) -> Self {
+        HdError::Key(error)
+    }
+}
+
+fn wordlist() -> Vec<&'static str> {
+    WORDLIST.lines().collect()
+}
+
+// 12 to 24 words out of the English wordlist
+#[derive(Clone, PartialEq)]
+pub struct Mnemonic {
+    words: Vec<&'static str>,
+}
+
+impl Mnemonic {
+    pub fn random(
+        word_count: usize,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Self, HdError> {
+        if !matches!(word_count, 12 | 15 | 18 | 21 | 24) {
+            return Err(HdError::InvalidWordCount(word_count));
+        }
+
+        let mut entropy = vec![0; word_count / 3 * 4];
+        rng.fill_bytes(&mut entropy);
+
+        Mnemonic::from_entropy(&entropy)
+    }
+
+    // Every 11 bits of the entropy followed by the first bits of its hash pick a word
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, HdError> {
+        if !matches!(entropy.len(), 16 | 20 | 24 | 28 | 32) {
+            return Err(HdError::InvalidEntropyLength(entropy.len()));
+        }
+
+        let checksum = Sha256::digest(entropy);
+        let bit_count = entropy.len() * 8 + entropy.len() / 4;
+        let bit = |idx: usize| {
+            let byte = if idx < entropy.len() * 8 {
+                entropy[idx / 8]
+            } else {
+                checksum[idx / 8 - entropy.len()]
+            };
+
+            (byte >> (7 - idx % 8)) & 1
+        };
+
+        let wordlist = wordlist();
+        let words = (0..bit_count / 11)
+            .map(|word| {
+                let idx = (0..11).fold(0, |acc, offset| {
+                    (acc << 1) | bit(word * 11 + offset) as usize
+                });
+
+                wordlist[idx]
+            })
+            .collect();
+
+        Ok(Self { words })
+    }
+
+    // Fails on a word outside the wordlist or a checksum that doesn't match
+    pub fn from_phrase(phrase: &str) -> Result<Self, HdError> {
+        let wordlist = wordlist();
+        let indexes = phrase
+            .split_whitespace()
+            .map(|word| {
+                wordlist
+                    .binary_search(&word)
+                    .map_err(|_| HdError::UnknownWord(word.to_string()))
+            })
+            .collect::<Result<Vec<usize>, HdError>>()?;
+
+        if !matches!(indexes.len(), 12 | 15 | 18 | 21 | 24) {
+            return Err(HdError::InvalidWordCount(indexes.len()));
+        }
+
+        let mut entropy = vec![0u8; indexes.len() / 3 * 4];
+
+        for bit in 0..entropy.len() * 8 {
+            let word = indexes[bit / 11];
+
+            entropy[bit / 8] |= (((word >> (10 - bit % 11)) & 1) as u8) << (7 - bit % 8);
+        }
+
+        let mnemonic = Mnemonic::from_entropy(&entropy)?;
+
+        if mnemonic
+            .words
+            .iter()
+            .ne(indexes.iter().map(|idx| &wordlist[*idx]))
+        {
+            return Err(HdError::InvalidChecksum);
+        }
+
+        Ok(mnemonic)
+    }
+
+    pub fn phrase(&self) -> String {
+        self.words.join(" ")
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.words.len()
+    }
+
+    // PBKDF2-HMAC-SHA512 of the phrase salted with "mnemonic" and the passphrase. BIP-39 wants
+    // both NFKD normalized, which only changes passphrases outside of ASCII, so those have to be
+    // normalized by the caller.
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        let mut seed = [0; 64];
+
+        pbkdf2::pbkdf2::<Hmac<Sha512>>(
+            self.phrase().as_bytes(),
+            format!("mnemonic{}", passphrase).as_bytes(),
+            SEED_ROUNDS,
+            &mut seed,
+        );
+
+        seed
+    }
+
+    // Key at the given path of the wallet this phrase and passphrase stand for
+    pub fn derive_keypair(
+        &self,
+        passphrase: &str,
+        path: &DerivationPath,
+    ) -> Result<Keypair, HdError> {
+        Ok(ExtendedPrivateKey::from_seed(&self.to_seed(passphrase))?
+            .derive_path(path)?
+            .keypair())
+    }
+}
+
+// The phrase is as secret as the keys it derives
+impl Debug for Mnemonic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mnemonic")
+            .field("word_count", &self.words.len())
+            .finish_non_exhaustive()
+    }
+}
+
+// Child indexes from the master key down, e.g. m/44'/60'/0'/0/0
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    // BIP-44 path of the Ethereum account with the given index
+    pub fn ethereum(index: u32) -> Self {
+        DerivationPath(vec![
+            44 + HARDENED_OFFSET,
+            60 + HARDENED_OFFSET,
+            HARDENED_OFFSET,
+            0,
+            index,
+        ])
+    }
+
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+// Hardened indexes end with ' or h
+impl FromStr for DerivationPath {
+    type Err = HdError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let invalid = || HdError::InvalidPath(path.to_string());
+        let mut segments = path.split('/');
+
+        if segments.next() != Some("m") {
+            return Err(invalid());
+        }
+
+        segments
+            .map(|segment| {
+                let (index, offset) = match segment
+                    .strip_suffix('\'')
+                    .or_else(|| segment.strip_suffix('h'))
+                {
+                    Some(index) => (index, HARDENED_OFFSET),
+                    None => (segment, 0),
+                };
+
+                index
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|index| *index < HARDENED_OFFSET)
+                    .map(|index| index + offset)
+                    .ok_or_else(invalid)
+            })
+            .collect::<Result<Vec<u32>, HdError>>()
+            .map(DerivationPath)
+    }
+}
+
+impl Display for DerivationPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "m")?;
+
+        for index in self.0.iter() {
+            if *index >= HARDENED_OFFSET {
+                write!(f, "/{}'", index - HARDENED_OFFSET)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Where a key sits in the tree
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct KeyOrigin {
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+}
+
+#[derive(Clone)]
+pub struct ExtendedPrivateKey {
+    keypair: Keypair,
+    chain_code: [u8; 32],
+    origin: KeyOrigin,
+}
+
+impl ExtendedPrivateKey {
+    // Master key, the left half of HMAC-SHA512("Bitcoin seed", seed) with the right one as its
+    // chain code
+    pub fn from_seed(seed: &[u8]) -> Result<Self, HdError> {
+        let (key, chain_code) = hmac_sha512(b"Bitcoin seed", seed);
+
+        Ok(Self {
+            keypair: Keypair::from_bytes(&key)?,
+            chain_code,
+            origin: KeyOrigin {
+                depth: 0,
+                parent_fingerprint: [0; 4],
+                child_number: 0,
+            },
+        })
+    }
+
+    // Hardened children hash the private key, the others the public key, so the latter can also
+    // be derived from the extended public key
+    pub fn derive_child(&self, index: u32) -> Result<Self, HdError> {
+        let depth = self
+            .origin
+            .depth
+            .checked_add(1)
+            .ok_or(HdError::DepthExceeded)?;
+
+        let mut data = if index >= HARDENED_OFFSET {
+            [&[0][..], &self.keypair.to_bytes()].concat()
+        } else {
+            self.keypair.public_key().to_sec1_bytes(true)
+        };
+        data.extend(index.to_be_bytes());
+
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+        let tweak = parse_scalar(&tweak).ok_or(HdError::InvalidChildKey(index))?;
+        let parent = parse_scalar(&self.keypair.to_bytes()).expect("a private key is a scalar");
+
+        let keypair = Keypair::from_bytes(&(tweak + parent).to_bytes())
+            .map_err(|_| HdError::InvalidChildKey(index))?;
+
+        Ok(Self {
+            keypair,
+            chain_code,
+            origin: KeyOrigin {
+                depth,
+                parent_fingerprint: fingerprint(&self.keypair.public_key()),
+                child_number: index,
+            },
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, HdError> {
+        path.indexes()
+            .iter()
+            .try_fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    pub fn keypair(&self) -> Keypair {
+        self.keypair.clone()
+    }
+
+    pub fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.origin.depth
+    }
+
+    pub fn extended_public_key(&self) -> ExtendedPublicKey {
+        ExtendedPublicKey {
+            public_key: self.keypair.public_key(),
+            chain_code: self.chain_code,
+            origin: self.origin,
+        }
+    }
+
+    pub fn to_xprv(&self) -> String {
+        let key = [&[0][..], &self.keypair.to_bytes()].concat();
+
+        serialize(XPRV_VERSION, self.origin, &self.chain_code, &key)
+    }
+
+    pub fn from_xprv(xprv: &str) -> Result<Self, HdError> {
+        let (origin, chain_code, key) = deserialize(XPRV_VERSION, xprv)?;
+
+        if key[0] != 0 {
+            return Err(HdError::InvalidExtendedKey);
+        }
+
+        Ok(Self {
+            keypair: Keypair::from_bytes(&key[1..])?,
+            chain_code,
+            origin,
+        })
+    }
+}
+
+// Only the public half is printed
+impl Debug for ExtendedPrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtendedPrivateKey")
+            .field("address", &self.keypair.address())
+            .field("depth", &self.origin.depth)
+            .field("child_number", &self.origin.child_number)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExtendedPublicKey {
+    public_key: PublicKey,
+    chain_code: [u8; 32],
+    origin: KeyOrigin,
+}
+
+impl ExtendedPublicKey {
+    // Same child public key as the one of the private child, without knowing any private key
+    pub fn derive_child(&self, index: u32) -> Result<Self, HdError> {
+        if index >= HARDENED_OFFSET {
+            return Err(HdError::HardenedFromPublicKey);
+        }
+
+        let depth = self
+            .origin
+            .depth
+            .checked_add(1)
+            .ok_or(HdError::DepthExceeded)?;
+
+        let mut data = self.public_key.to_sec1_bytes(true);
+        data.extend(index.to_be_bytes());
+
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+        let tweak = parse_scalar(&tweak).ok_or(HdError::InvalidChildKey(index))?;
+        let parent = k256::PublicKey::from(self.public_key.verifying_key()).to_projective();
+
+        let child = (ProjectivePoint::GENERATOR * tweak + parent).to_affine();
+        let public_key = PublicKey::from_sec1_bytes(child.to_encoded_point(true).as_bytes())
+            .map_err(|_| HdError::InvalidChildKey(index))?;
+
+        Ok(Self {
+            public_key,
+            chain_code,
+            origin: KeyOrigin {
+                depth,
+                parent_fingerprint: fingerprint(&self.public_key),
+                child_number: index,
+            },
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, HdError> {
+        path.indexes()
+            .iter()
+            .try_fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.origin.depth
+    }
+
+    pub fn to_xpub(&self) -> String {
+        serialize(
+            XPUB_VERSION,
+            self.origin,
+            &self.chain_code,
+            &self.public_key.to_sec1_bytes(true),
+        )
+    }
+
+    pub fn from_xpub(xpub: &str) -> Result<Self, HdError> {
+        let (origin, chain_code, key) = deserialize(XPUB_VERSION, xpub)?;
+
+        Ok(Self {
+            public_key: PublicKey::from_sec1_bytes(&key)?,
+            chain_code,
+            origin,
+        })
+    }
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+
+    let output = mac.finalize().into_bytes();
+
+    (
+        output[..32].try_into().unwrap(),
+        output[32..].try_into().unwrap(),
+    )
+}
+
+// None if the bytes are not below the order of the curve
+fn parse_scalar(bytes: &[u8; 32]) -> Option<Scalar> {
+    Option::from(Scalar::from_repr((*bytes).into()))
+}
+
+// First 4 bytes of RIPEMD160(SHA256(compressed public key))
+fn fingerprint(public_key: &PublicKey) -> [u8; 4] {
+    let hash = Ripemd160::digest(Sha256::digest(public_key.to_sec1_bytes(true)));
+
+    hash[..4].try_into().unwrap()
+}
+
+// version || depth || parent fingerprint || child number || chain code || key, in Base58Check
+fn serialize(version: [u8; 4], origin: KeyOrigin, chain_code: &[u8; 32], key: &[u8]) -> String {
+    let mut payload = version.to_vec();
+    payload.push(origin.depth);
+    payload.extend(origin.parent_fingerprint);
+    payload.extend(origin.child_number.to_be_bytes());
+    payload.extend(chain_code);
+    payload.extend(key);
+
+    base58check_encode(&payload)
+}
+
+fn deserialize(version: [u8; 4], encoded: &str) -> Result<(KeyOrigin, [u8; 32], Vec<u8>), HdError> {
+    let payload = base58check_decode(encoded).ok_or(HdError::InvalidExtendedKey)?;
+
+    if payload.len() != 78 || payload[..4] != version {
+        return Err(HdError::InvalidExtendedKey);
+    }
+
+    let origin = KeyOrigin {
+        depth: payload[4],
+        parent_fingerprint: payload[5..9].try_into().unwrap(),
+        child_number: u32::from_be_bytes(payload[9..13].try_into().unwrap()),
+    };
+
+    Ok((
+        origin,
+        payload[13..45].try_into().unwrap(),
+        payload[45..].to_vec(),
+    ))
+}
+
+// The payload followed by the first 4 bytes of its double SHA-256, in base 58 with a 1 for every
+// leading zero byte
+fn base58check_encode(payload: &[u8]) -> String {
+    let mut data = payload.to_vec();
+    data.extend(&Sha256::digest(Sha256::digest(payload))[..4]);
+
+    // Base 58 digits, least significant first
+    let mut digits: Vec<u8> = Vec::new();
+
+    for byte in data.iter() {
+        let mut carry = *byte as u32;
+
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let zeros = data.iter().take_while(|byte| **byte == 0).count();
+
+    let mut encoded = "1".repeat(zeros);
+    encoded.extend(
+        digits
+            .iter()
+            .rev()
+            .map(|digit| BASE58_ALPHABET[*digit as usize] as char),
+    );
+
+    encoded
+}
+
+fn base58check_decode(encoded: &str) -> Option<Vec<u8>> {
+    // Bytes, least significant first
+    let mut bytes: Vec<u8> = Vec::new();
+
+    for char in encoded.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|digit| *digit == char)? as u32;
+
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let zeros = encoded.bytes().take_while(|char| *char == b'1').count();
+    let mut data = vec![0; zeros];
+    data.extend(bytes.iter().rev());
+
+    if data.len() < 4 {
+        return None;
+    }
+
+    let (payload, checksum) = data.split_at(data.len() - 4);
+
+    (Sha256::digest(Sha256::digest(payload))[..4] == *checksum).then(|| payload.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    mod mnemonic {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        use crate::week_1::hd_wallet::{HdError, Mnemonic};
+
+        #[test]
+        fn should_match_the_bip39_test_vectors() {
+            let vectors = [
+                (
+                    "00000000000000000000000000000000",
+                    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+                    "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+                ),
+                (
+                    "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                    "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                    "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+                ),
+                (
+                    "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                    "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+                    "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e1613912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad",
+                ),
+            ];
+
+            for (entropy, phrase, seed) in vectors {
+                let mnemonic = Mnemonic::from_entropy(&hex::decode(entropy).unwrap()).unwrap();
+
+                assert_eq!(mnemonic.phrase(), phrase);
+                assert_eq!(Mnemonic::from_phrase(phrase), Ok(mnemonic.clone()));
+                assert_eq!(hex::encode(mnemonic.to_seed("TREZOR")), seed);
+            }
+        }
+
+        #[test]
+        fn should_reject_an_invalid_phrase() {
+            assert_eq!(
+                Mnemonic::from_phrase(
+                    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"
+                ),
+                Err(HdError::InvalidChecksum)
+            );
+            assert_eq!(
+                Mnemonic::from_phrase(
+                    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abcde"
+                ),
+                Err(HdError::UnknownWord(String::from("abcde")))
+            );
+            assert_eq!(
+                Mnemonic::from_phrase("abandon about"),
+                Err(HdError::InvalidWordCount(2))
+            );
+        }
+
+        #[test]
+        fn should_generate_valid_phrases() {
+            let mut rng = StdRng::seed_from_u64(1);
+
+            for word_count in [12, 15, 18, 21, 24] {
+                let mnemonic = Mnemonic::random(word_count, &mut rng).unwrap();
+
+                assert_eq!(mnemonic.word_count(), word_count);
+                assert_eq!(Mnemonic::from_phrase(&mnemonic.phrase()), Ok(mnemonic));
+            }
+
+            assert_eq!(
+                Mnemonic::random(13, &mut rng),
+                Err(HdError::InvalidWordCount(13))
+            );
+        }
+    }
+
+    mod derivation_path {
+        use crate::week_1::hd_wallet::{DerivationPath, HdError, HARDENED_OFFSET};
+
+        #[test]
+        fn should_parse_hardened_and_normal_indexes() {
+            let path: DerivationPath = "m/44'/60h/0'/0/7".parse().unwrap();
+
+            assert_eq!(
+                path.indexes(),
+                [
+                    44 + HARDENED_OFFSET,
+                    60 + HARDENED_OFFSET,
+                    HARDENED_OFFSET,
+                    0,
+                    7
+                ]
+            );
+            assert_eq!(path, DerivationPath::ethereum(7));
+            assert_eq!(path.to_string(), "m/44'/60'/0'/0/7");
+            assert!("m".parse::<DerivationPath>().unwrap().indexes().is_empty());
+        }
+
+        #[test]
+        fn should_reject_a_malformed_path() {
+            for path in ["44'/60'", "m/-1", "m/2147483648", "m/0''", "m//0"] {
+                assert_eq!(
+                    path.parse::<DerivationPath>(),
+                    Err(HdError::InvalidPath(path.to_string()))
+                );
+            }
+        }
+    }
+
+    mod extended_key {
+        use crate::week_1::hd_wallet::{
+            DerivationPath, ExtendedPrivateKey, ExtendedPublicKey, HdError, HARDENED_OFFSET,
+        };
+
+        // Test vector 1 of BIP-32
+        const SEED: &str = "000102030405060708090a0b0c0d0e0f";
+
+        #[test]
+        fn should_match_the_bip32_test_vector() {
+            let master = ExtendedPrivateKey::from_seed(&hex::decode(SEED).unwrap()).unwrap();
+            let vectors = [
+                (
+                    "m",
+                    "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi",
+                    "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+                ),
+                (
+                    "m/0'",
+                    "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7",
+                    "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+                ),
+                (
+                    "m/0'/1",
+                    "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs",
+                    "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ",
+                ),
+                (
+                    "m/0'/1/2'/2/1000000000",
+                    "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76",
+                    "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy",
+                ),
+            ];
+
+            for (path, xprv, xpub) in vectors {
+                let key = master.derive_path(&path.parse().unwrap()).unwrap();
+
+                assert_eq!(key.to_xprv(), xprv);
+                assert_eq!(key.extended_public_key().to_xpub(), xpub);
+            }
+        }
+
+        #[test]
+        fn should_round_trip_extended_keys() {
+            let master = ExtendedPrivateKey::from_seed(&hex::decode(SEED).unwrap()).unwrap();
+            let key = master.derive_path(&DerivationPath::ethereum(3)).unwrap();
+
+            let xprv = ExtendedPrivateKey::from_xprv(&key.to_xprv()).unwrap();
+            let xpub = ExtendedPublicKey::from_xpub(&key.extended_public_key().to_xpub()).unwrap();
+
+            assert_eq!(xprv.to_xprv(), key.to_xprv());
+            assert_eq!(xprv.depth(), 5);
+            assert_eq!(xpub, key.extended_public_key());
+        }
+
+        #[test]
+        fn should_derive_normal_children_from_the_public_key_alone() {
+            let master = ExtendedPrivateKey::from_seed(&hex::decode(SEED).unwrap()).unwrap();
+            let account = master
+                .derive_path(&"m/44'/60'/0'".parse().unwrap())
+                .unwrap();
+            let path: DerivationPath = "m/0/5".parse().unwrap();
+
+            assert_eq!(
+                account.extended_public_key().derive_path(&path),
+                Ok(account.derive_path(&path).unwrap().extended_public_key())
+            );
+            assert_eq!(
+                account.extended_public_key().derive_child(HARDENED_OFFSET),
+                Err(HdError::HardenedFromPublicKey)
+            );
+        }
+
+        #[test]
+        fn should_refuse_to_derive_past_the_maximum_depth() {
+            let mut key = ExtendedPrivateKey::from_seed(&hex::decode(SEED).unwrap()).unwrap();
+            key.origin.depth = u8::MAX - 1;
+
+            let deepest = key.derive_child(0).unwrap();
+
+            assert_eq!(deepest.depth(), u8::MAX);
+            assert!(matches!(
+                deepest.derive_child(0),
+                Err(HdError::DepthExceeded)
+            ));
+            assert_eq!(
+                deepest.extended_public_key().derive_child(0),
+                Err(HdError::DepthExceeded)
+            );
+        }
+
+        #[test]
+        fn should_reject_a_corrupted_extended_key() {
+            let xpub = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet9";
+
+            assert_eq!(
+                ExtendedPublicKey::from_xpub(xpub),
+                Err(HdError::InvalidExtendedKey)
+            );
+            assert!(matches!(
+                ExtendedPrivateKey::from_xprv(&xpub[..xpub.len() - 1]),
+                Err(HdError::InvalidExtendedKey)
+            ));
+        }
+    }
+
+    mod hardhat_accounts {
+        use ethers::signers::{coins_bip39::English, MnemonicBuilder, Signer};
+
+        use crate::week_1::{
+            digital_signatures::Address,
+            hd_wallet::{DerivationPath, Mnemonic},
+        };
+
+        const MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+        #[test]
+        fn should_derive_the_hardhat_default_accounts() {
+            let addresses = [
+                "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266",
+                "0x70997970C51812dc3A010C7d01b50e0d17dc79C8",
+                "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC",
+                "0x90F79bf6EB2c4f870365E785982E1f101E93b906",
+                "0x15d34AAf54267DB7D7c367839AAf71A00a2C6A65",
+                "0x9965507D1a55bcC2695C58ba16FB37d819B0A4dc",
+                "0x976EA74026E726554dB657fA54763abd0C3a0aa9",
+                "0x14dC79964da2C08b23698B3D3cc7Ca32193d9955",
+                "0x23618e81E3f5cdF7f54C3d65f7FBc0aBf5B21E8f",
+                "0xa0Ee7A142d267C1f36714E4a8F75612F20a79720",
+            ];
+            let mnemonic = Mnemonic::from_phrase(MNEMONIC).unwrap();
+
+            for (index, address) in addresses.iter().enumerate() {
+                let keypair = mnemonic
+                    .derive_keypair("", &DerivationPath::ethereum(index as u32))
+                    .unwrap();
+
+                assert_eq!(keypair.address().to_string(), *address);
+            }
+
+            assert_eq!(
+                mnemonic
+                    .derive_keypair("", &DerivationPath::ethereum(0))
+                    .unwrap()
+                    .to_hex(),
+                "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+            );
+        }
+
+        #[test]
+        fn should_derive_like_ethers_with_a_passphrase() {
+            let mnemonic = Mnemonic::from_phrase(MNEMONIC).unwrap();
+            let path = DerivationPath::ethereum(4);
+
+            let wallet = MnemonicBuilder::<English>::default()
+                .phrase(MNEMONIC)
+                .password("bootcamp")
+                .derivation_path(&path.to_string())
+                .unwrap()
+                .build()
+                .unwrap();
+
+            assert_eq!(
+                mnemonic
+                    .derive_keypair("bootcamp", &path)
+                    .unwrap()
+                    .address(),
+                Address::from(wallet.address().0)
+            );
+        }
+    }
+}
@@ -0,0 +1,645 @@
+use aes::Aes128;
+use ctr::{
+    cipher::{KeyIvInit, StreamCipher},
+    Ctr128BE,
+};
+use hmac::Hmac;
+use k256::elliptic_curve::rand_core::{CryptoRng, RngCore};
+use pbkdf2::pbkdf2;
+use salsa20::{
+    cipher::{typenum::U4, StreamCipherCore},
+    SalsaCore,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{fs, path::Path};
+
+use super::digital_signatures::{hash_message, KeyError, Keypair};
+
+// Web3 Secret Storage v3
+//
+// The password is stretched into a 32 byte key with scrypt or PBKDF2. Its first half encrypts the
+// private key with AES-128-CTR, and its second half is hashed along with the ciphertext into a
+// MAC, so a wrong password is told apart from a corrupted file before anything is decrypted.
+
+const VERSION: u8 = 3;
+const CIPHER: &str = "aes-128-ctr";
+const PRF: &str = "hmac-sha256";
+const DERIVED_KEY_LENGTH: usize = 32;
+
+// Limits on the work a keystore file can ask for. Memory is capped at the 256 MiB geth's standard
+// parameters need (n = 2^18, r = 8), and the rest is well above what geth and ethers write, so a
+// crafted file can't make decryption allocate more than that or spin forever.
+const MAX_SCRYPT_N: u64 = 1 << 20;
+const MAX_SCRYPT_R: u32 = 32;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_SCRYPT_MEMORY: u64 = 1 << 28;
+const MAX_SCRYPT_WORK: u64 = 1 << 24;
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Io(std::io::Error),
+    InvalidJson(String),
+    UnsupportedVersion(u8),
+    UnsupportedCipher(String),
+    UnsupportedKdf(String),
+    InvalidKdfParams,
+    MacMismatch,
+    Key(KeyError),
+}
+
+impl From<std::io::Error> for KeystoreError {
+    fn from(error: std::io::Error) -> Self {
+        KeystoreError::Io(error)
+    }
+}
+
+impl From<KeyError> for KeystoreError {
+    fn from(error: KeyError) -> Self {
+        KeystoreError::Key(error)
+    }
+}
+
+// How the password is stretched into the encryption key
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kdf {
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    Pbkdf2 { iterations: u32 },
+}
+
+impl Kdf {
+    // The parameters ethers and most wallets write, light enough to decrypt in a fraction of a
+    // second
+    pub fn scrypt() -> Self {
+        Kdf::Scrypt {
+            log_n: 13,
+            r: 8,
+            p: 1,
+        }
+    }
+
+    pub fn pbkdf2() -> Self {
+        Kdf::Pbkdf2 {
+            iterations: 262_144,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Keystore {
+    // Some clients capitalize it
+    #[serde(alias = "Crypto")]
+    pub crypto: KeystoreCrypto,
+    pub id: String,
+    pub version: u8,
+    // Lowercase hex without the 0x prefix, informative only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct KeystoreCrypto {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    #[serde(with = "hex")]
+    pub ciphertext: Vec<u8>,
+    pub kdf: String,
+    pub kdfparams: KdfParams,
+    #[serde(with = "hex")]
+    pub mac: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CipherParams {
+    #[serde(with = "hex")]
+    pub iv: Vec<u8>,
+}
+
+// Told apart by their fields, `kdf` names which one is expected
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KdfParams {
+    Scrypt {
+        dklen: usize,
+        n: u64,
+        r: u32,
+        p: u32,
+        #[serde(with = "hex")]
+        salt: Vec<u8>,
+    },
+    Pbkdf2 {
+        c: u32,
+        dklen: usize,
+        prf: String,
+        #[serde(with = "hex")]
+        salt: Vec<u8>,
+    },
+}
+
+impl KdfParams {
+    fn name(&self) -> &'static str {
+        match self {
+            KdfParams::Scrypt { .. } => "scrypt",
+            KdfParams::Pbkdf2 { .. } => "pbkdf2",
+        }
+    }
+
+    fn derive_key(&self, password: &str) -> Result<Vec<u8>, KeystoreError> {
+        match self {
+            KdfParams::Scrypt {
+                dklen,
+                n,
+                r,
+                p,
+                salt,
+            } => {
+                let (n, r, p) = (*n, *r as u64, *p as u64);
+
+                if *dklen != DERIVED_KEY_LENGTH
+                    || !n.is_power_of_two()
+                    || !(2..=MAX_SCRYPT_N).contains(&n)
+                    || !(1..=MAX_SCRYPT_R as u64).contains(&r)
+                    || !(1..=MAX_SCRYPT_P as u64).contains(&p)
+                    || 128 * r * n > MAX_SCRYPT_MEMORY
+                    || n * r * p > MAX_SCRYPT_WORK
+                {
+                    return Err(KeystoreError::InvalidKdfParams);
+                }
+
+                let mut key = vec![0; *dklen];
+
+                scrypt(
+                    password.as_bytes(),
+                    salt,
+                    n as usize,
+                    r as usize,
+                    p as usize,
+                    &mut key,
+                );
+
+                Ok(key)
+            }
+            KdfParams::Pbkdf2 {
+                c,
+                dklen,
+                prf,
+                salt,
+            } => {
+                if prf != PRF {
+                    return Err(KeystoreError::UnsupportedKdf(prf.clone()));
+                }
+
+                if *dklen != DERIVED_KEY_LENGTH || !(1..=MAX_PBKDF2_ITERATIONS).contains(c) {
+                    return Err(KeystoreError::InvalidKdfParams);
+                }
+
+                let mut key = vec![0; *dklen];
+
+                pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, *c, &mut key);
+
+                Ok(key)
+            }
+        }
+    }
+}
+
+impl Keystore {
+    pub fn encrypt(
+        keypair: &Keypair,
+        password: &str,
+        kdf: Kdf,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> Result<Self, KeystoreError> {
+        let mut salt = vec![0; 32];
+        let mut iv = vec![0; 16];
+        let mut id = [0; 16];
+
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut iv);
+        rng.fill_bytes(&mut id);
+
+        let kdfparams = match kdf {
+            Kdf::Scrypt { log_n, r, p } => KdfParams::Scrypt {
+                dklen: DERIVED_KEY_LENGTH,
+                n: 1_u64.checked_shl(log_n as u32).unwrap_or_default(),
+                r,
+                p,
+                salt,
+            },
+            Kdf::Pbkdf2 { iterations } => KdfParams::Pbkdf2 {
+                c: iterations,
+                dklen: DERIVED_KEY_LENGTH,
+                prf: String::from(PRF),
+                salt,
+            },
+        };
+
+        let key = kdfparams.derive_key(password)?;
+        let mut ciphertext = keypair.to_bytes().to_vec();
+        apply_keystream(&key, &iv, &mut ciphertext);
+
+        Ok(Self {
+            crypto: KeystoreCrypto {
+                cipher: String::from(CIPHER),
+                cipherparams: CipherParams { iv },
+                mac: mac(&key, &ciphertext),
+                ciphertext,
+                kdf: String::from(kdfparams.name()),
+                kdfparams,
+            },
+            id: uuid_v4(id),
+            version: VERSION,
+            address: Some(keypair.address().to_hex()),
+        })
+    }
+
+    // Checks the MAC before decrypting, so a wrong password fails with MacMismatch
+    pub fn decrypt(&self, password: &str) -> Result<Keypair, KeystoreError> {
+        if self.version != VERSION {
+            return Err(KeystoreError::UnsupportedVersion(self.version));
+        }
+
+        let crypto = &self.crypto;
+
+        if crypto.cipher != CIPHER {
+            return Err(KeystoreError::UnsupportedCipher(crypto.cipher.clone()));
+        }
+
+        if crypto.kdf != crypto.kdfparams.name() {
+            return Err(KeystoreError::UnsupportedKdf(crypto.kdf.clone()));
+        }
+
+        if crypto.cipherparams.iv.len() != 16 {
+            return Err(KeystoreError::InvalidJson(String::from(
+                "the iv has to be 16 bytes long",
+            )));
+        }
+
+        let key = crypto.kdfparams.derive_key(password)?;
+
+        // Compares every byte, so the time taken doesn't tell how much of the MAC matched
+        let difference = mac(&key, &crypto.ciphertext)
+            .iter()
+            .zip(crypto.mac.iter())
+            .fold((crypto.mac.len() != 32) as u8, |acc, (a, b)| acc | (a ^ b));
+
+        if difference != 0 {
+            return Err(KeystoreError::MacMismatch);
+        }
+
+        let mut private_key = crypto.ciphertext.clone();
+        apply_keystream(&key, &crypto.cipherparams.iv, &mut private_key);
+
+        Ok(Keypair::from_bytes(&private_key)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, KeystoreError> {
+        serde_json::from_str(json).map_err(|error| KeystoreError::InvalidJson(error.to_string()))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a keystore always serializes")
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, KeystoreError> {
+        Keystore::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), KeystoreError> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+}
+
+// scrypt as defined by RFC 7914, except that N isn't required to be below 2^(16 r). The Web3 Secret
+// Storage test vector uses r = 1 with N = 2^18, which geth decrypts and the scrypt crate refuses.
+fn scrypt(password: &[u8], salt: &[u8], n: usize, r: usize, p: usize, output: &mut [u8]) {
+    let mut bytes = vec![0; p * 128 * r];
+
+    pbkdf2::<Hmac<Sha256>>(password, salt, 1, &mut bytes);
+
+    // Mixed as little endian words
+    let block_length = 32 * r;
+    let mut blocks: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    let mut v = vec![0; n * block_length];
+    let mut t = vec![0; block_length];
+
+    for block in blocks.chunks_mut(block_length) {
+        ro_mix(block, &mut v, &mut t, n);
+    }
+
+    for (word, bytes) in blocks.iter().zip(bytes.chunks_exact_mut(4)) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+
+    pbkdf2::<Hmac<Sha256>>(password, &bytes, 1, output);
+}
+
+fn ro_mix(block: &mut [u32], v: &mut [u32], t: &mut [u32], n: usize) {
+    let length = block.len();
+
+    for chunk in v.chunks_mut(length) {
+        chunk.copy_from_slice(block);
+        block_mix(chunk, block);
+    }
+
+    for _ in 0..n {
+        // Integerify: the first word of the last 64 byte chunk, modulo the power of two n
+        let j = block[length - 16] as usize & (n - 1);
+
+        for ((t, a), b) in t.iter_mut().zip(block.iter()).zip(&v[j * length..]) {
+            *t = a ^ b;
+        }
+
+        block_mix(t, block);
+    }
+}
+
+// Salsa20/8 over each 64 byte chunk, with the even outputs written to the first half of the
+// output and the odd ones to the second half
+fn block_mix(input: &[u32], output: &mut [u32]) {
+    let mut x = [0; 16];
+    let mut keystream = [0; 64];
+
+    x.copy_from_slice(&input[input.len() - 16..]);
+
+    for (i, chunk) in input.chunks(16).enumerate() {
+        for (x, word) in x.iter_mut().zip(chunk) {
+            *x ^= word;
+        }
+
+        SalsaCore::<U4>::from_raw_state(x).write_keystream_block((&mut keystream).into());
+
+        for (x, bytes) in x.iter_mut().zip(keystream.chunks_exact(4)) {
+            *x = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+
+        let position = (i / 2) * 16 + (i % 2) * (input.len() / 2);
+
+        output[position..position + 16].copy_from_slice(&x);
+    }
+}
+
+// Encryption and decryption are the same XOR with the AES-128-CTR keystream
+fn apply_keystream(key: &[u8], iv: &[u8], data: &mut [u8]) {
+    let mut cipher = Ctr128BE::<Aes128>::new(key[..16].into(), iv.into());
+
+    cipher.apply_keystream(data);
+}
+
+// keccak256 of the second half of the derived key followed by the ciphertext
+fn mac(key: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    hash_message([&key[16..32], ciphertext].concat())
+}
+
+// Random UUID, with the version and variant bits set
+fn uuid_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = hex::encode(bytes);
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    const PASSWORD: &str = "testpassword";
+    const PRIVATE_KEY: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    pub fn keystore_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("keystore-{}-{}", std::process::id(), name))
+    }
+
+    mod decrypt {
+        use crate::week_1::keystore::{KdfParams, Keystore, KeystoreError};
+
+        // Test vectors of the Web3 Secret Storage definition
+        const PBKDF2: &str = r#"{
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+                "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                "kdf": "pbkdf2",
+                "kdfparams": {
+                    "c": 262144,
+                    "dklen": 32,
+                    "prf": "hmac-sha256",
+                    "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                },
+                "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+            },
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3
+        }"#;
+
+        const SCRYPT: &str = r#"{
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+                "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+                "kdf": "scrypt",
+                "kdfparams": {
+                    "dklen": 32,
+                    "n": 262144,
+                    "p": 8,
+                    "r": 1,
+                    "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+                },
+                "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+            },
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3
+        }"#;
+
+        #[test]
+        fn should_decrypt_the_pbkdf2_test_vector() {
+            let keystore = Keystore::from_json(PBKDF2).unwrap();
+
+            let keypair = keystore.decrypt(super::PASSWORD).unwrap();
+
+            assert_eq!(keypair.to_hex(), super::PRIVATE_KEY);
+        }
+
+        // RFC 7914 requires N to be below 2^(16 r), which the definition's scrypt vector doesn't
+        // respect. Geth decrypts it anyway, and so should we.
+        #[test]
+        fn should_decrypt_the_scrypt_test_vector() {
+            let keystore = Keystore::from_json(SCRYPT).unwrap();
+
+            let keypair = keystore.decrypt(super::PASSWORD).unwrap();
+
+            assert_eq!(keypair.to_hex(), super::PRIVATE_KEY);
+        }
+
+        #[test]
+        fn should_refuse_scrypt_parameters_above_the_limits() {
+            let params = [
+                (1 << 30, 8, 1),
+                (1 << 18, 1 << 20, 1),
+                (1 << 18, 1, 1 << 20),
+                (1 << 20, 32, 1),
+                (1 << 20, 8, 16),
+                (1 << 19, 8, 1),
+            ];
+
+            for (n, r, p) in params {
+                let mut keystore = Keystore::from_json(SCRYPT).unwrap();
+
+                if let KdfParams::Scrypt {
+                    n: kdf_n,
+                    r: kdf_r,
+                    p: kdf_p,
+                    ..
+                } = &mut keystore.crypto.kdfparams
+                {
+                    (*kdf_n, *kdf_r, *kdf_p) = (n, r, p);
+                }
+
+                assert!(matches!(
+                    keystore.decrypt(super::PASSWORD),
+                    Err(KeystoreError::InvalidKdfParams)
+                ));
+            }
+        }
+
+        #[test]
+        fn should_refuse_pbkdf2_iterations_above_the_limit() {
+            let mut keystore = Keystore::from_json(PBKDF2).unwrap();
+
+            if let KdfParams::Pbkdf2 { c, .. } = &mut keystore.crypto.kdfparams {
+                *c = u32::MAX;
+            }
+
+            assert!(matches!(
+                keystore.decrypt(super::PASSWORD),
+                Err(KeystoreError::InvalidKdfParams)
+            ));
+        }
+
+        #[test]
+        fn should_reject_a_wrong_password() {
+            let keystore = Keystore::from_json(PBKDF2).unwrap();
+
+            assert!(matches!(
+                keystore.decrypt("wrongpassword"),
+                Err(KeystoreError::MacMismatch)
+            ));
+        }
+
+        #[test]
+        fn should_reject_a_tampered_ciphertext() {
+            let mut keystore = Keystore::from_json(PBKDF2).unwrap();
+            keystore.crypto.ciphertext[0] ^= 1;
+
+            assert!(matches!(
+                keystore.decrypt(super::PASSWORD),
+                Err(KeystoreError::MacMismatch)
+            ));
+        }
+
+        #[test]
+        fn should_reject_an_unsupported_cipher() {
+            let mut keystore = Keystore::from_json(PBKDF2).unwrap();
+            keystore.crypto.cipher = String::from("aes-128-cbc");
+
+            assert!(matches!(
+                keystore.decrypt(super::PASSWORD),
+                Err(KeystoreError::UnsupportedCipher(cipher)) if cipher == "aes-128-cbc"
+            ));
+        }
+    }
+
+    mod encrypt {
+        use ethers::signers::{LocalWallet, Signer};
+        use rand::{rngs::StdRng, SeedableRng};
+        use std::fs;
+
+        use super::keystore_path;
+        use crate::week_1::{
+            digital_signatures::{Address, Keypair},
+            keystore::{Kdf, Keystore},
+        };
+
+        #[test]
+        fn should_round_trip_with_either_kdf() {
+            let keypair = Keypair::from_hex(super::PRIVATE_KEY).unwrap();
+            let kdfs = [Kdf::scrypt(), Kdf::Pbkdf2 { iterations: 1_024 }];
+
+            for kdf in kdfs {
+                let keystore = Keystore::encrypt(
+                    &keypair,
+                    super::PASSWORD,
+                    kdf,
+                    &mut StdRng::seed_from_u64(1),
+                )
+                .unwrap();
+                let parsed = Keystore::from_json(&keystore.to_json()).unwrap();
+
+                assert_eq!(parsed, keystore);
+                assert_eq!(parsed.address, Some(keypair.address().to_hex()));
+                assert_eq!(
+                    parsed.decrypt(super::PASSWORD).unwrap().to_hex(),
+                    super::PRIVATE_KEY
+                );
+            }
+        }
+
+        #[test]
+        fn should_write_a_file_ethers_can_decrypt() {
+            let path = keystore_path("to-ethers.json");
+            let keypair = Keypair::from_seed(1);
+
+            Keystore::encrypt(
+                &keypair,
+                super::PASSWORD,
+                Kdf::scrypt(),
+                &mut StdRng::seed_from_u64(2),
+            )
+            .unwrap()
+            .write(&path)
+            .unwrap();
+
+            let wallet = LocalWallet::decrypt_keystore(&path, super::PASSWORD).unwrap();
+
+            assert_eq!(Address::from(wallet.address().0), keypair.address());
+
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn should_read_a_file_written_by_ethers() {
+            let dir = keystore_path("from-ethers");
+            fs::create_dir_all(&dir).unwrap();
+
+            let (wallet, name) = LocalWallet::new_keystore(
+                &dir,
+                &mut StdRng::seed_from_u64(3),
+                super::PASSWORD,
+                None,
+            )
+            .unwrap();
+
+            let keypair = Keystore::read(dir.join(name))
+                .unwrap()
+                .decrypt(super::PASSWORD)
+                .unwrap();
+
+            assert_eq!(keypair.address(), Address::from(wallet.address().0));
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
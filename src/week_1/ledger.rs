@@ -0,0 +1,301 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+};
+
+use super::proof_of_work::{Transaction, TransactionError, TRANSFER_GAS};
+
+// Account Ledger
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct Account {
+    pub balance: u128,
+    // Nonce the account's next transaction has to use
+    pub nonce: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LedgerConfig {
+    // Newly minted coins paid to the beneficiary of every block, on top of the tips
+    pub block_reward: u128,
+    // Balances the chain starts with
+    pub allocations: BTreeMap<String, u128>,
+    // Gas a block aims to use, the base fee rises above it and falls below it
+    pub gas_target: u64,
+    // Most gas a block can use
+    pub gas_limit: u64,
+    // Base fee of the first block
+    pub initial_base_fee: u128,
+    // The base fee moves by at most 1 / denominator of itself from one block to the next
+    pub base_fee_max_change_denominator: u128,
+}
+
+impl LedgerConfig {
+    pub fn new() -> Self {
+        Self {
+            block_reward: 50,
+            allocations: BTreeMap::new(),
+            gas_target: 5 * TRANSFER_GAS,
+            gas_limit: 10 * TRANSFER_GAS,
+            initial_base_fee: 0,
+            base_fee_max_change_denominator: 8,
+        }
+    }
+
+    // EIP-1559 base fee of the block following one with the given base fee and gas usage. A full
+    // block always raises it by at least 1, so it can climb back from 0.
+    pub fn next_base_fee(&self, parent_base_fee: u128, parent_gas_used: u64) -> u128 {
+        let target = self.gas_target.max(1) as u128;
+        let gas_used = parent_gas_used as u128;
+        let denominator = self.base_fee_max_change_denominator.max(1);
+
+        match gas_used.cmp(&target) {
+            Ordering::Equal => parent_base_fee,
+            Ordering::Greater => {
+                let delta =
+                    parent_base_fee.saturating_mul(gas_used - target) / target / denominator;
+
+                parent_base_fee.saturating_add(delta.max(1))
+            }
+            Ordering::Less => {
+                let delta =
+                    parent_base_fee.saturating_mul(target - gas_used) / target / denominator;
+
+                parent_base_fee - delta
+            }
+        }
+    }
+}
+
+// Balances and nonces of every account that has been touched
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Ledger {
+    accounts: BTreeMap<String, Account>,
+    burned: u128,
+}
+
+impl Ledger {
+    pub fn genesis(config: &LedgerConfig) -> Self {
+        let mut ledger = Ledger::default();
+
+        for (address, balance) in config.allocations.iter() {
+            ledger.credit(address, *balance);
+        }
+
+        ledger
+    }
+
+    pub fn account(&self, address: &str) -> Account {
+        self.accounts.get(address).copied().unwrap_or_default()
+    }
+
+    pub fn balance(&self, address: &str) -> u128 {
+        self.account(address).balance
+    }
+
+    pub fn nonce(&self, address: &str) -> u64 {
+        self.account(address).nonce
+    }
+
+    // Base fee taken out of circulation so far
+    pub fn burned(&self) -> u128 {
+        self.burned
+    }
+
+    pub fn nonces(&self) -> HashMap<String, u64> {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.nonce > 0)
+            .map(|(address, account)| (address.clone(), account.nonce))
+            .collect()
+    }
+
+    pub fn credit(&mut self, address: &str, amount: u128) {
+        let account = self.accounts.entry(address.to_string()).or_default();
+
+        account.balance = account.balance.saturating_add(amount);
+    }
+
+    // Moves the value to the recipient and takes the gas fees from the sender. The base fee is
+    // burned and the tip is returned so it can be paid to the block's beneficiary. The sender has
+    // to hold the most the transaction can cost. Nothing changes if the transaction is rejected.
+    pub fn apply_transaction(
+        &mut self,
+        transaction: &Transaction,
+        base_fee: u128,
+    ) -> Result<u128, TransactionError> {
+        let sender = transaction.sender()?;
+        let account = self.account(&sender);
+
+        check_nonce(account.nonce, transaction.nonce())?;
+
+        let max_fee = transaction.max_fee_per_gas();
+
+        if transaction.max_priority_fee_per_gas() > max_fee {
+            return Err(TransactionError::PriorityFeeAboveMaxFee);
+        }
+
+        let tip = transaction
+            .effective_tip(base_fee)
+            .ok_or(TransactionError::MaxFeeBelowBaseFee { base_fee, max_fee })?;
+
+        let required = transaction.max_cost();
+
+        if account.balance < required {
+            return Err(TransactionError::InsufficientBalance {
+                balance: account.balance,
+                required,
+            });
+        }
+
+        let gas = transaction.gas() as u128;
+        let burned = base_fee.saturating_mul(gas);
+        let tips = tip.saturating_mul(gas);
+
+        self.accounts.insert(
+            sender,
+            Account {
+                balance: account.balance - transaction.value() - burned - tips,
+                nonce: account.nonce + 1,
+            },
+        );
+        self.credit(transaction.to(), transaction.value());
+        self.burned = self.burned.saturating_add(burned);
+
+        Ok(tips)
+    }
+}
+
+pub fn check_nonce(expected: u64, found: u64) -> Result<(), TransactionError> {
+    match found.cmp(&expected) {
+        Ordering::Less => Err(TransactionError::NonceTooLow { expected, found }),
+        Ordering::Greater => Err(TransactionError::NonceTooHigh { expected, found }),
+        Ordering::Equal => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod apply_transaction {
+        use k256::ecdsa::SigningKey;
+
+        use crate::week_1::{
+            ledger::{Account, Ledger, LedgerConfig},
+            proof_of_work::{Transaction, TransactionError},
+        };
+
+        fn funded_ledger(balance: u128) -> (Ledger, SigningKey, String) {
+            let key = SigningKey::from_bytes(&[1; 32]).unwrap();
+            let sender = Transaction::sign(&key, String::new(), 0, 0, 0)
+                .sender()
+                .unwrap();
+
+            let mut config = LedgerConfig::new();
+            config.allocations.insert(sender.clone(), balance);
+
+            (Ledger::genesis(&config), key, sender)
+        }
+
+        #[test]
+        fn should_move_the_value_burn_the_base_fee_and_return_the_tip() {
+            let (mut ledger, key, sender) = funded_ledger(1_000_000);
+            let transaction = Transaction::sign_with_fees(&key, String::from("Niapa"), 60, 5, 1, 0);
+
+            let tip = ledger.apply_transaction(&transaction, 2).unwrap();
+
+            assert_eq!(tip, 21_000);
+            assert_eq!(ledger.burned(), 42_000);
+            assert_eq!(
+                ledger.account(&sender),
+                Account {
+                    balance: 1_000_000 - 60 - 63_000,
+                    nonce: 1
+                }
+            );
+            assert_eq!(ledger.balance("Niapa"), 60);
+        }
+
+        #[test]
+        fn should_cap_the_tip_at_what_the_max_fee_leaves() {
+            let (mut ledger, key, _) = funded_ledger(1_000_000);
+            let transaction = Transaction::sign_with_fees(&key, String::from("Niapa"), 0, 5, 4, 0);
+
+            assert_eq!(ledger.apply_transaction(&transaction, 3), Ok(2 * 21_000));
+        }
+
+        #[test]
+        fn should_reject_a_max_fee_below_the_base_fee() {
+            let (mut ledger, key, sender) = funded_ledger(1_000_000);
+            let transaction = Transaction::sign(&key, String::from("Niapa"), 1, 5, 0);
+
+            assert_eq!(
+                ledger.apply_transaction(&transaction, 6),
+                Err(TransactionError::MaxFeeBelowBaseFee {
+                    base_fee: 6,
+                    max_fee: 5
+                })
+            );
+            assert_eq!(ledger.nonce(&sender), 0);
+        }
+
+        #[test]
+        fn should_reject_an_overspend() {
+            let (mut ledger, key, sender) = funded_ledger(100_000);
+            let transaction = Transaction::sign(&key, String::from("Niapa"), 1, 5, 0);
+
+            // The max fee has to be covered even if the base fee is lower
+            let result = ledger.apply_transaction(&transaction, 0);
+
+            assert_eq!(
+                result,
+                Err(TransactionError::InsufficientBalance {
+                    balance: 100_000,
+                    required: 105_001
+                })
+            );
+            assert_eq!(ledger.balance(&sender), 100_000);
+            assert_eq!(ledger.nonce(&sender), 0);
+        }
+
+        #[test]
+        fn should_reject_a_nonce_out_of_sequence() {
+            let (mut ledger, key, _) = funded_ledger(100);
+            let transaction = Transaction::sign(&key, String::from("Niapa"), 1, 1, 1);
+
+            assert_eq!(
+                ledger.apply_transaction(&transaction, 0),
+                Err(TransactionError::NonceTooHigh {
+                    expected: 0,
+                    found: 1
+                })
+            );
+        }
+    }
+    mod next_base_fee {
+        use crate::week_1::{ledger::LedgerConfig, proof_of_work::TRANSFER_GAS};
+
+        #[test]
+        fn should_hold_at_the_target() {
+            let config = LedgerConfig::new();
+
+            assert_eq!(config.next_base_fee(1_000, config.gas_target), 1_000);
+        }
+
+        #[test]
+        fn should_move_by_an_eighth_at_most() {
+            let config = LedgerConfig::new();
+
+            assert_eq!(config.next_base_fee(1_000, config.gas_limit), 1_125);
+            assert_eq!(config.next_base_fee(1_000, 0), 875);
+            assert_eq!(config.next_base_fee(1_000, 6 * TRANSFER_GAS), 1_025);
+        }
+
+        #[test]
+        fn should_climb_back_from_zero() {
+            let config = LedgerConfig::new();
+
+            assert_eq!(config.next_base_fee(0, config.gas_limit), 1);
+            assert_eq!(config.next_base_fee(0, 0), 0);
+        }
+    }
+}
@@ -0,0 +1,661 @@
+use k256::elliptic_curve::bigint::ArrayEncoding;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use super::{
+    digital_signatures::Keypair,
+    ledger::LedgerConfig,
+    proof_of_work::{Block, DifficultyConfig, Miner, Transaction},
+    transaction_pool::PoolConfig,
+};
+
+// Network Simulator
+//
+// Time is simulated in milliseconds and advanced by a single event queue, so a run only depends on
+// its seed. Every link between two nodes is a tokio channel, and the queue decides when the next
+// message on it arrives, after the sampled latency, unless the message is lost on the way. Nodes
+// gossip the blocks they mine or import, and only ask a peer for its whole chain when a block
+// builds on one they don't know.
+
+// Accounts that send the synthetic transactions
+const ACCOUNTS: usize = 8;
+// Genesis balance of every account, enough that synthetic transactions never overspend
+const ACCOUNT_BALANCE: u128 = 1_000_000_000;
+
+// Miners work with timestamps in seconds
+fn to_seconds(millis: u64) -> u64 {
+    millis / 1_000
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LinkConfig {
+    pub latency: u64,
+    pub jitter: u64,
+    pub loss: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SimulationConfig {
+    pub seed: u64,
+    pub hashrates: Vec<u64>,
+    pub link: LinkConfig,
+    pub block_interval: u64,
+    pub transaction_interval: u64,
+    pub duration: u64,
+}
+
+impl SimulationConfig {
+    pub fn new(seed: u64, nodes: usize) -> Self {
+        Self {
+            seed,
+            hashrates: vec![1; nodes],
+            link: LinkConfig {
+                latency: 100,
+                jitter: 50,
+                loss: 0.0,
+            },
+            block_interval: 10_000,
+            transaction_interval: 5_000,
+            duration: 120_000,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SimulationEvent {
+    TransactionSubmitted {
+        time: u64,
+        node: usize,
+    },
+    BlockMined {
+        time: u64,
+        node: usize,
+        height: usize,
+        hash: String,
+    },
+    MessageDropped {
+        time: u64,
+        from: usize,
+        to: usize,
+    },
+    ChainAdopted {
+        time: u64,
+        node: usize,
+        height: usize,
+        reverted: usize,
+    },
+}
+
+pub struct SimulationReport {
+    pub events: Vec<SimulationEvent>,
+    pub blocks_mined: usize,
+    pub stale_blocks: usize,
+    pub orphan_rate: f64,
+    // Mean time in milliseconds between a block being mined and every node having adopted it
+    pub average_propagation_delay: Option<f64>,
+    pub tip_heights: Vec<usize>,
+    pub converged: bool,
+}
+
+#[derive(Clone)]
+enum Message {
+    Transaction(Transaction),
+    Block(Block),
+    GetChain,
+    Chain(Vec<Block>),
+}
+
+// One direction of a connection between two nodes
+struct Link {
+    sender: UnboundedSender<Message>,
+    // Messages on a link arrive in the order they were sent, so none can arrive before this
+    last_arrival: u64,
+}
+
+enum Action {
+    FindBlock(usize),
+    SubmitTransaction,
+    // The next message on the link from one node to the other arrives
+    Deliver { from: usize, to: usize },
+}
+
+struct ScheduledAction {
+    time: u64,
+    sequence: u64,
+    action: Action,
+}
+
+// BinaryHeap is a max heap, so the ordering is reversed to pop the earliest action first
+impl Ord for ScheduledAction {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.time, other.sequence).cmp(&(self.time, self.sequence))
+    }
+}
+
+impl PartialOrd for ScheduledAction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ScheduledAction {
+    fn eq(&self, other: &Self) -> bool {
+        (self.time, self.sequence) == (other.time, other.sequence)
+    }
+}
+
+impl Eq for ScheduledAction {}
+
+struct Node {
+    miner: Miner,
+    // Receiving end of the link from every peer
+    inbox: HashMap<usize, UnboundedReceiver<Message>>,
+    seen_transactions: HashSet<String>,
+    seen_blocks: HashSet<String>,
+}
+
+struct Account {
+    keypair: Keypair,
+    address: String,
+    next_nonce: u64,
+}
+
+struct BlockPropagation {
+    mined_at: u64,
+    reached: HashSet<usize>,
+    fully_propagated_at: Option<u64>,
+}
+
+fn block_hash(block: &Block) -> String {
+    hex::encode(block.hash().to_be_byte_array())
+}
+
+pub struct NetworkSimulator {
+    config: SimulationConfig,
+    rng: StdRng,
+    clock: u64,
+    sequence: u64,
+    queue: BinaryHeap<ScheduledAction>,
+    nodes: Vec<Node>,
+    links: HashMap<(usize, usize), Link>,
+    events: Vec<SimulationEvent>,
+    accounts: Vec<Account>,
+    blocks_mined: usize,
+    propagation: HashMap<String, BlockPropagation>,
+}
+
+impl NetworkSimulator {
+    pub fn new(config: SimulationConfig) -> Self {
+        // Miners retarget towards the simulated block interval, which is kept in milliseconds
+        let difficulty = DifficultyConfig {
+            target_block_time: to_seconds(config.block_interval).max(1),
+            ..DifficultyConfig::new()
+        };
+
+        let mut rng = StdRng::seed_from_u64(config.seed);
+
+        let accounts: Vec<Account> = (0..ACCOUNTS)
+            .map(|_| {
+                let keypair = Keypair::random(&mut rng);
+
+                Account {
+                    address: keypair.address().to_hex(),
+                    keypair,
+                    next_nonce: 0,
+                }
+            })
+            .collect();
+
+        let mut ledger_config = LedgerConfig::new();
+
+        for account in accounts.iter() {
+            ledger_config
+                .allocations
+                .insert(account.address.clone(), ACCOUNT_BALANCE);
+        }
+
+        // Every node pays its rewards to one of the accounts
+        let mut nodes: Vec<Node> = (0..config.hashrates.len())
+            .map(|idx| {
+                let mut miner =
+                    Miner::with_config(difficulty, PoolConfig::new(), ledger_config.clone());
+                miner.set_beneficiary(accounts[idx % ACCOUNTS].address.clone());
+
+                Node {
+                    miner,
+                    inbox: HashMap::new(),
+                    seen_transactions: HashSet::new(),
+                    seen_blocks: HashSet::new(),
+                }
+            })
+            .collect();
+
+        let mut links = HashMap::new();
+
+        for from in 0..nodes.len() {
+            for (to, node) in nodes.iter_mut().enumerate() {
+                if from == to {
+                    continue;
+                }
+
+                let (sender, receiver) = unbounded_channel();
+
+                node.inbox.insert(from, receiver);
+                links.insert(
+                    (from, to),
+                    Link {
+                        sender,
+                        last_arrival: 0,
+                    },
+                );
+            }
+        }
+
+        Self {
+            rng,
+            config,
+            clock: 0,
+            sequence: 0,
+            queue: BinaryHeap::new(),
+            nodes,
+            links,
+            events: Vec::new(),
+            accounts,
+            blocks_mined: 0,
+            propagation: HashMap::new(),
+        }
+    }
+
+    pub fn run(mut self) -> SimulationReport {
+        for node in 0..self.nodes.len() {
+            let delay = self.sample_block_delay(node);
+            self.schedule(delay, Action::FindBlock(node));
+        }
+
+        let delay = self.sample_exponential(self.config.transaction_interval as f64);
+        self.schedule(delay, Action::SubmitTransaction);
+
+        while let Some(scheduled) = self.queue.pop() {
+            let is_production = !matches!(scheduled.action, Action::Deliver { .. });
+
+            // Once the run is over nothing new is produced, but messages in flight still land
+            if is_production && scheduled.time > self.config.duration {
+                continue;
+            }
+
+            self.clock = scheduled.time;
+
+            match scheduled.action {
+                Action::FindBlock(node) => self.find_block(node),
+                Action::SubmitTransaction => self.submit_transaction(),
+                Action::Deliver { from, to } => self.deliver(from, to),
+            }
+        }
+
+        self.report()
+    }
+
+    fn schedule(&mut self, delay: u64, action: Action) {
+        self.sequence += 1;
+
+        self.queue.push(ScheduledAction {
+            time: self.clock + delay,
+            sequence: self.sequence,
+            action,
+        });
+    }
+
+    fn sample_exponential(&mut self, mean: f64) -> u64 {
+        let uniform: f64 = self.rng.gen();
+
+        (-(1.0 - uniform).ln() * mean).round() as u64
+    }
+
+    // Each node finds blocks at a rate proportional to its share of the total hashrate
+    fn sample_block_delay(&mut self, node: usize) -> u64 {
+        let total_hashrate: u64 = self.config.hashrates.iter().sum();
+        let share = self.config.hashrates[node] as f64 / total_hashrate.max(1) as f64;
+
+        if share == 0.0 {
+            return u64::MAX / 2;
+        }
+
+        self.sample_exponential(self.config.block_interval as f64 / share)
+    }
+
+    fn broadcast(&mut self, from: usize, message: Message, except: Option<usize>) {
+        for to in 0..self.nodes.len() {
+            if to == from || Some(to) == except {
+                continue;
+            }
+
+            self.send(from, to, message.clone());
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, message: Message) {
+        let link = self.config.link;
+
+        if link.loss > 0.0 && self.rng.gen_bool(link.loss.min(1.0)) {
+            self.events.push(SimulationEvent::MessageDropped {
+                time: self.clock,
+                from,
+                to,
+            });
+
+            return;
+        }
+
+        let delay = link.latency + self.rng.gen_range(0..=link.jitter);
+        let link = self.links.get_mut(&(from, to)).unwrap();
+        let arrival = (self.clock + delay).max(link.last_arrival);
+
+        link.last_arrival = arrival;
+
+        // The receiving end lives as long as the simulator, so sending can't fail
+        let _ = link.sender.send(message);
+
+        self.schedule(arrival - self.clock, Action::Deliver { from, to });
+    }
+
+    fn find_block(&mut self, node: usize) {
+        self.nodes[node].miner.mine_at(to_seconds(self.clock));
+        self.blocks_mined += 1;
+
+        let miner = &self.nodes[node].miner;
+        let block = miner.get_blocks().last().unwrap().clone();
+        let hash = block_hash(&block);
+
+        self.events.push(SimulationEvent::BlockMined {
+            time: self.clock,
+            node,
+            height: miner.get_block_height(),
+            hash: hash.clone(),
+        });
+
+        self.nodes[node].seen_blocks.insert(hash.clone());
+
+        self.propagation.insert(
+            hash.clone(),
+            BlockPropagation {
+                mined_at: self.clock,
+                reached: HashSet::new(),
+                fully_propagated_at: None,
+            },
+        );
+        self.mark_reached(node, hash);
+
+        self.broadcast(node, Message::Block(block), None);
+
+        let delay = self.sample_block_delay(node);
+        self.schedule(delay, Action::FindBlock(node));
+    }
+
+    fn submit_transaction(&mut self) {
+        let node = self.rng.gen_range(0..self.nodes.len());
+        let sender = self.rng.gen_range(0..ACCOUNTS);
+        let recipient = self.rng.gen_range(0..ACCOUNTS);
+
+        let transaction = Transaction::sign(
+            self.accounts[sender].keypair.signing_key(),
+            self.accounts[recipient].address.clone(),
+            1,
+            self.rng.gen_range(1..=100),
+            self.accounts[sender].next_nonce,
+        );
+
+        self.accounts[sender].next_nonce += 1;
+
+        self.events.push(SimulationEvent::TransactionSubmitted {
+            time: self.clock,
+            node,
+        });
+
+        self.receive_transaction(node, node, transaction);
+
+        let delay = self.sample_exponential(self.config.transaction_interval as f64);
+        self.schedule(delay, Action::SubmitTransaction);
+    }
+
+    // Every scheduled delivery matches one message sent on the link, in the same order
+    fn deliver(&mut self, from: usize, node: usize) {
+        let Some(Ok(message)) = self.nodes[node]
+            .inbox
+            .get_mut(&from)
+            .map(|inbox| inbox.try_recv())
+        else {
+            return;
+        };
+
+        match message {
+            Message::Transaction(transaction) => self.receive_transaction(node, from, transaction),
+            Message::Block(block) => self.receive_block(node, from, block),
+            Message::GetChain => {
+                let blocks = self.nodes[node].miner.get_blocks().to_vec();
+
+                self.send(node, from, Message::Chain(blocks));
+            }
+            Message::Chain(blocks) => self.receive_chain(node, from, blocks),
+        }
+    }
+
+    fn receive_transaction(&mut self, node: usize, from: usize, transaction: Transaction) {
+        let is_new = self.nodes[node]
+            .seen_transactions
+            .insert(transaction.to_string());
+
+        if !is_new {
+            return;
+        }
+
+        // Transactions that arrive ahead of the ones they depend on are rejected and not relayed
+        if self.nodes[node]
+            .miner
+            .add_transaction_at(transaction.clone(), to_seconds(self.clock))
+            .is_ok()
+        {
+            self.broadcast(node, Message::Transaction(transaction), Some(from));
+        }
+    }
+
+    fn receive_block(&mut self, node: usize, from: usize, block: Block) {
+        let hash = block_hash(&block);
+
+        if !self.nodes[node].seen_blocks.insert(hash.clone()) {
+            return;
+        }
+
+        let miner = &mut self.nodes[node].miner;
+        let parent = miner
+            .get_blocks()
+            .iter()
+            .rposition(|known| known.hash() == block.parent_hash());
+
+        match parent {
+            Some(idx) if idx + 1 == miner.get_block_height() => {
+                if miner.import_block(block.clone()).is_err() {
+                    return;
+                }
+
+                self.events.push(SimulationEvent::ChainAdopted {
+                    time: self.clock,
+                    node,
+                    height: miner.get_block_height(),
+                    reverted: 0,
+                });
+
+                self.mark_reached(node, hash);
+                self.broadcast(node, Message::Block(block), Some(from));
+            }
+            // A block on an older block of our chain ties at best with ours, as its sibling on
+            // our chain shares its bits
+            Some(_) => {}
+            None => self.send(node, from, Message::GetChain),
+        }
+    }
+
+    fn receive_chain(&mut self, node: usize, from: usize, blocks: Vec<Block>) {
+        let height = blocks.len();
+
+        let Ok(reorg) = self.nodes[node]
+            .miner
+            .replace_chain_at(blocks, to_seconds(self.clock))
+        else {
+            return;
+        };
+
+        self.events.push(SimulationEvent::ChainAdopted {
+            time: self.clock,
+            node,
+            height,
+            reverted: reorg.reverted.len(),
+        });
+
+        for block in reorg.adopted.iter() {
+            let hash = block_hash(block);
+
+            self.nodes[node].seen_blocks.insert(hash.clone());
+            self.mark_reached(node, hash);
+        }
+
+        // Peers only need the new tip, they ask for the rest if they miss its parent
+        if let Some(tip) = reorg.adopted.last() {
+            self.broadcast(node, Message::Block(tip.clone()), Some(from));
+        }
+    }
+
+    fn mark_reached(&mut self, node: usize, hash: String) {
+        let node_count = self.nodes.len();
+
+        if let Some(propagation) = self.propagation.get_mut(&hash) {
+            propagation.reached.insert(node);
+
+            if propagation.reached.len() == node_count && propagation.fully_propagated_at.is_none()
+            {
+                propagation.fully_propagated_at = Some(self.clock);
+            }
+        }
+    }
+
+    fn report(self) -> SimulationReport {
+        let tip_heights: Vec<usize> = self
+            .nodes
+            .iter()
+            .map(|node| node.miner.get_block_height())
+            .collect();
+
+        let tips: HashSet<Option<String>> = self
+            .nodes
+            .iter()
+            .map(|node| node.miner.get_blocks().last().map(block_hash))
+            .collect();
+
+        let canonical_height = tip_heights.iter().copied().max().unwrap_or_default();
+        let stale_blocks = self.blocks_mined.saturating_sub(canonical_height);
+
+        let delays: Vec<u64> = self
+            .propagation
+            .values()
+            .filter_map(|propagation| {
+                propagation
+                    .fully_propagated_at
+                    .map(|time| time - propagation.mined_at)
+            })
+            .collect();
+
+        let average_propagation_delay = if delays.is_empty() {
+            None
+        } else {
+            Some(delays.iter().sum::<u64>() as f64 / delays.len() as f64)
+        };
+
+        SimulationReport {
+            events: self.events,
+            blocks_mined: self.blocks_mined,
+            stale_blocks,
+            orphan_rate: if self.blocks_mined == 0 {
+                0.0
+            } else {
+                stale_blocks as f64 / self.blocks_mined as f64
+            },
+            average_propagation_delay,
+            tip_heights,
+            converged: tips.len() == 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod run {
+        use crate::week_1::network_simulator::{
+            NetworkSimulator, SimulationConfig, SimulationEvent,
+        };
+
+        #[test]
+        fn should_be_reproducible_with_the_same_seed() {
+            let mut config = SimulationConfig::new(7, 4);
+            config.link.loss = 0.1;
+
+            let first = NetworkSimulator::new(config.clone()).run();
+            let second = NetworkSimulator::new(config).run();
+
+            assert_eq!(first.events, second.events);
+            assert_eq!(first.blocks_mined, second.blocks_mined);
+        }
+
+        #[test]
+        fn should_diverge_with_a_different_seed() {
+            let first = NetworkSimulator::new(SimulationConfig::new(1, 4)).run();
+            let second = NetworkSimulator::new(SimulationConfig::new(2, 4)).run();
+
+            assert_ne!(first.events, second.events);
+        }
+
+        #[test]
+        fn should_converge_over_lossless_links() {
+            let report = NetworkSimulator::new(SimulationConfig::new(42, 5)).run();
+
+            assert!(report.converged);
+            assert!(report.blocks_mined > 0);
+            assert!(report.tip_heights.iter().all(|height| *height > 0));
+            assert!(report.average_propagation_delay.unwrap() >= 100.0);
+        }
+
+        #[test]
+        fn should_produce_stale_blocks_with_slow_links() {
+            let mut config = SimulationConfig::new(3, 6);
+            config.block_interval = 1_000;
+            config.link.latency = 2_000;
+            config.duration = 30_000;
+
+            let report = NetworkSimulator::new(config).run();
+
+            assert!(report.stale_blocks > 0);
+            assert!(report.orphan_rate > 0.0 && report.orphan_rate < 1.0);
+            let reorgs = report.events.iter().filter(|event| {
+                matches!(event, SimulationEvent::ChainAdopted { reverted, .. } if *reverted > 0)
+            });
+
+            assert!(reorgs.count() > 0);
+        }
+
+        #[test]
+        fn should_record_dropped_messages() {
+            let mut config = SimulationConfig::new(11, 3);
+            config.link.loss = 0.5;
+
+            let report = NetworkSimulator::new(config).run();
+
+            assert!(report
+                .events
+                .iter()
+                .any(|event| matches!(event, SimulationEvent::MessageDropped { .. })));
+        }
+    }
+}
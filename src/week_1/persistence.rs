@@ -0,0 +1,152 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fs, path::Path};
+
+// Chain Persistence
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SnapshotFormat {
+    Json,
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError<E> {
+    Io(std::io::Error),
+    Encoding(String),
+    UnsupportedVersion(u32),
+    InvalidBlock(E),
+}
+
+impl<E> From<std::io::Error> for SnapshotError<E> {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<T> {
+    version: u32,
+    state: T,
+}
+
+// Both formats store the version first, so it can be read without knowing the layout of the state
+#[derive(Deserialize)]
+struct SnapshotVersion {
+    version: u32,
+}
+
+fn encode<T: Serialize, E>(format: SnapshotFormat, value: &T) -> Result<Vec<u8>, SnapshotError<E>> {
+    match format {
+        SnapshotFormat::Json => serde_json::to_vec_pretty(value)
+            .map_err(|error| SnapshotError::Encoding(error.to_string())),
+        SnapshotFormat::Binary => {
+            bincode::serialize(value).map_err(|error| SnapshotError::Encoding(error.to_string()))
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned, E>(
+    format: SnapshotFormat,
+    bytes: &[u8],
+) -> Result<T, SnapshotError<E>> {
+    match format {
+        SnapshotFormat::Json => serde_json::from_slice(bytes)
+            .map_err(|error| SnapshotError::Encoding(error.to_string())),
+        SnapshotFormat::Binary => {
+            bincode::deserialize(bytes).map_err(|error| SnapshotError::Encoding(error.to_string()))
+        }
+    }
+}
+
+pub fn write_snapshot<T: Serialize, E>(
+    path: impl AsRef<Path>,
+    format: SnapshotFormat,
+    state: &T,
+) -> Result<(), SnapshotError<E>> {
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        state,
+    };
+
+    fs::write(path, encode(format, &snapshot)?)?;
+
+    Ok(())
+}
+
+pub fn read_snapshot<T: DeserializeOwned, E>(
+    path: impl AsRef<Path>,
+    format: SnapshotFormat,
+) -> Result<T, SnapshotError<E>> {
+    let bytes = fs::read(path)?;
+
+    let SnapshotVersion { version } = decode(format, &bytes)?;
+
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let snapshot: Snapshot<T> = decode(format, &bytes)?;
+
+    Ok(snapshot.state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    pub fn snapshot_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("snapshot-{}-{}", std::process::id(), name))
+    }
+
+    mod read_snapshot {
+        use std::fs;
+
+        use super::snapshot_path;
+        use crate::week_1::persistence::{
+            read_snapshot, write_snapshot, SnapshotError, SnapshotFormat,
+        };
+
+        #[test]
+        fn should_read_back_the_written_state() {
+            for format in [SnapshotFormat::Json, SnapshotFormat::Binary] {
+                let path = snapshot_path(&format!("roundtrip-{:?}", format));
+                let state = vec![String::from("A"), String::from("B")];
+
+                write_snapshot::<_, ()>(&path, format, &state).unwrap();
+
+                let loaded: Vec<String> = read_snapshot::<_, ()>(&path, format).unwrap();
+
+                assert_eq!(loaded, state);
+
+                fs::remove_file(path).unwrap();
+            }
+        }
+
+        #[test]
+        fn should_reject_an_unknown_version() {
+            let path = snapshot_path("unknown-version.json");
+
+            fs::write(&path, r#"{"version":42,"state":[]}"#).unwrap();
+
+            let result = read_snapshot::<Vec<String>, ()>(&path, SnapshotFormat::Json);
+
+            assert!(matches!(result, Err(SnapshotError::UnsupportedVersion(42))));
+
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn should_fail_on_garbage_input() {
+            let path = snapshot_path("garbage.bin");
+
+            fs::write(&path, [1, 0]).unwrap();
+
+            let result = read_snapshot::<Vec<String>, ()>(&path, SnapshotFormat::Binary);
+
+            assert!(matches!(result, Err(SnapshotError::Encoding(_))));
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,846 @@
+use k256::{ecdsa::SigningKey, elliptic_curve::bigint::ArrayEncoding, U256};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::{
+    digital_signatures::sign_message_with_key,
+    ledger::{Ledger, LedgerConfig},
+    proof_of_work::{recover_signer, signer_address, Block, Transaction, TransactionError},
+};
+
+// Proof of Stake
+//
+// Blocks and transactions are the proof of work ones. A block's beneficiary is its proposer and
+// its bits are unused, the right to propose comes from stake instead of a hash below a target.
+
+// Value sent here is locked as stake of the sender
+pub const DEPOSIT_ADDRESS: &str = "00000000219ab540356cbb839cbe05303d7705fa";
+
+#[derive(Clone, Copy, Debug)]
+pub struct StakeConfig {
+    // Slots per epoch, every epoch starts with a checkpoint validators attest to
+    pub epoch_length: u64,
+    // Seconds per slot, block timestamps are derived from their slot
+    pub slot_duration: u64,
+    // Smallest deposit that registers a new validator
+    pub min_deposit: u128,
+    // Percentage of its stake a slashed validator loses
+    pub slash_penalty: u128,
+    // Mixed into proposer selection, the same seed always picks the same proposers
+    pub seed: u64,
+}
+
+impl StakeConfig {
+    pub fn new() -> Self {
+        Self {
+            epoch_length: 4,
+            slot_duration: 12,
+            min_deposit: 32,
+            slash_penalty: 50,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Validator {
+    pub stake: u128,
+    // Slashed validators can neither propose nor attest
+    pub slashed: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Checkpoint {
+    pub epoch: u64,
+    pub hash: U256,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SlashingEvidence {
+    pub validator: String,
+    pub slot: u64,
+    pub first: U256,
+    pub second: U256,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum StakeError {
+    InvalidSignature,
+    UnknownValidator,
+    SlashedValidator,
+    WrongProposer {
+        expected: Option<String>,
+        found: String,
+    },
+    SlotNotAfterHead {
+        head: u64,
+        found: u64,
+    },
+    UnknownParent,
+    // The header doesn't match the one the slot and the proposer dictate
+    InvalidBlock,
+    InvalidTransaction {
+        index: usize,
+        error: TransactionError,
+    },
+    DepositTooSmall {
+        minimum: u128,
+        found: u128,
+    },
+    AlreadyKnown,
+    DoubleProposal(SlashingEvidence),
+    UnknownCheckpoint,
+    AlreadyAttested,
+}
+
+fn sign_payload(signing_key: &SigningKey, payload: &str) -> String {
+    hex::encode(sign_message_with_key(signing_key, payload.as_bytes()).as_ref())
+}
+
+fn proposal_payload(slot: u64, hash: U256) -> String {
+    format!(
+        "{{slot:{},block:{}}}",
+        slot,
+        hex::encode(hash.to_be_byte_array())
+    )
+}
+
+fn attestation_payload(checkpoint: Checkpoint) -> String {
+    format!(
+        "{{epoch:{},target:{}}}",
+        checkpoint.epoch,
+        hex::encode(checkpoint.hash.to_be_byte_array())
+    )
+}
+
+// A block signed by the validator proposing it for the slot
+#[derive(Clone)]
+pub struct Proposal {
+    pub slot: u64,
+    pub block: Block,
+    signature: String,
+}
+
+impl Proposal {
+    pub fn sign(signing_key: &SigningKey, slot: u64, block: Block) -> Self {
+        Self {
+            signature: sign_payload(signing_key, &proposal_payload(slot, block.hash())),
+            slot,
+            block,
+        }
+    }
+
+    pub fn proposer(&self) -> Option<String> {
+        recover_signer(
+            proposal_payload(self.slot, self.block.hash()).as_bytes(),
+            &self.signature,
+        )
+    }
+}
+
+// A validator's vote for the checkpoint of an epoch
+#[derive(Clone)]
+pub struct Attestation {
+    pub checkpoint: Checkpoint,
+    signature: String,
+}
+
+impl Attestation {
+    pub fn sign(signing_key: &SigningKey, checkpoint: Checkpoint) -> Self {
+        Self {
+            signature: sign_payload(signing_key, &attestation_payload(checkpoint)),
+            checkpoint,
+        }
+    }
+
+    pub fn attester(&self) -> Option<String> {
+        recover_signer(
+            attestation_payload(self.checkpoint).as_bytes(),
+            &self.signature,
+        )
+    }
+}
+
+pub struct BeaconChain {
+    config: StakeConfig,
+    ledger_config: LedgerConfig,
+    validators: BTreeMap<String, Validator>,
+    blocks: Vec<Block>,
+    // Slot of every block, slots without a proposal are skipped
+    slots: Vec<u64>,
+    ledger: Ledger,
+    // Proposer and block accepted for each slot, a second block is evidence for slashing
+    proposals: HashMap<u64, (String, U256)>,
+    attestations: HashMap<u64, HashSet<String>>,
+    justified_epochs: HashSet<u64>,
+    justified: Checkpoint,
+    finalized: Checkpoint,
+    slashings: Vec<SlashingEvidence>,
+}
+
+impl BeaconChain {
+    // Starts from a genesis block at slot 0, which is justified and finalized from the start
+    pub fn new(
+        config: StakeConfig,
+        ledger_config: LedgerConfig,
+        validators: Vec<(String, u128)>,
+    ) -> Self {
+        let genesis = Block::new(
+            U256::ZERO,
+            0,
+            U256::ZERO,
+            U256::ZERO,
+            0,
+            0,
+            String::new(),
+            Vec::new(),
+        );
+        let checkpoint = Checkpoint {
+            epoch: 0,
+            hash: genesis.hash(),
+        };
+
+        Self {
+            config,
+            ledger: Ledger::genesis(&ledger_config),
+            ledger_config,
+            validators: validators
+                .into_iter()
+                .map(|(address, stake)| {
+                    let validator = Validator {
+                        stake,
+                        slashed: false,
+                    };
+
+                    (address, validator)
+                })
+                .collect(),
+            blocks: vec![genesis],
+            slots: vec![0],
+            proposals: HashMap::new(),
+            attestations: HashMap::new(),
+            justified_epochs: HashSet::from([0]),
+            justified: checkpoint,
+            finalized: checkpoint,
+            slashings: Vec::new(),
+        }
+    }
+
+    pub fn get_blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn get_head_slot(&self) -> u64 {
+        *self.slots.last().unwrap()
+    }
+
+    pub fn get_balance(&self, address: &str) -> u128 {
+        self.ledger.balance(address)
+    }
+
+    pub fn get_validator(&self, address: &str) -> Option<Validator> {
+        self.validators.get(address).copied()
+    }
+
+    pub fn get_justified(&self) -> Checkpoint {
+        self.justified
+    }
+
+    pub fn get_finalized(&self) -> Checkpoint {
+        self.finalized
+    }
+
+    pub fn get_slashings(&self) -> &[SlashingEvidence] {
+        &self.slashings
+    }
+
+    // Stake of the validators that haven't been slashed
+    pub fn active_stake(&self) -> u128 {
+        self.validators
+            .values()
+            .filter(|validator| !validator.slashed)
+            .map(|validator| validator.stake)
+            .sum()
+    }
+
+    // Picks the slot's proposer with a probability proportional to its stake. The draw only
+    // depends on the seed and the slot, so every node agrees on it.
+    pub fn proposer(&self, slot: u64) -> Option<String> {
+        let total = self.active_stake();
+
+        if total == 0 {
+            return None;
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.config.seed.to_be_bytes());
+        hasher.update(slot.to_be_bytes());
+        let hash = hasher.finalize();
+
+        let mut draw = u128::from_be_bytes(hash[..16].try_into().unwrap()) % total;
+
+        for (address, validator) in self.validators.iter() {
+            if validator.slashed {
+                continue;
+            }
+
+            if draw < validator.stake {
+                return Some(address.clone());
+            }
+
+            draw -= validator.stake;
+        }
+
+        None
+    }
+
+    // Builds and signs the block the key would propose on top of the head
+    pub fn build_proposal(
+        &self,
+        signing_key: &SigningKey,
+        slot: u64,
+        transactions: Vec<Transaction>,
+    ) -> Proposal {
+        let block = self.expected_block(slot, signer_address(signing_key), transactions);
+
+        Proposal::sign(signing_key, slot, block)
+    }
+
+    fn expected_block(&self, slot: u64, proposer: String, transactions: Vec<Transaction>) -> Block {
+        let head = self.blocks.last().unwrap();
+
+        Block::new(
+            U256::ZERO,
+            0,
+            U256::from(self.blocks.len() as u128),
+            head.hash(),
+            slot * self.config.slot_duration,
+            0,
+            proposer,
+            transactions,
+        )
+    }
+
+    // Appends the proposal's block to the head. A second block from the same proposer for a
+    // slot slashes it.
+    pub fn import_proposal(&mut self, proposal: Proposal) -> Result<(), StakeError> {
+        let proposer = proposal.proposer().ok_or(StakeError::InvalidSignature)?;
+        let hash = proposal.block.hash();
+
+        if let Some((accepted_proposer, accepted_hash)) = self.proposals.get(&proposal.slot) {
+            if *accepted_hash == hash {
+                return Err(StakeError::AlreadyKnown);
+            }
+
+            if *accepted_proposer == proposer {
+                let evidence = SlashingEvidence {
+                    validator: proposer,
+                    slot: proposal.slot,
+                    first: *accepted_hash,
+                    second: hash,
+                };
+
+                self.slash(evidence.clone());
+
+                return Err(StakeError::DoubleProposal(evidence));
+            }
+        }
+
+        match self.validators.get(&proposer) {
+            None => return Err(StakeError::UnknownValidator),
+            Some(validator) if validator.slashed => return Err(StakeError::SlashedValidator),
+            Some(_) => {}
+        }
+
+        let expected = self.proposer(proposal.slot);
+
+        if expected.as_ref() != Some(&proposer) {
+            return Err(StakeError::WrongProposer {
+                expected,
+                found: proposer,
+            });
+        }
+
+        let head = self.get_head_slot();
+
+        if proposal.slot <= head {
+            return Err(StakeError::SlotNotAfterHead {
+                head,
+                found: proposal.slot,
+            });
+        }
+
+        if proposal.block.parent_hash() != self.blocks.last().unwrap().hash() {
+            return Err(StakeError::UnknownParent);
+        }
+
+        let transactions = proposal.block.transactions().to_vec();
+        let expected_block = self.expected_block(proposal.slot, proposer.clone(), transactions);
+
+        if expected_block.hash() != hash {
+            return Err(StakeError::InvalidBlock);
+        }
+
+        let (ledger, deposits) = self.apply_transactions(&proposer, &proposal.block)?;
+
+        self.ledger = ledger;
+
+        for (address, amount) in deposits {
+            self.validators
+                .entry(address)
+                .or_insert(Validator {
+                    stake: 0,
+                    slashed: false,
+                })
+                .stake += amount;
+        }
+
+        self.proposals.insert(proposal.slot, (proposer, hash));
+        self.blocks.push(proposal.block);
+        self.slots.push(proposal.slot);
+
+        Ok(())
+    }
+
+    // Runs the block's transactions against a copy of the ledger and pays the proposer. Returns
+    // the new ledger and the stake deposits the block makes.
+    fn apply_transactions(
+        &self,
+        proposer: &str,
+        block: &Block,
+    ) -> Result<(Ledger, Vec<(String, u128)>), StakeError> {
+        let mut ledger = self.ledger.clone();
+        let mut deposits = Vec::new();
+        let mut fees: u128 = 0;
+
+        for (idx, transaction) in block.transactions().iter().enumerate() {
+            // There's no fee market here, the whole gas price goes to the proposer
+            let fee = ledger
+                .apply_transaction(transaction, 0)
+                .map_err(|error| StakeError::InvalidTransaction { index: idx, error })?;
+
+            fees = fees.saturating_add(fee);
+
+            if transaction.to() != DEPOSIT_ADDRESS {
+                continue;
+            }
+
+            // Checked above, a transaction with an invalid signature can't be applied
+            let sender = transaction.sender().unwrap();
+
+            if !self.validators.contains_key(&sender)
+                && transaction.value() < self.config.min_deposit
+            {
+                return Err(StakeError::DepositTooSmall {
+                    minimum: self.config.min_deposit,
+                    found: transaction.value(),
+                });
+            }
+
+            deposits.push((sender, transaction.value()));
+        }
+
+        ledger.credit(
+            proposer,
+            self.ledger_config.block_reward.saturating_add(fees),
+        );
+
+        Ok((ledger, deposits))
+    }
+
+    fn slash(&mut self, evidence: SlashingEvidence) {
+        if let Some(validator) = self.validators.get_mut(&evidence.validator) {
+            if !validator.slashed {
+                validator.slashed = true;
+                validator.stake -= validator.stake * self.config.slash_penalty / 100;
+            }
+        }
+
+        self.slashings.push(evidence);
+    }
+
+    // Checkpoint of an epoch that has started: the last block at or before its first slot
+    pub fn checkpoint(&self, epoch: u64) -> Option<Checkpoint> {
+        let start = epoch * self.config.epoch_length;
+
+        if start > self.get_head_slot() {
+            return None;
+        }
+
+        let idx = self.slots.partition_point(|slot| *slot <= start) - 1;
+
+        Some(Checkpoint {
+            epoch,
+            hash: self.blocks[idx].hash(),
+        })
+    }
+
+    // Counts the attestation towards its checkpoint. Two thirds of the active stake justify it,
+    // and justifying two consecutive checkpoints finalizes the first one.
+    pub fn import_attestation(&mut self, attestation: Attestation) -> Result<(), StakeError> {
+        let attester = attestation.attester().ok_or(StakeError::InvalidSignature)?;
+
+        match self.validators.get(&attester) {
+            None => return Err(StakeError::UnknownValidator),
+            Some(validator) if validator.slashed => return Err(StakeError::SlashedValidator),
+            Some(_) => {}
+        }
+
+        let epoch = attestation.checkpoint.epoch;
+
+        if self.checkpoint(epoch) != Some(attestation.checkpoint) {
+            return Err(StakeError::UnknownCheckpoint);
+        }
+
+        if !self.attestations.entry(epoch).or_default().insert(attester) {
+            return Err(StakeError::AlreadyAttested);
+        }
+
+        self.update_finality(epoch);
+
+        Ok(())
+    }
+
+    fn update_finality(&mut self, epoch: u64) {
+        let attesting_stake: u128 = self.attestations[&epoch]
+            .iter()
+            .filter_map(|attester| self.validators.get(attester))
+            .filter(|validator| !validator.slashed)
+            .map(|validator| validator.stake)
+            .sum();
+
+        if attesting_stake * 3 < self.active_stake() * 2 {
+            return;
+        }
+
+        let checkpoint = self.checkpoint(epoch).unwrap();
+
+        self.justified_epochs.insert(epoch);
+
+        if epoch > self.justified.epoch {
+            self.justified = checkpoint;
+        }
+
+        let is_source_justified = epoch > 0 && self.justified_epochs.contains(&(epoch - 1));
+
+        if is_source_justified && epoch - 1 > self.finalized.epoch {
+            self.finalized = self.checkpoint(epoch - 1).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+
+    use crate::week_1::{
+        ledger::LedgerConfig,
+        proof_of_stake::{BeaconChain, StakeConfig},
+        proof_of_work::signer_address,
+    };
+
+    pub fn validator_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32]).unwrap()
+    }
+
+    // Chain whose validators are the keys seeded with 1 to 3, staking 100 each
+    pub fn beacon_chain() -> BeaconChain {
+        let validators = (1..=3)
+            .map(|seed| (signer_address(&validator_key(seed)), 100))
+            .collect();
+
+        BeaconChain::new(StakeConfig::new(), LedgerConfig::new(), validators)
+    }
+
+    // Signing key of the proposer the chain expects for the slot
+    pub fn proposer_key(chain: &BeaconChain, slot: u64) -> SigningKey {
+        let proposer = chain.proposer(slot).unwrap();
+
+        (1..=3)
+            .map(validator_key)
+            .find(|key| signer_address(key) == proposer)
+            .unwrap()
+    }
+
+    pub fn propose_slots(chain: &mut BeaconChain, slots: std::ops::RangeInclusive<u64>) {
+        for slot in slots {
+            let proposal = chain.build_proposal(&proposer_key(chain, slot), slot, Vec::new());
+            chain.import_proposal(proposal).unwrap();
+        }
+    }
+
+    mod proposer {
+        use crate::week_1::{
+            ledger::LedgerConfig,
+            proof_of_stake::{tests::beacon_chain, BeaconChain, StakeConfig},
+        };
+
+        #[test]
+        fn should_pick_the_same_proposers_for_the_same_seed() {
+            let chain = beacon_chain();
+            let other = beacon_chain();
+
+            for slot in 0..50 {
+                assert_eq!(chain.proposer(slot), other.proposer(slot));
+            }
+        }
+
+        #[test]
+        fn should_pick_proposers_in_proportion_to_their_stake() {
+            let validators = vec![(String::from("A"), 300), (String::from("B"), 100)];
+            let chain = BeaconChain::new(StakeConfig::new(), LedgerConfig::new(), validators);
+
+            let picked_a = (0..4_000)
+                .filter(|slot| chain.proposer(*slot).as_deref() == Some("A"))
+                .count();
+
+            assert!((2_800..3_200).contains(&picked_a), "{}", picked_a);
+        }
+    }
+
+    mod import_proposal {
+        use k256::{ecdsa::SigningKey, U256};
+
+        use crate::week_1::{
+            ledger::LedgerConfig,
+            proof_of_stake::{
+                tests::{beacon_chain, proposer_key, validator_key},
+                BeaconChain, Proposal, StakeConfig, StakeError, Validator, DEPOSIT_ADDRESS,
+            },
+            proof_of_work::{signer_address, Block, Transaction},
+        };
+
+        #[test]
+        fn should_append_the_block_of_the_expected_proposer() {
+            let mut chain = beacon_chain();
+            let key = proposer_key(&chain, 1);
+
+            let proposal = chain.build_proposal(&key, 1, Vec::new());
+
+            assert_eq!(chain.import_proposal(proposal), Ok(()));
+            assert_eq!(chain.get_blocks().len(), 2);
+            assert_eq!(chain.get_head_slot(), 1);
+            assert_eq!(chain.get_balance(&signer_address(&key)), 50);
+        }
+
+        #[test]
+        fn should_reject_a_proposal_from_another_validator() {
+            let mut chain = beacon_chain();
+            let expected = chain.proposer(1).unwrap();
+            let key = (1..=3)
+                .map(validator_key)
+                .find(|key| signer_address(key) != expected)
+                .unwrap();
+
+            let proposal = chain.build_proposal(&key, 1, Vec::new());
+
+            assert_eq!(
+                chain.import_proposal(proposal),
+                Err(StakeError::WrongProposer {
+                    expected: Some(expected),
+                    found: signer_address(&key)
+                })
+            );
+        }
+
+        #[test]
+        fn should_slash_a_double_proposal() {
+            let mut chain = beacon_chain();
+            let key = proposer_key(&chain, 1);
+            let proposer = signer_address(&key);
+
+            let first = chain.build_proposal(&key, 1, Vec::new());
+            let conflicting = Block::new(
+                U256::ONE,
+                0,
+                U256::ONE,
+                first.block.parent_hash(),
+                12,
+                0,
+                proposer.clone(),
+                Vec::new(),
+            );
+
+            chain.import_proposal(first).unwrap();
+            let result = chain.import_proposal(Proposal::sign(&key, 1, conflicting));
+
+            assert!(matches!(result, Err(StakeError::DoubleProposal(_))));
+            assert_eq!(chain.get_slashings().len(), 1);
+
+            let validator = chain.get_validator(&proposer).unwrap();
+            assert!(validator.slashed);
+            assert_eq!(validator.stake, 50);
+            assert_eq!(chain.active_stake(), 200);
+        }
+
+        #[test]
+        fn should_register_a_stake_deposit() {
+            let depositor = SigningKey::from_bytes(&[9; 32]).unwrap();
+            let depositor_address = signer_address(&depositor);
+
+            let mut ledger_config = LedgerConfig::new();
+            ledger_config
+                .allocations
+                .insert(depositor_address.clone(), 100_000);
+
+            let validators = (1..=3)
+                .map(|seed| (signer_address(&validator_key(seed)), 100))
+                .collect();
+            let mut chain = BeaconChain::new(StakeConfig::new(), ledger_config, validators);
+
+            let deposit = Transaction::sign(&depositor, String::from(DEPOSIT_ADDRESS), 100, 1, 0);
+            let proposal = chain.build_proposal(&proposer_key(&chain, 1), 1, vec![deposit]);
+
+            chain.import_proposal(proposal).unwrap();
+
+            assert_eq!(
+                chain.get_validator(&depositor_address),
+                Some(Validator {
+                    stake: 100,
+                    slashed: false
+                })
+            );
+            assert_eq!(
+                chain.get_balance(&depositor_address),
+                100_000 - 100 - 21_000
+            );
+            assert_eq!(chain.active_stake(), 400);
+        }
+    }
+
+    mod finality {
+        use crate::week_1::proof_of_stake::{
+            tests::{beacon_chain, propose_slots, validator_key},
+            Attestation, StakeError,
+        };
+
+        #[test]
+        fn should_justify_a_checkpoint_with_two_thirds_of_the_stake() {
+            let mut chain = beacon_chain();
+            propose_slots(&mut chain, 1..=4);
+
+            let checkpoint = chain.checkpoint(1).unwrap();
+
+            for seed in 1..=2 {
+                chain
+                    .import_attestation(Attestation::sign(&validator_key(seed), checkpoint))
+                    .unwrap();
+            }
+
+            assert_eq!(chain.get_justified(), checkpoint);
+            assert_eq!(chain.get_finalized().epoch, 0);
+        }
+
+        #[test]
+        fn should_not_justify_a_checkpoint_with_a_third_of_the_stake() {
+            let mut chain = beacon_chain();
+            propose_slots(&mut chain, 1..=4);
+
+            let checkpoint = chain.checkpoint(1).unwrap();
+
+            chain
+                .import_attestation(Attestation::sign(&validator_key(1), checkpoint))
+                .unwrap();
+
+            assert_eq!(chain.get_justified().epoch, 0);
+        }
+
+        #[test]
+        fn should_finalize_after_two_consecutive_justified_checkpoints() {
+            let mut chain = beacon_chain();
+            propose_slots(&mut chain, 1..=8);
+
+            for epoch in 1..=2 {
+                let checkpoint = chain.checkpoint(epoch).unwrap();
+
+                for seed in 1..=3 {
+                    chain
+                        .import_attestation(Attestation::sign(&validator_key(seed), checkpoint))
+                        .unwrap();
+                }
+            }
+
+            assert_eq!(chain.get_justified().epoch, 2);
+            assert_eq!(chain.get_finalized(), chain.checkpoint(1).unwrap());
+        }
+
+        #[test]
+        fn should_reject_votes_for_unknown_or_future_checkpoints() {
+            let mut chain = beacon_chain();
+            propose_slots(&mut chain, 1..=4);
+
+            let mut checkpoint = chain.checkpoint(1).unwrap();
+            checkpoint.epoch = 2;
+
+            assert_eq!(
+                chain.import_attestation(Attestation::sign(&validator_key(1), checkpoint)),
+                Err(StakeError::UnknownCheckpoint)
+            );
+        }
+
+        #[test]
+        fn should_count_a_validator_once_per_epoch() {
+            let mut chain = beacon_chain();
+            propose_slots(&mut chain, 1..=4);
+
+            let attestation = Attestation::sign(&validator_key(1), chain.checkpoint(1).unwrap());
+
+            chain.import_attestation(attestation.clone()).unwrap();
+
+            assert_eq!(
+                chain.import_attestation(attestation),
+                Err(StakeError::AlreadyAttested)
+            );
+        }
+    }
+
+    mod compared_to_proof_of_work {
+        use k256::ecdsa::SigningKey;
+
+        use crate::week_1::{
+            ledger::LedgerConfig,
+            proof_of_stake::{
+                tests::{proposer_key, validator_key},
+                BeaconChain, StakeConfig,
+            },
+            proof_of_work::{signer_address, DifficultyConfig, Miner, Transaction},
+            transaction_pool::PoolConfig,
+        };
+
+        #[test]
+        fn should_reach_the_same_balances_from_the_same_transactions() {
+            let sender = SigningKey::from_bytes(&[9; 32]).unwrap();
+            let transactions: Vec<Transaction> = (0..3)
+                .map(|nonce| Transaction::sign(&sender, String::from("Niapa"), 10, 1, nonce))
+                .collect();
+
+            let mut ledger_config = LedgerConfig::new();
+            ledger_config
+                .allocations
+                .insert(signer_address(&sender), 100_000);
+
+            let mut miner = Miner::with_config(
+                DifficultyConfig::new(),
+                PoolConfig::new(),
+                ledger_config.clone(),
+            );
+            transactions
+                .iter()
+                .for_each(|transaction| miner.add_transaction(transaction.clone()).unwrap());
+            miner.mine();
+
+            let validators = (1..=3)
+                .map(|seed| (signer_address(&validator_key(seed)), 100))
+                .collect();
+            let mut chain = BeaconChain::new(StakeConfig::new(), ledger_config, validators);
+
+            let proposal = chain.build_proposal(&proposer_key(&chain, 1), 1, transactions);
+            chain.import_proposal(proposal).unwrap();
+
+            for address in ["Niapa", &signer_address(&sender)] {
+                assert_eq!(chain.get_balance(address), miner.get_balance(address));
+            }
+            assert_eq!(chain.get_balance("Niapa"), 30);
+        }
+    }
+}
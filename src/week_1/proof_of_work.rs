@@ -1,448 +1,3273 @@
-use k256::{elliptic_curve::bigint::ArrayEncoding, U256};
+use k256::{ecdsa::SigningKey, elliptic_curve::bigint::ArrayEncoding, U256};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::{collections::VecDeque, fmt::Display};
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::week_2::merkle_tree::{verify_proof, MerkleeTree, MerkleeTreeProofNode};
+
+use super::{
+    block_tree::{Branch, TreeBlock},
+    digital_signatures::{recover_public_key, sign_message_with_key, PublicKey},
+    encoding::{Decode, DecodeError, Decoder, Encode, Encoder},
+    ledger::{Ledger, LedgerConfig},
+    persistence::{read_snapshot, write_snapshot, SnapshotError, SnapshotFormat},
+    transaction_pool::{PoolConfig, PoolStats, TransactionPool},
+};
 
 // Build a Miner
+// Easiest target a block can be mined against, retargeting never goes above it
 const TARGET_DIFFICULTY: &str = "0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
-const MAX_TRANSACTIONS: u128 = 10;
+// Gas used by a value transfer, the only kind of transaction there is
+pub const TRANSFER_GAS: u64 = 21_000;
+// Rewards of miners that haven't set a beneficiary are burned
+const DEFAULT_BENEFICIARY: &str = "0000000000000000000000000000000000000000";
+
+// Difficulty Retargeting
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct DifficultyConfig {
+    // Number of blocks between two target adjustments
+    pub retarget_interval: usize,
+    // Desired time between blocks, in seconds
+    pub target_block_time: u64,
+    // Largest factor the target can move by in a single adjustment
+    pub max_adjustment: u64,
+    // Hash function block headers are mined with
+    pub header_hashing: HeaderHashing,
+}
+
+impl DifficultyConfig {
+    pub fn new() -> Self {
+        Self {
+            retarget_interval: 10,
+            target_block_time: 10,
+            max_adjustment: 4,
+            header_hashing: HeaderHashing::Sha256,
+        }
+    }
+}
+
+// Encodes a target in Bitcoin's compact "bits" format: one exponent byte followed by a
+// 3 byte mantissa. Targets with more precision than the mantissa can hold are truncated.
+pub fn target_to_bits(target: U256) -> u32 {
+    let mut size = target.bits_vartime().div_ceil(8);
+
+    let shifted = if size <= 3 {
+        target.shl_vartime(8 * (3 - size))
+    } else {
+        target.shr_vartime(8 * (size - 3))
+    };
+
+    let bytes = shifted.to_be_byte_array();
+    let mut mantissa = u32::from_be_bytes([0, bytes[29], bytes[30], bytes[31]]);
+
+    // The high mantissa bit is a sign bit, so the value moves one byte over to keep it positive
+    if mantissa & 0x0080_0000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+
+    ((size as u32) << 24) | mantissa
+}
+
+// Decodes compact "bits", rejecting negative and overflowing encodings
+pub fn bits_to_target(bits: u32) -> Option<U256> {
+    let size = (bits >> 24) as usize;
+    let mantissa = bits & 0x007f_ffff;
+
+    if bits & 0x0080_0000 != 0 && mantissa != 0 {
+        return None;
+    }
+
+    if size <= 3 {
+        return Some(U256::from(mantissa >> (8 * (3 - size))));
+    }
+
+    let target = U256::from(mantissa);
+
+    if mantissa != 0 && target.bits_vartime() + 8 * (size - 3) > 256 {
+        return None;
+    }
+
+    Some(target.shl_vartime(8 * (size - 3)))
+}
+
+fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+// Serializes a U256 as a hex string for human readable formats and as raw bytes otherwise
+mod u256_serde {
+    use k256::{elliptic_curve::bigint::ArrayEncoding, U256};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes = value.to_be_byte_array();
+
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(bytes))
+        } else {
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        let bytes = if deserializer.is_human_readable() {
+            hex::decode(String::deserialize(deserializer)?).map_err(D::Error::custom)?
+        } else {
+            Vec::<u8>::deserialize(deserializer)?
+        };
+
+        if bytes.len() != 32 {
+            return Err(D::Error::invalid_length(bytes.len(), &"32 bytes"));
+        }
+
+        Ok(U256::from_be_slice(&bytes))
+    }
+}
+
+// The sender isn't part of the transaction, it's the address recovered from the signature
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    to: String,
+    value: u128,
+    // Most the sender pays per unit of gas, base fee included
+    max_fee_per_gas: u128,
+    // Paid to the miner per unit of gas on top of the base fee, as far as the max fee allows.
+    // Blocks are filled with the best tipping transactions first.
+    max_priority_fee_per_gas: u128,
+    nonce: u64,
+    // r || s || recovery id
+    #[serde(with = "hex")]
+    signature: [u8; 65],
+    // Recovering the sender is expensive, so it's only done once per transaction
+    #[serde(skip)]
+    sender: OnceLock<Option<String>>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum TransactionError {
+    InvalidSignature,
+    AlreadyKnown,
+    AlreadyIncluded,
+    NonceTooLow {
+        expected: u64,
+        found: u64,
+    },
+    NonceTooHigh {
+        expected: u64,
+        found: u64,
+    },
+    InsufficientBalance {
+        balance: u128,
+        required: u128,
+    },
+    ReplacementUnderpriced {
+        minimum_max_fee: u128,
+        minimum_priority_fee: u128,
+    },
+    PriorityFeeAboveMaxFee,
+    MaxFeeBelowBaseFee {
+        base_fee: u128,
+        max_fee: u128,
+    },
+    PoolFull,
+}
+
+fn signing_payload(
+    to: &str,
+    value: u128,
+    max_fee_per_gas: u128,
+    max_priority_fee_per_gas: u128,
+    nonce: u64,
+) -> String {
+    format!(
+        "{{to:{},value:{},max_fee_per_gas:{},max_priority_fee_per_gas:{},nonce:{}}}",
+        to, value, max_fee_per_gas, max_priority_fee_per_gas, nonce
+    )
+}
+
+impl Transaction {
+    // Legacy pricing, the whole gas price is offered and whatever the base fee leaves is the tip
+    pub fn sign(
+        signing_key: &SigningKey,
+        to: String,
+        value: u128,
+        gas_price: u128,
+        nonce: u64,
+    ) -> Self {
+        Transaction::sign_with_fees(signing_key, to, value, gas_price, gas_price, nonce)
+    }
+
+    pub fn sign_with_fees(
+        signing_key: &SigningKey,
+        to: String,
+        value: u128,
+        max_fee_per_gas: u128,
+        max_priority_fee_per_gas: u128,
+        nonce: u64,
+    ) -> Self {
+        let payload = signing_payload(&to, value, max_fee_per_gas, max_priority_fee_per_gas, nonce);
+        let signature = sign_message_with_key(signing_key, payload.as_bytes());
+
+        // The signer's address is already known, so there's nothing to recover
+        let sender = signer_address(signing_key);
+
+        Self {
+            to,
+            value,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            nonce,
+            signature: signature.as_ref().try_into().unwrap(),
+            sender: OnceLock::from(Some(sender)),
+        }
+    }
+
+    pub fn sender(&self) -> Result<String, TransactionError> {
+        self.sender
+            .get_or_init(|| self.recover_sender())
+            .clone()
+            .ok_or(TransactionError::InvalidSignature)
+    }
+
+    pub fn to(&self) -> &str {
+        &self.to
+    }
+
+    pub fn value(&self) -> u128 {
+        self.value
+    }
+
+    pub fn max_fee_per_gas(&self) -> u128 {
+        self.max_fee_per_gas
+    }
+
+    pub fn max_priority_fee_per_gas(&self) -> u128 {
+        self.max_priority_fee_per_gas
+    }
+
+    pub fn gas(&self) -> u64 {
+        TRANSFER_GAS
+    }
+
+    // Tip per unit of gas at the given base fee, none if the max fee doesn't cover the base fee
+    pub fn effective_tip(&self, base_fee: u128) -> Option<u128> {
+        self.max_fee_per_gas
+            .checked_sub(base_fee)
+            .map(|headroom| headroom.min(self.max_priority_fee_per_gas))
+    }
+
+    // Most the transaction can cost the sender, which has to hold it upfront
+    pub fn max_cost(&self) -> u128 {
+        self.max_fee_per_gas
+            .saturating_mul(self.gas() as u128)
+            .saturating_add(self.value)
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    // SHA-256 of the canonical encoding, signature included
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(self.to_bytes()).into()
+    }
+
+    // Size of the transaction's canonical encoding, in bytes
+    pub fn size(&self) -> usize {
+        self.to_bytes().len()
+    }
+
+    fn recover_sender(&self) -> Option<String> {
+        let payload = signing_payload(
+            &self.to,
+            self.value,
+            self.max_fee_per_gas,
+            self.max_priority_fee_per_gas,
+            self.nonce,
+        );
+
+        recover_public_key(payload.as_bytes(), &self.signature)
+            .ok()
+            .map(|public_key| public_key.address().to_hex())
+    }
+}
+
+pub fn signer_address(signing_key: &SigningKey) -> String {
+    PublicKey::from(signing_key.verifying_key())
+        .address()
+        .to_hex()
+}
+
+// Address of the key that produced the hex encoded signature over the payload
+pub fn recover_signer(payload: &[u8], signature: &str) -> Option<String> {
+    let signature_bytes = hex::decode(signature).ok()?;
+
+    recover_public_key(payload, &signature_bytes)
+        .ok()
+        .map(|public_key| public_key.address().to_hex())
+}
+
+impl Display for Transaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{to:{},value:{},max_fee_per_gas:{},max_priority_fee_per_gas:{},nonce:{},signature:{}}}",
+            self.to,
+            self.value,
+            self.max_fee_per_gas,
+            self.max_priority_fee_per_gas,
+            self.nonce,
+            hex::encode(self.signature)
+        )
+    }
+}
+
+impl Encode for Transaction {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.string(&self.to);
+        encoder.u128(self.value);
+        encoder.u128(self.max_fee_per_gas);
+        encoder.u128(self.max_priority_fee_per_gas);
+        encoder.u64(self.nonce);
+        encoder.fixed(&self.signature);
+    }
+}
+
+impl Decode for Transaction {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Self {
+            to: decoder.string()?,
+            value: decoder.u128()?,
+            max_fee_per_gas: decoder.u128()?,
+            max_priority_fee_per_gas: decoder.u128()?,
+            nonce: decoder.u64()?,
+            signature: decoder.fixed()?,
+            sender: OnceLock::new(),
+        })
+    }
+}
+
+// Hash Functions
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum HeaderHashing {
+    // SHA-256 over the canonical encoding of the header
+    #[default]
+    Sha256,
+    // Double SHA-256 over an 80 byte Bitcoin header. The fields Bitcoin keeps in the coinbase
+    // transaction are committed to through the merkle root slot instead.
+    DoubleSha256,
+}
+
+impl HeaderHashing {
+    fn to_u8(self) -> u8 {
+        match self {
+            HeaderHashing::Sha256 => 0,
+            HeaderHashing::DoubleSha256 => 1,
+        }
+    }
+
+    fn from_u8(value: u8) -> Result<Self, DecodeError> {
+        match value {
+            0 => Ok(HeaderHashing::Sha256),
+            1 => Ok(HeaderHashing::DoubleSha256),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+
+    // Bytes the header hash is computed over
+    fn preimage(self, header: &BlockHeader) -> Vec<u8> {
+        match self {
+            HeaderHashing::Sha256 => header.to_bytes(),
+            HeaderHashing::DoubleSha256 => header.to_bitcoin_header().to_bytes().to_vec(),
+        }
+    }
+
+    // Overwrites the nonce in a preimage, so miners don't have to encode the header for every
+    // attempt. Nonces are searched in the 32 bit range, the higher bytes stay zero.
+    fn write_nonce(self, preimage: &mut [u8], nonce: u32) {
+        match self {
+            // After the hashing byte, at the end of the 32 byte big endian nonce
+            HeaderHashing::Sha256 => preimage[29..33].copy_from_slice(&nonce.to_be_bytes()),
+            HeaderHashing::DoubleSha256 => preimage[76..80].copy_from_slice(&nonce.to_le_bytes()),
+        }
+    }
+
+    fn digest(self, preimage: &[u8]) -> U256 {
+        match self {
+            HeaderHashing::Sha256 => U256::from_be_byte_array(Sha256::digest(preimage)),
+            HeaderHashing::DoubleSha256 => U256::from_le_slice(&double_sha256(preimage)),
+        }
+    }
+}
+
+pub fn double_sha256(data: impl AsRef<[u8]>) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+const BITCOIN_HEADER_VERSION: u32 = 1;
+
+// Integers are little endian and hashes are in their internal byte order, which is the reverse
+// of the one block explorers display
+#[derive(Clone, PartialEq, Debug)]
+pub struct BitcoinHeader {
+    pub version: u32,
+    pub prev_block: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BitcoinHeader {
+    pub fn to_bytes(&self) -> [u8; 80] {
+        let mut bytes = [0; 80];
+
+        bytes[..4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[4..36].copy_from_slice(&self.prev_block);
+        bytes[36..68].copy_from_slice(&self.merkle_root);
+        bytes[68..72].copy_from_slice(&self.time.to_le_bytes());
+        bytes[72..76].copy_from_slice(&self.bits.to_le_bytes());
+        bytes[76..].copy_from_slice(&self.nonce.to_le_bytes());
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; 80]) -> Self {
+        let u32_at = |idx: usize| u32::from_le_bytes(bytes[idx..idx + 4].try_into().unwrap());
+
+        Self {
+            version: u32_at(0),
+            prev_block: bytes[4..36].try_into().unwrap(),
+            merkle_root: bytes[36..68].try_into().unwrap(),
+            time: u32_at(68),
+            bits: u32_at(72),
+            nonce: u32_at(76),
+        }
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        double_sha256(self.to_bytes())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Block {
+    #[serde(with = "u256_serde")]
+    nonce: U256,
+    extranonce: u64,
+    #[serde(with = "u256_serde")]
+    id: U256,
+    #[serde(with = "u256_serde")]
+    parent_hash: U256,
+    timestamp: u64,
+    bits: u32,
+    // Address the block reward and the tips are paid to
+    beneficiary: String,
+    // Burned per unit of gas by every transaction in the block
+    base_fee: u128,
+    gas_used: u64,
+    #[serde(with = "hex")]
+    transactions_root: [u8; 32],
+    hashing: HeaderHashing,
+    #[serde(with = "u256_serde")]
+    hash: U256,
+    transactions: Vec<Transaction>,
+}
+
+// Transactions are hashed into the tree by their canonical encoding
+fn transaction_leaf(transaction: &Transaction) -> String {
+    hex::encode(transaction.to_bytes())
+}
+
+fn transactions_root(transactions: &[Transaction]) -> [u8; 32] {
+    let root = MerkleeTree::new(transactions.iter().map(transaction_leaf).collect()).get_root();
+
+    hex::decode(root)
+        .ok()
+        .and_then(|root| root.try_into().ok())
+        .expect("the root is a hex encoded keccak256 hash")
+}
+
+fn gas_used(transactions: &[Transaction]) -> u64 {
+    transactions.iter().map(Transaction::gas).sum()
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BlockHeader {
+    #[serde(with = "u256_serde")]
+    nonce: U256,
+    extranonce: u64,
+    #[serde(with = "u256_serde")]
+    id: U256,
+    #[serde(with = "u256_serde")]
+    parent_hash: U256,
+    timestamp: u64,
+    bits: u32,
+    beneficiary: String,
+    base_fee: u128,
+    gas_used: u64,
+    #[serde(with = "hex")]
+    transactions_root: [u8; 32],
+    hashing: HeaderHashing,
+    #[serde(with = "u256_serde")]
+    hash: U256,
+}
+
+impl BlockHeader {
+    pub fn to_hash(&self) -> U256 {
+        self.hashing.digest(&self.hashing.preimage(self))
+    }
+
+    // Bitcoin's header only has room for 32 bit nonces and timestamps, larger ones are truncated
+    // and rejected by validation
+    pub fn to_bitcoin_header(&self) -> BitcoinHeader {
+        let mut commitment = Encoder::new();
+        commitment.fixed(&self.id.to_be_byte_array());
+        commitment.u64(self.extranonce);
+        commitment.string(&self.beneficiary);
+        commitment.u128(self.base_fee);
+        commitment.u64(self.gas_used);
+        commitment.fixed(&self.transactions_root);
+
+        BitcoinHeader {
+            version: BITCOIN_HEADER_VERSION,
+            prev_block: self.parent_hash.to_le_byte_array().into(),
+            merkle_root: double_sha256(commitment.into_bytes()),
+            time: self.timestamp as u32,
+            bits: self.bits,
+            nonce: self.nonce.to_le_byte_array()[..4]
+                .try_into()
+                .map(u32::from_le_bytes)
+                .unwrap(),
+        }
+    }
+
+    pub fn hash(&self) -> U256 {
+        self.hash
+    }
+
+    pub fn base_fee(&self) -> u128 {
+        self.base_fee
+    }
+
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    pub fn transactions_root(&self) -> [u8; 32] {
+        self.transactions_root
+    }
+
+    pub fn hashing(&self) -> HeaderHashing {
+        self.hashing
+    }
+}
+
+// The hash isn't part of the encoding, it is derived from the other fields
+impl Encode for BlockHeader {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u8(self.hashing.to_u8());
+        encoder.fixed(&self.nonce.to_be_byte_array());
+        encoder.u64(self.extranonce);
+        encoder.fixed(&self.id.to_be_byte_array());
+        encoder.fixed(&self.parent_hash.to_be_byte_array());
+        encoder.u64(self.timestamp);
+        encoder.u32(self.bits);
+        encoder.string(&self.beneficiary);
+        encoder.u128(self.base_fee);
+        encoder.u64(self.gas_used);
+        encoder.fixed(&self.transactions_root);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        let mut header = Self {
+            hashing: HeaderHashing::from_u8(decoder.u8()?)?,
+            nonce: U256::from_be_byte_array(decoder.fixed::<32>()?.into()),
+            extranonce: decoder.u64()?,
+            id: U256::from_be_byte_array(decoder.fixed::<32>()?.into()),
+            parent_hash: U256::from_be_byte_array(decoder.fixed::<32>()?.into()),
+            timestamp: decoder.u64()?,
+            bits: decoder.u32()?,
+            beneficiary: decoder.string()?,
+            base_fee: decoder.u128()?,
+            gas_used: decoder.u64()?,
+            transactions_root: decoder.fixed()?,
+            hash: U256::ZERO,
+        };
+
+        header.hash = header.to_hash();
+
+        Ok(header)
+    }
+}
+
+// Checks that the transaction is in the block using nothing but its header and the proof
+pub fn verify_transaction_proof(
+    header: &BlockHeader,
+    transaction: &Transaction,
+    proof: &[MerkleeTreeProofNode],
+) -> bool {
+    header.hash == header.to_hash()
+        && verify_proof(
+            transaction_leaf(transaction),
+            proof,
+            &hex::encode(header.transactions_root),
+        )
+}
+
+impl Block {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        nonce: U256,
+        extranonce: u64,
+        id: U256,
+        parent_hash: U256,
+        timestamp: u64,
+        bits: u32,
+        beneficiary: String,
+        transactions: Vec<Transaction>,
+    ) -> Self {
+        let mut block = Self {
+            nonce,
+            extranonce,
+            id,
+            parent_hash,
+            timestamp,
+            bits,
+            beneficiary,
+            base_fee: 0,
+            gas_used: gas_used(&transactions),
+            transactions_root: transactions_root(&transactions),
+            hashing: HeaderHashing::default(),
+            hash: U256::ZERO,
+            transactions,
+        };
+
+        block.hash = block.to_hash();
+
+        block
+    }
+
+    pub fn to_hash(&self) -> U256 {
+        self.header().to_hash()
+    }
+
+    // Everything but the transactions, which the header commits to through their Merkle root
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            nonce: self.nonce,
+            extranonce: self.extranonce,
+            id: self.id,
+            parent_hash: self.parent_hash,
+            timestamp: self.timestamp,
+            bits: self.bits,
+            beneficiary: self.beneficiary.clone(),
+            base_fee: self.base_fee,
+            gas_used: self.gas_used,
+            transactions_root: self.transactions_root,
+            hashing: self.hashing,
+            hash: self.hash,
+        }
+    }
+
+    // Rehashes the block with the given function
+    pub fn with_hashing(mut self, hashing: HeaderHashing) -> Self {
+        self.hashing = hashing;
+        self.hash = self.to_hash();
+
+        self
+    }
+
+    // Rehashes the block with the given base fee
+    pub fn with_base_fee(mut self, base_fee: u128) -> Self {
+        self.base_fee = base_fee;
+        self.hash = self.to_hash();
+
+        self
+    }
+
+    pub fn hash(&self) -> U256 {
+        self.hash
+    }
+
+    pub fn id(&self) -> U256 {
+        self.id
+    }
+
+    pub fn parent_hash(&self) -> U256 {
+        self.parent_hash
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn extranonce(&self) -> u64 {
+        self.extranonce
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn beneficiary(&self) -> &str {
+        &self.beneficiary
+    }
+
+    pub fn base_fee(&self) -> u128 {
+        self.base_fee
+    }
+
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    pub fn transactions_root(&self) -> [u8; 32] {
+        self.transactions_root
+    }
+
+    // Expected number of hashes needed to find a block at this target, 2^256 / (target + 1).
+    // It's computed as !target / (target + 1) + 1 so that it fits in 256 bits.
+    pub fn work(&self) -> u128 {
+        let target = bits_to_target(self.bits).unwrap_or(U256::MAX);
+
+        if target == U256::MAX {
+            return 1;
+        }
+
+        let work = target
+            .not()
+            .wrapping_div(&target.wrapping_add(&U256::ONE))
+            .wrapping_add(&U256::ONE);
+
+        let bytes = work.to_be_byte_array();
+
+        if bytes[..16].iter().any(|byte| *byte != 0) {
+            return u128::MAX;
+        }
+
+        u128::from_be_bytes(bytes[16..].try_into().unwrap())
+    }
+}
+
+impl Encode for Block {
+    fn encode(&self, encoder: &mut Encoder) {
+        self.header().encode(encoder);
+        encoder.sequence(&self.transactions);
+    }
+}
+
+impl Decode for Block {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        let header = BlockHeader::decode(decoder)?;
+
+        Ok(Self {
+            nonce: header.nonce,
+            extranonce: header.extranonce,
+            id: header.id,
+            parent_hash: header.parent_hash,
+            timestamp: header.timestamp,
+            bits: header.bits,
+            beneficiary: header.beneficiary,
+            base_fee: header.base_fee,
+            gas_used: header.gas_used,
+            transactions_root: header.transactions_root,
+            hashing: header.hashing,
+            hash: header.hash,
+            transactions: decoder.sequence()?,
+        })
+    }
+}
+
+impl TreeBlock for Block {
+    type Hash = U256;
+
+    fn hash(&self) -> U256 {
+        self.hash
+    }
+
+    fn parent_hash(&self) -> U256 {
+        self.parent_hash
+    }
+
+    fn work(&self) -> u128 {
+        Block::work(self)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum InvalidBlockReason {
+    UnexpectedId,
+    UnexpectedHeaderHashing,
+    HeaderFieldOutOfRange,
+    HashMismatch,
+    TransactionsRootMismatch,
+    ParentHashMismatch,
+    TimestampBeforeParent,
+    UnexpectedBits {
+        expected: u32,
+        found: u32,
+    },
+    HashAboveTarget,
+    UnexpectedBaseFee {
+        expected: u128,
+        found: u128,
+    },
+    GasUsedMismatch,
+    GasLimitExceeded,
+    InvalidTransaction {
+        index: usize,
+        error: TransactionError,
+    },
+}
+
+#[derive(PartialEq, Debug)]
+pub struct InvalidBlock {
+    index: usize,
+    reason: InvalidBlockReason,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ChainReplacementError {
+    InvalidCandidate(InvalidBlock),
+    InsufficientWork { current: u128, candidate: u128 },
+}
+
+// Blocks that left the canonical chain and the blocks that replaced them, both in chain order
+pub struct ChainReorg {
+    pub reverted: Vec<Block>,
+    pub adopted: Vec<Block>,
+}
+
+// Blocks a new block builds on, indexed by height, so a block tree branch can be validated
+// against without copying it into a chain
+trait Parents {
+    fn count(&self) -> usize;
+
+    fn at(&self, height: usize) -> &Block;
+
+    fn last(&self) -> Option<&Block> {
+        self.count().checked_sub(1).map(|height| self.at(height))
+    }
+}
+
+impl Parents for [Block] {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn at(&self, height: usize) -> &Block {
+        &self[height]
+    }
+}
+
+impl Parents for Branch<'_, Block, Ledger> {
+    fn count(&self) -> usize {
+        self.height() as usize + 1
+    }
+
+    fn at(&self, height: usize) -> &Block {
+        self.get(height as u64).unwrap()
+    }
+}
+
+// Computes the bits a block at the given height has to be mined with, based on the blocks below it.
+// The target only moves on retarget heights, towards the configured block time.
+fn next_bits(blocks: &(impl Parents + ?Sized), height: usize, config: &DifficultyConfig) -> u32 {
+    let max_target = U256::from_be_hex(TARGET_DIFFICULTY);
+
+    if height == 0 {
+        return target_to_bits(max_target);
+    }
+
+    let previous_bits = blocks.at(height - 1).bits;
+    let interval = config.retarget_interval.max(2);
+
+    if !height.is_multiple_of(interval) {
+        return previous_bits;
+    }
+
+    let first_block = blocks.at(height - interval);
+    let last_block = blocks.at(height - 1);
+
+    // A window of N blocks only spans N - 1 block times
+    let expected_timespan = config.target_block_time.max(1) * (interval as u64 - 1);
+    let max_adjustment = config.max_adjustment.max(1);
+
+    let actual_timespan = last_block
+        .timestamp
+        .saturating_sub(first_block.timestamp)
+        .clamp(
+            expected_timespan / max_adjustment,
+            expected_timespan * max_adjustment,
+        )
+        .max(1);
+
+    let previous_target = bits_to_target(previous_bits).unwrap_or(max_target);
+
+    // Dividing first keeps the product inside 256 bits, the precision lost is far below what
+    // the compact encoding can represent anyway
+    let new_target = previous_target
+        .wrapping_div(&U256::from(expected_timespan))
+        .wrapping_mul(&U256::from(actual_timespan));
+
+    target_to_bits(new_target.min(max_target))
+}
+
+// Base fee of the block on top of the given parent. The first block uses the configured one.
+fn next_base_fee(parent: Option<&Block>, config: &LedgerConfig) -> u128 {
+    parent.map_or(config.initial_base_fee, |parent| {
+        config.next_base_fee(parent.base_fee, parent.gas_used)
+    })
+}
+
+// Checks a block against the chain it builds on, first the header and then the body
+fn validate_block(
+    parents: &(impl Parents + ?Sized),
+    block: &Block,
+    config: &DifficultyConfig,
+    ledger_config: &LedgerConfig,
+    ledger: &mut Ledger,
+) -> Result<(), InvalidBlockReason> {
+    let height = parents.count();
+
+    if block.id != U256::from(height as u128) {
+        return Err(InvalidBlockReason::UnexpectedId);
+    }
+
+    if block.hashing != config.header_hashing {
+        return Err(InvalidBlockReason::UnexpectedHeaderHashing);
+    }
+
+    // Bitcoin headers would silently drop the bits that don't fit
+    let fits_bitcoin_header =
+        block.nonce <= U256::from(u32::MAX) && block.timestamp <= u32::MAX as u64;
+
+    if block.hashing == HeaderHashing::DoubleSha256 && !fits_bitcoin_header {
+        return Err(InvalidBlockReason::HeaderFieldOutOfRange);
+    }
+
+    if block.hash != block.to_hash() {
+        return Err(InvalidBlockReason::HashMismatch);
+    }
+
+    if block.transactions_root != transactions_root(&block.transactions) {
+        return Err(InvalidBlockReason::TransactionsRootMismatch);
+    }
+
+    let parent = parents.last();
+
+    if block.parent_hash != parent.map_or(U256::ZERO, |parent| parent.hash) {
+        return Err(InvalidBlockReason::ParentHashMismatch);
+    }
+
+    if parent.is_some_and(|parent| block.timestamp < parent.timestamp) {
+        return Err(InvalidBlockReason::TimestampBeforeParent);
+    }
+
+    let expected_bits = next_bits(parents, height, config);
+
+    if block.bits != expected_bits {
+        return Err(InvalidBlockReason::UnexpectedBits {
+            expected: expected_bits,
+            found: block.bits,
+        });
+    }
+
+    let target = bits_to_target(block.bits).unwrap();
+
+    if !target.gt(&block.hash) {
+        return Err(InvalidBlockReason::HashAboveTarget);
+    }
+
+    let expected_base_fee = next_base_fee(parent, ledger_config);
+
+    if block.base_fee != expected_base_fee {
+        return Err(InvalidBlockReason::UnexpectedBaseFee {
+            expected: expected_base_fee,
+            found: block.base_fee,
+        });
+    }
+
+    if block.gas_used != gas_used(&block.transactions) {
+        return Err(InvalidBlockReason::GasUsedMismatch);
+    }
+
+    if block.gas_used > ledger_config.gas_limit {
+        return Err(InvalidBlockReason::GasLimitExceeded);
+    }
+
+    apply_block(ledger, block, ledger_config)
+}
+
+// Applies the block's transactions in order, then pays the reward and the tips to its beneficiary.
+// The base fee part of the fees is burned.
+fn apply_block(
+    ledger: &mut Ledger,
+    block: &Block,
+    config: &LedgerConfig,
+) -> Result<(), InvalidBlockReason> {
+    let mut tips: u128 = 0;
+
+    for (idx, transaction) in block.transactions.iter().enumerate() {
+        let tip = ledger
+            .apply_transaction(transaction, block.base_fee)
+            .map_err(|error| InvalidBlockReason::InvalidTransaction { index: idx, error })?;
+
+        tips = tips.saturating_add(tip);
+    }
+
+    ledger.credit(&block.beneficiary, config.block_reward.saturating_add(tips));
+
+    Ok(())
+}
+
+fn validate_blocks(
+    blocks: &[Block],
+    config: &DifficultyConfig,
+    ledger_config: &LedgerConfig,
+) -> Result<(), InvalidBlock> {
+    let mut ledger = Ledger::genesis(ledger_config);
+
+    for (idx, block) in blocks.iter().enumerate() {
+        validate_block(&blocks[..idx], block, config, ledger_config, &mut ledger)
+            .map_err(|reason| InvalidBlock { index: idx, reason })?;
+    }
+
+    Ok(())
+}
+
+// State after applying the given blocks, which are expected to be valid
+fn ledger_at(blocks: &[Block], config: &LedgerConfig) -> Ledger {
+    let mut ledger = Ledger::genesis(config);
+
+    for block in blocks {
+        let _ = apply_block(&mut ledger, block, config);
+    }
+
+    ledger
+}
+
+fn cumulative_work(blocks: &[Block]) -> u128 {
+    blocks.iter().map(Block::work).sum()
+}
+
+// Everything about the next block except the values the miner searches over
+struct BlockTemplate {
+    id: U256,
+    parent_hash: U256,
+    timestamp: u64,
+    bits: u32,
+    target: U256,
+    beneficiary: String,
+    base_fee: u128,
+    gas_used: u64,
+    // Computed once, it doesn't change between nonces
+    transactions_root: [u8; 32],
+    hashing: HeaderHashing,
+    transactions: Vec<Transaction>,
+}
+
+impl BlockTemplate {
+    fn header(&self, nonce: u32, extranonce: u64) -> BlockHeader {
+        BlockHeader {
+            nonce: U256::from(nonce),
+            extranonce,
+            id: self.id,
+            parent_hash: self.parent_hash,
+            timestamp: self.timestamp,
+            bits: self.bits,
+            beneficiary: self.beneficiary.clone(),
+            base_fee: self.base_fee,
+            gas_used: self.gas_used,
+            transactions_root: self.transactions_root,
+            hashing: self.hashing,
+            hash: U256::ZERO,
+        }
+    }
+
+    // Only called once a hash below the target is found
+    fn seal(&self, nonce: u32, extranonce: u64, hash: U256) -> Block {
+        Block {
+            nonce: U256::from(nonce),
+            extranonce,
+            id: self.id,
+            parent_hash: self.parent_hash,
+            timestamp: self.timestamp,
+            bits: self.bits,
+            beneficiary: self.beneficiary.clone(),
+            base_fee: self.base_fee,
+            gas_used: self.gas_used,
+            transactions_root: self.transactions_root,
+            hashing: self.hashing,
+            hash,
+            transactions: self.transactions.clone(),
+        }
+    }
+
+    // Searches nonces in the given range, rolling the extranonce every time the range runs out.
+    // The header is encoded once per extranonce, each attempt only rewrites the nonce bytes.
+    fn search(
+        &self,
+        nonces: std::ops::Range<u64>,
+        stop: &AtomicBool,
+        cancellation: &CancellationHandle,
+    ) -> SearchOutcome {
+        let mut outcome = SearchOutcome {
+            block: None,
+            hashes: 0,
+            extranonce_rolls: 0,
+        };
+
+        for extranonce in 0.. {
+            let mut preimage = self.hashing.preimage(&self.header(0, extranonce));
+
+            for nonce in nonces.clone() {
+                if stop.load(Ordering::Relaxed) || cancellation.is_cancelled() {
+                    return outcome;
+                }
+
+                self.hashing.write_nonce(&mut preimage, nonce as u32);
+                let hash = self.hashing.digest(&preimage);
+                outcome.hashes += 1;
+
+                if self.target.gt(&hash) {
+                    stop.store(true, Ordering::Relaxed);
+                    outcome.block = Some(self.seal(nonce as u32, extranonce, hash));
+
+                    return outcome;
+                }
+            }
+
+            outcome.extranonce_rolls += 1;
+        }
+
+        outcome
+    }
+}
+
+struct SearchOutcome {
+    block: Option<Block>,
+    hashes: u64,
+    extranonce_rolls: u64,
+}
+
+// Parallel Mining
+#[derive(Clone, Default)]
+pub struct CancellationHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MiningOptions {
+    pub threads: usize,
+    // Highest nonce tried before the extranonce is rolled
+    pub max_nonce: u32,
+}
+
+impl MiningOptions {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads,
+            max_nonce: u32::MAX,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MiningStats {
+    pub hashes: u64,
+    pub extranonce_rolls: u64,
+    pub elapsed: Duration,
+    // Hashes per second over the whole search
+    pub hashrate: f64,
+}
+
+#[derive(Debug)]
+pub enum MiningError {
+    Cancelled(MiningStats),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Miner {
+    mempool: TransactionPool,
+    blocks: Vec<Block>,
+    difficulty: DifficultyConfig,
+    ledger_config: LedgerConfig,
+    beneficiary: String,
+    // Derived from the blocks, kept up to date as they are appended and rebuilt when they are
+    // replaced or loaded
+    #[serde(skip)]
+    ledger: Ledger,
+    #[serde(skip)]
+    included: HashSet<[u8; 32]>,
+}
+
+impl Miner {
+    pub fn new() -> Self {
+        Miner::with_difficulty(DifficultyConfig::new())
+    }
+
+    pub fn with_difficulty(difficulty: DifficultyConfig) -> Self {
+        Miner::with_config(difficulty, PoolConfig::new(), LedgerConfig::new())
+    }
+
+    pub fn with_config(
+        difficulty: DifficultyConfig,
+        pool: PoolConfig,
+        ledger_config: LedgerConfig,
+    ) -> Self {
+        Miner {
+            mempool: TransactionPool::new(pool),
+            blocks: Vec::new(),
+            difficulty,
+            ledger: Ledger::genesis(&ledger_config),
+            ledger_config,
+            beneficiary: String::from(DEFAULT_BENEFICIARY),
+            included: HashSet::new(),
+        }
+    }
+
+    // Address the blocks mined from now on pay their reward and fees to
+    pub fn set_beneficiary(&mut self, address: String) {
+        self.beneficiary = address;
+    }
+
+    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), TransactionError> {
+        self.add_transaction_at(transaction, current_timestamp())
+    }
+
+    // Only accepts transactions with a valid signature that continue the sender's nonce sequence,
+    // or that pay enough to replace the pending one using the same nonce. The sender has to be
+    // able to pay for it on top of its pending transactions.
+    pub fn add_transaction_at(
+        &mut self,
+        transaction: Transaction,
+        now: u64,
+    ) -> Result<(), TransactionError> {
+        let sender = transaction.sender()?;
+
+        if self.mempool.contains(&transaction) {
+            return Err(TransactionError::AlreadyKnown);
+        }
+
+        if self.included.contains(&transaction.hash()) {
+            return Err(TransactionError::AlreadyIncluded);
+        }
+
+        let account = self.ledger.account(&sender);
+        if transaction.max_priority_fee_per_gas > transaction.max_fee_per_gas {
+            return Err(TransactionError::PriorityFeeAboveMaxFee);
+        }
+
+        let required = self
+            .mempool
+            .pending_cost(&sender, transaction.nonce)
+            .saturating_add(transaction.max_cost());
+
+        if account.balance < required {
+            return Err(TransactionError::InsufficientBalance {
+                balance: account.balance,
+                required,
+            });
+        }
+
+        self.mempool
+            .insert(transaction, sender, account.nonce, now)?;
+
+        Ok(())
+    }
+
+    // Nonce the sender's next transaction has to use, counting the ones waiting in the mempool
+    pub fn get_next_nonce(&self, sender: &str) -> u64 {
+        self.mempool
+            .pending_nonce(sender)
+            .unwrap_or_else(|| self.ledger.nonce(sender))
+    }
+
+    pub fn mine(&mut self) {
+        self.mine_at(current_timestamp())
+    }
+
+    // Mines the next block with the given timestamp, which can't go below the parent's one
+    pub fn mine_at(&mut self, timestamp: u64) {
+        let template = self.next_template(timestamp);
+
+        let outcome = template.search(
+            0..u32::MAX as u64 + 1,
+            &AtomicBool::new(false),
+            &CancellationHandle::new(),
+        );
+
+        self.append_block(outcome.block.unwrap());
+    }
+
+    // Splits the nonce range between the worker threads. Each worker rolls its own extranonce when
+    // its share runs out, the nonce ranges don't overlap so neither do the searched headers.
+    pub fn mine_parallel(
+        &mut self,
+        options: MiningOptions,
+        cancellation: &CancellationHandle,
+    ) -> Result<MiningStats, MiningError> {
+        let template = self.next_template(current_timestamp());
+        let threads = options.threads.max(1) as u64;
+        let nonce_count = options.max_nonce as u64 + 1;
+        let chunk_size = nonce_count.div_ceil(threads);
+
+        let stop = AtomicBool::new(false);
+        let started_at = Instant::now();
+
+        let outcomes: Vec<SearchOutcome> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|worker| worker * chunk_size..((worker + 1) * chunk_size).min(nonce_count))
+                .filter(|nonces| !nonces.is_empty())
+                .map(|nonces| {
+                    let template = &template;
+                    let stop = &stop;
+
+                    scope.spawn(move || template.search(nonces, stop, cancellation))
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        let elapsed = started_at.elapsed();
+        let hashes = outcomes.iter().map(|outcome| outcome.hashes).sum();
+
+        let stats = MiningStats {
+            hashes,
+            extranonce_rolls: outcomes
+                .iter()
+                .map(|outcome| outcome.extranonce_rolls)
+                .sum(),
+            elapsed,
+            hashrate: hashes as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        };
+
+        match outcomes.into_iter().find_map(|outcome| outcome.block) {
+            Some(block) => {
+                self.append_block(block);
+
+                Ok(stats)
+            }
+            None => Err(MiningError::Cancelled(stats)),
+        }
+    }
+
+    // Builds the header to mine on from the best paying transactions in the mempool. They stay
+    // pooled until a block including them is appended. The timestamp can't go below the parent's one.
+    fn next_template(&self, timestamp: u64) -> BlockTemplate {
+        let timestamp = self
+            .blocks
+            .last()
+            .map_or(timestamp, |parent| timestamp.max(parent.timestamp));
+        let bits = self.get_next_bits();
+
+        let base_fee = self.get_next_base_fee();
+
+        // Transactions the sender can no longer pay for after a reorg are left out
+        let mut ledger = self.ledger.clone();
+        let mut transactions = self.mempool.best_transactions(
+            self.ledger_config.gas_limit,
+            base_fee,
+            &ledger.nonces(),
+        );
+
+        transactions.retain(|transaction| ledger.apply_transaction(transaction, base_fee).is_ok());
+
+        BlockTemplate {
+            id: U256::from(self.get_block_height() as u128),
+            parent_hash: self.blocks.last().map_or(U256::ZERO, |parent| parent.hash),
+            timestamp,
+            bits,
+            target: bits_to_target(bits).unwrap(),
+            beneficiary: self.beneficiary.clone(),
+            base_fee,
+            gas_used: gas_used(&transactions),
+            transactions_root: transactions_root(&transactions),
+            hashing: self.difficulty.header_hashing,
+            transactions,
+        }
+    }
+
+    pub fn get_block_height(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn get_block_by_block_number(&self, idx: usize) -> Option<&Block> {
+        self.blocks.get(idx)
+    }
+
+    pub fn get_mempool_size(&self) -> usize {
+        self.mempool.len()
+    }
+
+    pub fn get_mempool_stats(&self) -> PoolStats {
+        self.mempool.stats()
+    }
+
+    pub fn get_blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn get_header(&self, idx: usize) -> Option<BlockHeader> {
+        self.blocks.get(idx).map(Block::header)
+    }
+
+    // Merkle proof that the transaction at the given index is included in the block
+    pub fn get_transaction_proof(
+        &self,
+        idx: usize,
+        transaction_idx: usize,
+    ) -> Option<Vec<MerkleeTreeProofNode>> {
+        let block = self.blocks.get(idx)?;
+
+        if transaction_idx >= block.transactions.len() {
+            return None;
+        }
+
+        let leaves = block.transactions.iter().map(transaction_leaf).collect();
+
+        Some(MerkleeTree::new(leaves).get_proof(transaction_idx))
+    }
+
+    // State after applying the block with the given number
+    pub fn get_state_at(&self, idx: usize) -> Option<Ledger> {
+        (idx < self.blocks.len()).then(|| ledger_at(&self.blocks[..=idx], &self.ledger_config))
+    }
+
+    pub fn get_balance(&self, address: &str) -> u128 {
+        self.ledger.balance(address)
+    }
+
+    pub fn get_balance_at(&self, address: &str, idx: usize) -> Option<u128> {
+        self.get_state_at(idx).map(|ledger| ledger.balance(address))
+    }
+
+    // Nonce of the account's next transaction according to the chain, without the mempool
+    pub fn get_nonce(&self, address: &str) -> u64 {
+        self.ledger.nonce(address)
+    }
+
+    pub fn get_nonce_at(&self, address: &str, idx: usize) -> Option<u64> {
+        self.get_state_at(idx).map(|ledger| ledger.nonce(address))
+    }
+
+    pub fn get_next_bits(&self) -> u32 {
+        next_bits(self.blocks.as_slice(), self.blocks.len(), &self.difficulty)
+    }
+
+    pub fn get_next_base_fee(&self) -> u128 {
+        next_base_fee(self.blocks.last(), &self.ledger_config)
+    }
+
+    // Total base fee burned by the chain
+    pub fn get_burned(&self) -> u128 {
+        self.ledger.burned()
+    }
+
+    pub fn validate_chain(&self) -> Result<(), InvalidBlock> {
+        validate_blocks(&self.blocks, &self.difficulty, &self.ledger_config)
+    }
+
+    pub fn cumulative_work(&self) -> u128 {
+        cumulative_work(&self.blocks)
+    }
+
+    // Checks a block on top of a block tree branch with our rules, starting from the state of the
+    // branch's tip. Returns the state after the block.
+    pub fn check_block(
+        &self,
+        branch: &Branch<Block, Ledger>,
+        block: &Block,
+    ) -> Result<Ledger, InvalidBlockReason> {
+        let mut ledger = branch.state().clone();
+
+        validate_block(
+            branch,
+            block,
+            &self.difficulty,
+            &self.ledger_config,
+            &mut ledger,
+        )?;
+
+        Ok(ledger)
+    }
+
+    // Appends a block mined by someone else on top of our tip, after checking its header and body.
+    // Transactions it includes are dropped from the mempool.
+    pub fn import_block(&mut self, block: Block) -> Result<(), InvalidBlock> {
+        validate_block(
+            self.blocks.as_slice(),
+            &block,
+            &self.difficulty,
+            &self.ledger_config,
+            &mut self.ledger.clone(),
+        )
+        .map_err(|reason| InvalidBlock {
+            index: self.blocks.len(),
+            reason,
+        })?;
+
+        self.append_block(block);
+
+        Ok(())
+    }
+
+    // The block is expected to be valid on top of the tip
+    fn append_block(&mut self, block: Block) {
+        let _ = apply_block(&mut self.ledger, &block, &self.ledger_config);
+
+        self.included
+            .extend(block.transactions.iter().map(Transaction::hash));
+        self.blocks.push(block);
+        self.mempool.remove_confirmed(&self.ledger.nonces());
+    }
+
+    // Recomputes the tip state and the included transactions from the blocks
+    fn rebuild_state(&mut self) {
+        self.ledger = ledger_at(&self.blocks, &self.ledger_config);
+        self.included = self
+            .blocks
+            .iter()
+            .flat_map(|block| block.transactions.iter().map(Transaction::hash))
+            .collect();
+    }
+
+    pub fn replace_chain(
+        &mut self,
+        candidate: Vec<Block>,
+    ) -> Result<ChainReorg, ChainReplacementError> {
+        self.replace_chain_at(candidate, current_timestamp())
+    }
+
+    // Adopts the candidate chain if it is valid and carries more work than ours. Transactions from
+    // reverted blocks that the new chain doesn't include go back to the mempool, which is rebuilt
+    // against the new chain's nonces at the given time.
+    pub fn replace_chain_at(
+        &mut self,
+        candidate: Vec<Block>,
+        now: u64,
+    ) -> Result<ChainReorg, ChainReplacementError> {
+        validate_blocks(&candidate, &self.difficulty, &self.ledger_config)
+            .map_err(ChainReplacementError::InvalidCandidate)?;
+
+        let current_work = self.cumulative_work();
+        let candidate_work = cumulative_work(&candidate);
+
+        if candidate_work <= current_work {
+            return Err(ChainReplacementError::InsufficientWork {
+                current: current_work,
+                candidate: candidate_work,
+            });
+        }
+
+        let fork_point = self
+            .blocks
+            .iter()
+            .zip(candidate.iter())
+            .take_while(|(current, candidate)| current.hash == candidate.hash)
+            .count();
+
+        let adopted = candidate[fork_point..].to_vec();
+        let reverted = self.blocks.split_off(fork_point);
+
+        self.blocks = candidate;
+        self.rebuild_state();
+
+        let mut pending: Vec<Transaction> = reverted
+            .iter()
+            .flat_map(|block| block.transactions.iter().cloned())
+            .chain(self.mempool.take_all())
+            .collect();
+
+        // Inserting in nonce order keeps each sender's sequence gapless, the transactions the new
+        // chain already includes are rejected as stale
+        pending.sort_by_key(|transaction| transaction.nonce);
+
+        let nonces = self.ledger.nonces();
+
+        for transaction in pending {
+            if let Ok(sender) = transaction.sender() {
+                let confirmed = nonces.get(&sender).copied().unwrap_or_default();
+                let _ = self.mempool.insert(transaction, sender, confirmed, now);
+            }
+        }
+
+        Ok(ChainReorg { reverted, adopted })
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        format: SnapshotFormat,
+    ) -> Result<(), SnapshotError<InvalidBlock>> {
+        write_snapshot(path, format, self)
+    }
+
+    pub fn load(
+        path: impl AsRef<Path>,
+        format: SnapshotFormat,
+    ) -> Result<Miner, SnapshotError<InvalidBlock>> {
+        let mut miner: Miner = read_snapshot(path, format)?;
+
+        miner
+            .validate_chain()
+            .map_err(SnapshotError::InvalidBlock)?;
+
+        miner.rebuild_state();
+
+        // Pooled transactions aren't covered by the chain validation, so they go through the same
+        // checks as new ones and whatever fails them is dropped
+        let mut pending = miner.mempool.take_all();
+        pending.sort_by_key(|transaction| transaction.nonce);
+
+        let now = current_timestamp();
+
+        for transaction in pending {
+            let _ = miner.add_transaction_at(transaction, now);
+        }
+
+        miner.mempool.remove_confirmed(&miner.ledger.nonces());
+
+        Ok(miner)
+    }
+}
+
+// TODO: Remove duplicated test from different sections
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+
+    use crate::week_1::{
+        ledger::LedgerConfig,
+        proof_of_work::{DifficultyConfig, Miner, Transaction},
+        transaction_pool::PoolConfig,
+    };
+
+    const VITALIK_PRIVATE_KEY: &str =
+        "6b911fd37cdf5c81d4c0adb1ab7fa822ed253ab0ad9aa18d77257c88b29b718e";
+    pub const VITALIK_ADDRESS: &str = "16bb6031cbf3a12b899ab99d96b64b7bbd719705";
+
+    pub fn vitalik() -> SigningKey {
+        SigningKey::from_bytes(&hex::decode(VITALIK_PRIVATE_KEY).unwrap()).unwrap()
+    }
+
+    // Miner whose chain starts with a balance for the test account
+    pub fn funded_miner() -> Miner {
+        funded_miner_with(|_| {})
+    }
+
+    // The ledger config can only be changed before the miner computes its genesis state
+    pub fn funded_miner_with(configure: impl FnOnce(&mut LedgerConfig)) -> Miner {
+        let mut ledger_config = LedgerConfig::new();
+        ledger_config
+            .allocations
+            .insert(String::from(VITALIK_ADDRESS), 1_000_000);
+
+        configure(&mut ledger_config);
+
+        Miner::with_config(DifficultyConfig::new(), PoolConfig::new(), ledger_config)
+    }
+
+    pub fn transaction_to(to: &str, nonce: u64) -> Transaction {
+        Transaction::sign(&vitalik(), String::from(to), 1, 1, nonce)
+    }
+
+    mod first_section {
+        mod add_transaction {
+            use crate::week_1::proof_of_work::tests::{funded_miner, transaction_to};
+
+            #[test]
+            fn should_add_transaction_to_the_mempool() {
+                let mut miner = funded_miner();
+
+                let transaction = transaction_to("Niapa", 0);
+
+                miner.add_transaction(transaction).unwrap();
+
+                assert_eq!(miner.get_mempool_size(), 1)
+            }
+        }
+    }
+
+    mod second_section {
+        mod mine {
+
+            mod first_block {
+                use k256::U256;
+
+                use crate::week_1::proof_of_work::tests::{funded_miner, transaction_to};
+
+                #[test]
+                fn should_add_to_the_blocks() {
+                    let mut miner = funded_miner();
+
+                    let transaction = transaction_to("Niapa", 0);
+
+                    miner.add_transaction(transaction).unwrap();
+
+                    miner.mine();
+
+                    assert_eq!(miner.get_block_height(), 1)
+                }
+
+                #[test]
+                fn should_store_the_expected_id() {
+                    let mut miner = funded_miner();
+
+                    let transaction = transaction_to("Niapa", 0);
+
+                    miner.add_transaction(transaction).unwrap();
+
+                    miner.mine();
+
+                    let block = miner.get_block_by_block_number(0).unwrap();
+
+                    assert_eq!(block.id, U256::from(0 as u128))
+                }
+            }
+
+            mod second_block {
+                use k256::U256;
+
+                use crate::week_1::proof_of_work::tests::{funded_miner, transaction_to};
+
+                #[test]
+                fn should_add_to_the_blocks() {
+                    let mut miner = funded_miner();
+
+                    miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+                    miner.add_transaction(transaction_to("Niapa", 1)).unwrap();
+
+                    miner.mine();
+
+                    miner.add_transaction(transaction_to("Niapa", 2)).unwrap();
+                    miner.add_transaction(transaction_to("Niapa", 3)).unwrap();
+
+                    miner.mine();
+
+                    assert_eq!(miner.get_block_height(), 2)
+                }
+
+                #[test]
+                fn should_store_the_expected_id() {
+                    let mut miner = funded_miner();
+
+                    miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+                    miner.add_transaction(transaction_to("Niapa", 1)).unwrap();
+
+                    miner.mine();
+
+                    miner.add_transaction(transaction_to("Niapa", 2)).unwrap();
+                    miner.add_transaction(transaction_to("Niapa", 3)).unwrap();
+
+                    miner.mine();
+
+                    let block = miner.get_block_by_block_number(1).unwrap();
+
+                    assert_eq!(block.id, U256::from(1_u32))
+                }
+            }
+        }
+    }
+
+    mod third_section {
+
+        mod first_block {
+            use crate::week_1::proof_of_work::tests::{funded_miner, transaction_to};
+
+            #[test]
+            fn should_add_to_the_blocks() {
+                let mut miner = funded_miner();
+
+                let transaction = transaction_to("Niapa", 0);
+
+                miner.add_transaction(transaction).unwrap();
+
+                miner.mine();
+
+                assert_eq!(miner.get_block_height(), 1)
+            }
+        }
+
+        mod second_block {
+            use crate::week_1::proof_of_work::tests::{funded_miner, transaction_to};
+
+            #[test]
+            fn should_add_to_the_blocks() {
+                let mut miner = funded_miner();
+
+                let transaction = transaction_to("Niapa", 0);
+                let next_transaction = transaction_to("Niapa", 1);
+
+                miner.add_transaction(transaction).unwrap();
+
+                miner.mine();
+
+                miner.add_transaction(next_transaction).unwrap();
+
+                miner.mine();
+
+                assert_eq!(miner.get_block_height(), 2)
+            }
+        }
+    }
+
+    mod fourth_section {
+
+        mod with_5_mempool_transactions {
+            use crate::week_1::proof_of_work::tests::{funded_miner, transaction_to};
+
+            #[test]
+            fn should_add_to_the_blocks() {
+                let mut miner = funded_miner();
+
+                for nonce in 0..5 {
+                    let transaction = transaction_to("Niapa", nonce);
+
+                    miner.add_transaction(transaction).unwrap();
+                }
+
+                miner.mine();
+
+                assert_eq!(miner.blocks.len(), 1);
+            }
+
+            #[test]
+            fn should_store_the_transactions_on_the_block() {
+                let mut miner = funded_miner();
+
+                for nonce in 0..5 {
+                    let transaction = transaction_to("Niapa", nonce);
+
+                    miner.add_transaction(transaction).unwrap();
+                }
+
+                miner.mine();
+
+                let block = miner.get_block_by_block_number(0).unwrap();
+
+                assert_eq!(block.transactions.len(), 5)
+            }
+
+            #[test]
+            fn should_clear_the_mempool() {
+                let mut miner = funded_miner();
+
+                for nonce in 0..5 {
+                    let transaction = transaction_to("Niapa", nonce);
+
+                    miner.add_transaction(transaction).unwrap();
+                }
+
+                miner.mine();
+
+                assert_eq!(miner.mempool.len(), 0)
+            }
+        }
+    }
+
+    mod fifth_section {
+        mod with_5_mempool_transactions {
+            use k256::U256;
+
+            use crate::week_1::proof_of_work::{
+                tests::{funded_miner, transaction_to},
+                TARGET_DIFFICULTY,
+            };
+
+            #[test]
+            fn should_add_to_the_blocks() {
+                let mut miner = funded_miner();
+
+                for nonce in 0..5 {
+                    let transaction = transaction_to("Niapa", nonce);
+
+                    miner.add_transaction(transaction).unwrap();
+                }
+
+                miner.mine();
+
+                assert_eq!(miner.blocks.len(), 1);
+            }
+
+            #[test]
+            fn should_store_the_transactions_on_the_block() {
+                let mut miner = funded_miner();
+
+                for nonce in 0..5 {
+                    let transaction = transaction_to("Niapa", nonce);
+
+                    miner.add_transaction(transaction).unwrap();
+                }
+
+                miner.mine();
+
+                let block = miner.get_block_by_block_number(0).unwrap();
+
+                assert_eq!(block.transactions.len(), 5)
+            }
+
+            #[test]
+            fn should_clear_the_mempool() {
+                let mut miner = funded_miner();
+
+                for nonce in 0..5 {
+                    let transaction = transaction_to("Niapa", nonce);
+
+                    miner.add_transaction(transaction).unwrap();
+                }
+
+                miner.mine();
+
+                assert_eq!(miner.mempool.len(), 0)
+            }
+
+            #[test]
+            fn should_have_a_hash_smaller_than_the_target_difficulty() {
+                let mut miner = funded_miner();
+
+                for nonce in 0..5 {
+                    let transaction = transaction_to("Niapa", nonce);
+
+                    miner.add_transaction(transaction).unwrap();
+                }
+
+                let target_difficulty = U256::from_be_hex(TARGET_DIFFICULTY);
+
+                miner.mine();
+
+                let block = miner.get_block_by_block_number(0).unwrap();
+
+                assert!(target_difficulty.ge(&block.hash))
+            }
+        }
+    }
+
+    mod persistence {
+        use std::fs;
+
+        use k256::U256;
+
+        use crate::week_1::{
+            persistence::{SnapshotError, SnapshotFormat},
+            proof_of_work::{
+                tests::{funded_miner, transaction_to},
+                InvalidBlock, InvalidBlockReason, Miner, TransactionError,
+            },
+        };
+
+        fn snapshot_path(name: &str) -> std::path::PathBuf {
+            std::env::temp_dir().join(format!("miner-{}-{}", std::process::id(), name))
+        }
+
+        #[test]
+        fn should_resume_mining_from_a_snapshot() {
+            for format in [SnapshotFormat::Json, SnapshotFormat::Binary] {
+                let path = snapshot_path(&format!("resume-{:?}", format));
+                let mut miner = funded_miner();
+
+                miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+                miner.mine();
+                miner.add_transaction(transaction_to("Niapa", 1)).unwrap();
+
+                miner.save(&path, format).unwrap();
+
+                let mut loaded = Miner::load(&path, format).unwrap();
+
+                assert_eq!(loaded.get_block_height(), 1);
+                assert_eq!(loaded.get_mempool_size(), 1);
+                assert_eq!(loaded.blocks[0].hash, miner.blocks[0].hash);
+                assert_eq!(loaded.get_balance("Niapa"), miner.get_balance("Niapa"));
+                assert_eq!(
+                    loaded.add_transaction(miner.blocks[0].transactions[0].clone()),
+                    Err(TransactionError::AlreadyIncluded)
+                );
+
+                loaded.mine();
+
+                assert_eq!(loaded.get_block_height(), 2);
+                assert_eq!(loaded.blocks[1].id, U256::from(1_u32));
+                assert_eq!(loaded.validate_chain(), Ok(()));
+
+                fs::remove_file(path).unwrap();
+            }
+        }
+
+        #[test]
+        fn should_name_the_corrupted_block() {
+            let path = snapshot_path("corrupted.json");
+            let mut miner = funded_miner();
+
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            miner.mine();
+            miner.add_transaction(transaction_to("Niapa", 1)).unwrap();
+            miner.mine();
+
+            miner.blocks[1].transactions[0].to = String::from("Mallory");
+
+            miner.save(&path, SnapshotFormat::Json).unwrap();
+
+            let result = Miner::load(&path, SnapshotFormat::Json);
+
+            assert!(matches!(
+                result,
+                Err(SnapshotError::InvalidBlock(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::TransactionsRootMismatch
+                }))
+            ));
+
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn should_drop_a_corrupted_mempool_entry() {
+            let path = snapshot_path("corrupted-mempool.json");
+            let mut miner = funded_miner();
+
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            miner.mine();
+            miner.add_transaction(transaction_to("Niapa", 1)).unwrap();
+            miner.add_transaction(transaction_to("Satoshi", 2)).unwrap();
+
+            miner.save(&path, SnapshotFormat::Json).unwrap();
+
+            // Changing the recipient makes the signature recover some other, unfunded sender
+            let snapshot = fs::read_to_string(&path).unwrap();
+            fs::write(&path, snapshot.replace("Satoshi", "Mallory")).unwrap();
+
+            let loaded = Miner::load(&path, SnapshotFormat::Json).unwrap();
+
+            assert_eq!(loaded.get_mempool_size(), 1);
+            assert!(loaded.mempool.contains(&transaction_to("Niapa", 1)));
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    mod replace_chain {
+        use crate::week_1::proof_of_work::{
+            tests::{funded_miner, transaction_to},
+            ChainReplacementError,
+        };
+
+        #[test]
+        fn should_adopt_a_longer_chain() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
+
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            miner.mine();
+
+            peer.mine();
+            peer.mine();
+
+            let reorg = miner.replace_chain(peer.blocks.clone()).unwrap();
+
+            assert_eq!(reorg.reverted.len(), 1);
+            assert_eq!(reorg.adopted.len(), 2);
+            assert_eq!(miner.get_block_height(), 2);
+            assert_eq!(miner.blocks[1].hash, peer.blocks[1].hash);
+        }
+
+        #[test]
+        fn should_return_reverted_transactions_to_the_mempool() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
+
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            miner.add_transaction(transaction_to("Satoshi", 1)).unwrap();
+            miner.mine();
+
+            peer.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            peer.mine();
+            peer.mine();
+
+            miner.replace_chain(peer.blocks.clone()).unwrap();
+
+            assert_eq!(miner.get_mempool_size(), 1);
+            assert_eq!(miner.mempool.transactions().next().unwrap().to, "Satoshi");
+        }
+
+        #[test]
+        fn should_keep_pending_transactions_through_a_reorg() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
+
+            miner.mine_at(10);
+
+            peer.mine_at(10);
+            peer.mine_at(20);
+
+            let transaction = transaction_to("Niapa", 0);
+            miner.add_transaction_at(transaction.clone(), 15).unwrap();
+
+            miner.replace_chain_at(peer.blocks.clone(), 25).unwrap();
+
+            assert_eq!(miner.get_mempool_size(), 1);
+            assert!(miner.mempool.contains(&transaction));
+        }
+
+        #[test]
+        fn should_keep_the_current_chain_on_a_tie() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
+
+            miner.mine();
+            peer.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            peer.mine();
+
+            let result = miner.replace_chain(peer.blocks.clone());
+
+            assert_eq!(
+                result.err(),
+                Some(ChainReplacementError::InsufficientWork {
+                    current: 16,
+                    candidate: 16
+                })
+            );
+        }
+
+        #[test]
+        fn should_reject_an_invalid_candidate() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
+
+            peer.mine();
+            peer.mine();
+
+            peer.blocks[1]
+                .transactions
+                .push(transaction_to("Mallory", 0));
+
+            let result = miner.replace_chain(peer.blocks.clone());
+
+            assert!(matches!(
+                result,
+                Err(ChainReplacementError::InvalidCandidate(_))
+            ));
+            assert_eq!(miner.get_block_height(), 0);
+        }
+    }
+
+    mod difficulty {
+        use k256::U256;
+
+        use crate::week_1::proof_of_work::{
+            bits_to_target, target_to_bits, Block, ChainReplacementError, DifficultyConfig,
+            HeaderHashing, InvalidBlock, InvalidBlockReason, Miner, TARGET_DIFFICULTY,
+        };
+
+        const RETARGET_EVERY_5_BLOCKS: DifficultyConfig = DifficultyConfig {
+            retarget_interval: 5,
+            target_block_time: 10,
+            max_adjustment: 4,
+            header_hashing: HeaderHashing::Sha256,
+        };
+
+        fn mine_blocks(miner: &mut Miner, count: usize, block_time: u64) {
+            for _ in 0..count {
+                let timestamp = miner
+                    .get_blocks()
+                    .last()
+                    .map_or(1_000, |block| block.timestamp + block_time);
+
+                miner.mine_at(timestamp);
+            }
+        }
 
-#[derive(Clone)]
-struct Transaction {
-    sender: String,
-    to: String,
-}
+        #[test]
+        fn should_roundtrip_compact_bits() {
+            for bits in [0x1d00ffff, 0x1b0404cb, 0x200fffff, 0x03123456] {
+                assert_eq!(target_to_bits(bits_to_target(bits).unwrap()), bits);
+            }
+        }
 
-impl Display for Transaction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{sender:{},to:{}}}", self.sender, self.to)
-    }
-}
+        #[test]
+        fn should_decode_the_bitcoin_genesis_target() {
+            let target = bits_to_target(0x1d00ffff).unwrap();
 
-#[derive(Clone)]
-struct Block {
-    nonce: U256,
-    id: U256,
-    hash: U256,
-    transactions: Vec<Transaction>,
-}
+            assert_eq!(
+                target,
+                U256::from_be_hex(
+                    "00000000ffff0000000000000000000000000000000000000000000000000000"
+                )
+            );
+        }
 
-impl Block {
-    pub fn new(nonce: U256, id: U256, transactions: Vec<Transaction>) -> Self {
-        let stringified_nonce = &nonce.to_string();
-        let stringified_id: &str = &id.to_string();
-        let stringified_transactions = transactions
-            .clone()
-            .into_iter()
-            .map(|transaction| transaction.to_string())
-            .collect::<Vec<String>>()
-            .join(",");
+        #[test]
+        fn should_reject_negative_and_overflowing_bits() {
+            assert_eq!(bits_to_target(0x04923456), None);
+            assert_eq!(bits_to_target(0xff123456), None);
+        }
 
-        let byte_data = vec![
-            "{nonce:",
-            stringified_nonce,
-            ",id:",
-            stringified_id,
-            ",transactions:[",
-            &stringified_transactions,
-            "]}",
-        ];
+        #[test]
+        fn should_start_at_the_easiest_target() {
+            let miner = Miner::new();
 
-        let stringified_byte_data = byte_data.join("");
+            assert_eq!(
+                miner.get_next_bits(),
+                target_to_bits(U256::from_be_hex(TARGET_DIFFICULTY))
+            );
+        }
 
-        let mut hasher = Sha256::new();
+        #[test]
+        fn should_make_mining_harder_when_blocks_are_fast() {
+            let mut miner = Miner::with_difficulty(RETARGET_EVERY_5_BLOCKS);
 
-        hasher.update(stringified_byte_data);
+            mine_blocks(&mut miner, 5, 1);
 
-        let hash = hasher.finalize();
+            let previous_target = bits_to_target(miner.blocks[4].bits).unwrap();
+            let next_target = bits_to_target(miner.get_next_bits()).unwrap();
 
-        let hash = U256::from_be_byte_array(hash);
+            // Blocks came in 10 times faster than expected, so the adjustment is clamped to 4
+            let expected_bits = target_to_bits(previous_target.wrapping_div(&U256::from(4_u32)));
 
-        Self {
-            nonce,
-            id,
-            hash,
-            transactions,
+            assert_eq!(next_target, bits_to_target(expected_bits).unwrap());
+
+            mine_blocks(&mut miner, 1, 1);
+
+            assert!(next_target.gt(&miner.blocks[5].hash));
+            assert_eq!(miner.validate_chain(), Ok(()));
         }
-    }
-}
 
-struct Miner {
-    mempool: VecDeque<Transaction>,
-    blocks: Vec<Block>,
-}
+        #[test]
+        fn should_not_go_above_the_easiest_target() {
+            let mut miner = Miner::new();
 
-impl Miner {
-    pub fn new() -> Self {
-        Miner {
-            mempool: VecDeque::new(),
-            blocks: Vec::new(),
+            mine_blocks(&mut miner, 10, 100);
+
+            assert_eq!(miner.get_next_bits(), miner.blocks[0].bits);
         }
-    }
 
-    pub fn add_transaction(&mut self, transaction: Transaction) {
-        self.mempool.push_back(transaction)
-    }
+        #[test]
+        fn should_only_retarget_every_interval() {
+            let mut miner = Miner::with_difficulty(RETARGET_EVERY_5_BLOCKS);
 
-    pub fn mine(&mut self) {
-        let mut nonce = 0;
+            mine_blocks(&mut miner, 5, 1);
+            let first_retarget = miner.get_next_bits();
 
-        let mut transactions = Vec::new();
+            mine_blocks(&mut miner, 4, 1);
 
-        for _ in 0..MAX_TRANSACTIONS {
-            if let Some(value) = self.mempool.pop_front() {
-                transactions.push(value);
-            } else {
-                break;
-            }
+            assert_ne!(first_retarget, miner.blocks[0].bits);
+            assert_eq!(miner.get_next_bits(), first_retarget);
         }
 
-        loop {
-            let new_block = Block::new(
-                U256::from(nonce as u32),
-                U256::from(self.get_block_height() as u128),
-                transactions.clone(),
-            );
+        #[test]
+        fn should_reject_blocks_with_the_wrong_bits() {
+            let mut miner = Miner::with_difficulty(RETARGET_EVERY_5_BLOCKS);
+            let mut peer = Miner::new();
 
-            let target_difficulty = U256::from_be_hex(TARGET_DIFFICULTY);
+            mine_blocks(&mut peer, 7, 1);
 
-            if target_difficulty.gt(&new_block.hash) {
-                self.blocks.push(new_block);
-                break;
-            }
+            let previous_target = bits_to_target(peer.blocks[4].bits).unwrap();
+            let expected_bits = target_to_bits(previous_target.wrapping_div(&U256::from(4_u32)));
 
-            nonce += 1;
+            let result = miner.replace_chain(peer.blocks.clone());
+
+            assert_eq!(
+                result.err(),
+                Some(ChainReplacementError::InvalidCandidate(InvalidBlock {
+                    index: 5,
+                    reason: InvalidBlockReason::UnexpectedBits {
+                        expected: expected_bits,
+                        found: peer.blocks[5].bits
+                    }
+                }))
+            );
         }
-    }
 
-    pub fn get_block_height(&self) -> usize {
-        self.blocks.len()
+        #[test]
+        fn should_reject_blocks_above_their_target() {
+            let mut miner = Miner::new();
+            let bits = miner.get_next_bits();
+            let target = bits_to_target(bits).unwrap();
+
+            let block = (0_u32..)
+                .map(|nonce| {
+                    Block::new(
+                        U256::from(nonce),
+                        0,
+                        U256::ZERO,
+                        U256::ZERO,
+                        1_000,
+                        bits,
+                        String::from("Niapa"),
+                        Vec::new(),
+                    )
+                })
+                .find(|block| !target.gt(&block.hash))
+                .unwrap();
+
+            miner.blocks.push(block);
+
+            assert_eq!(
+                miner.validate_chain(),
+                Err(InvalidBlock {
+                    index: 0,
+                    reason: InvalidBlockReason::HashAboveTarget
+                })
+            );
+        }
     }
 
-    pub fn get_block_by_block_number(&self, idx: usize) -> Option<&Block> {
-        self.blocks.get(idx)
-    }
+    mod mine_parallel {
+        use crate::week_1::proof_of_work::{
+            tests::{funded_miner, transaction_to},
+            CancellationHandle, HeaderHashing, MiningError, MiningOptions,
+        };
 
-    pub fn get_mempool_size(&self) -> usize {
-        self.mempool.len()
-    }
-}
+        #[test]
+        fn should_mine_a_valid_block_with_several_threads() {
+            let mut miner = funded_miner();
 
-// TODO: Remove duplicated test from different sections
-#[cfg(test)]
-mod tests {
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
 
-    mod first_section {
-        mod add_transaction {
-            use crate::week_1::proof_of_work::{Miner, Transaction};
+            let stats = miner
+                .mine_parallel(MiningOptions::new(4), &CancellationHandle::new())
+                .unwrap();
 
-            #[test]
-            fn should_add_transaction_to_the_mempool() {
-                let mut miner = Miner::new();
+            assert_eq!(miner.get_block_height(), 1);
+            assert_eq!(miner.blocks[0].transactions.len(), 1);
+            assert_eq!(miner.validate_chain(), Ok(()));
+            assert!(stats.hashes > 0);
+            assert!(stats.hashrate > 0.0);
+        }
 
-                let transaction = Transaction {
-                    to: String::from("Niapa"),
-                    sender: String::from("Vitalik"),
-                };
+        #[test]
+        fn should_roll_the_extranonce_when_the_nonces_run_out() {
+            let mut miner = funded_miner();
+            let options = MiningOptions {
+                threads: 1,
+                max_nonce: 0,
+            };
 
-                miner.add_transaction(transaction);
+            let stats = miner
+                .mine_parallel(options, &CancellationHandle::new())
+                .unwrap();
 
-                assert_eq!(miner.get_mempool_size(), 1)
-            }
+            let block = miner.get_block_by_block_number(0).unwrap();
+
+            assert_eq!(stats.extranonce_rolls, block.extranonce);
+            assert_eq!(stats.hashes, block.extranonce + 1);
+            assert_eq!(miner.validate_chain(), Ok(()));
         }
-    }
 
-    mod second_section {
-        mod mine {
+        #[test]
+        fn should_only_rewrite_the_nonce_between_attempts() {
+            let template = funded_miner().next_template(1_000);
 
-            mod first_block {
-                use k256::U256;
+            for hashing in [HeaderHashing::Sha256, HeaderHashing::DoubleSha256] {
+                let mut preimage = hashing.preimage(&template.header(0, 7));
 
-                use crate::week_1::proof_of_work::{Miner, Transaction};
+                hashing.write_nonce(&mut preimage, 0xdeadbeef);
 
-                #[test]
-                fn should_add_to_the_blocks() {
-                    let mut miner = Miner::new();
+                assert_eq!(preimage, hashing.preimage(&template.header(0xdeadbeef, 7)));
+            }
+        }
 
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+        #[test]
+        fn should_stop_when_cancelled() {
+            let mut miner = funded_miner();
+            let cancellation = CancellationHandle::new();
 
-                    miner.add_transaction(transaction);
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
 
-                    miner.mine();
+            cancellation.cancel();
 
-                    assert_eq!(miner.get_block_height(), 1)
-                }
+            let result = miner.mine_parallel(MiningOptions::new(2), &cancellation);
 
-                #[test]
-                fn should_store_the_expected_id() {
-                    let mut miner = Miner::new();
+            assert!(matches!(result, Err(MiningError::Cancelled(stats)) if stats.hashes == 0));
+            assert_eq!(miner.get_block_height(), 0);
+            assert_eq!(miner.get_mempool_size(), 1);
+        }
+    }
 
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+    mod chain_linkage {
+        use k256::U256;
 
-                    miner.add_transaction(transaction);
+        use crate::week_1::proof_of_work::{
+            bits_to_target,
+            tests::{funded_miner, transaction_to},
+            Block, InvalidBlock, InvalidBlockReason, Transaction,
+        };
 
-                    miner.mine();
+        #[test]
+        fn should_link_blocks_to_their_parent() {
+            let mut miner = funded_miner();
 
-                    let block = miner.get_block_by_block_number(0).unwrap();
+            miner.mine_at(1_000);
+            miner.mine_at(1_010);
 
-                    assert_eq!(block.id, U256::from(0 as u128))
-                }
-            }
+            assert_eq!(miner.blocks[0].parent_hash, U256::ZERO);
+            assert_eq!(miner.blocks[1].parent_hash, miner.blocks[0].hash);
+        }
 
-            mod second_block {
-                use k256::U256;
+        #[test]
+        fn should_detect_a_broken_link() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
 
-                use crate::week_1::proof_of_work::{Miner, Transaction};
+            miner.mine_at(1_000);
+            miner.mine_at(1_010);
 
-                #[test]
-                fn should_add_to_the_blocks() {
-                    let mut miner = Miner::new();
+            peer.mine_at(2_000);
+            peer.mine_at(2_010);
 
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+            miner.blocks[1] = peer.blocks[1].clone();
 
-                    miner.add_transaction(transaction.clone());
-                    miner.add_transaction(transaction.clone());
+            assert_eq!(
+                miner.validate_chain(),
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::ParentHashMismatch
+                })
+            );
+        }
 
-                    miner.mine();
+        #[test]
+        fn should_import_a_block_mined_by_a_peer() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
 
-                    miner.add_transaction(transaction.clone());
-                    miner.add_transaction(transaction);
+            miner.mine_at(1_000);
+            peer.replace_chain(miner.blocks.clone()).unwrap();
 
-                    miner.mine();
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            peer.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            peer.mine_at(1_010);
 
-                    assert_eq!(miner.get_block_height(), 2)
-                }
+            miner.import_block(peer.blocks[1].clone()).unwrap();
 
-                #[test]
-                fn should_store_the_expected_id() {
-                    let mut miner = Miner::new();
+            assert_eq!(miner.get_block_height(), 2);
+            assert_eq!(miner.get_mempool_size(), 0);
+            assert_eq!(miner.validate_chain(), Ok(()));
+        }
 
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+        #[test]
+        fn should_reject_a_block_on_another_parent() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
 
-                    miner.add_transaction(transaction.clone());
-                    miner.add_transaction(transaction.clone());
+            miner.mine_at(1_000);
 
-                    miner.mine();
+            peer.mine_at(2_000);
+            peer.mine_at(2_010);
 
-                    miner.add_transaction(transaction.clone());
-                    miner.add_transaction(transaction);
+            assert_eq!(
+                miner.import_block(peer.blocks[1].clone()),
+                Err(InvalidBlock {
+                    index: 1,
+                    reason: InvalidBlockReason::ParentHashMismatch
+                })
+            );
+            assert_eq!(miner.get_block_height(), 1);
+        }
 
-                    miner.mine();
+        #[test]
+        fn should_reject_a_block_at_the_wrong_height() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
 
-                    let block = miner.get_block_by_block_number(1).unwrap();
+            peer.mine_at(1_000);
+            peer.mine_at(1_010);
 
-                    assert_eq!(block.id, U256::from(1_u32))
-                }
-            }
+            assert_eq!(
+                miner.import_block(peer.blocks[1].clone()),
+                Err(InvalidBlock {
+                    index: 0,
+                    reason: InvalidBlockReason::UnexpectedId
+                })
+            );
+        }
+
+        #[test]
+        fn should_reject_an_oversized_body() {
+            let mut miner = funded_miner();
+            let bits = miner.get_next_bits();
+            let target = bits_to_target(bits).unwrap();
+            let transactions: Vec<Transaction> = (0..11)
+                .map(|nonce| transaction_to("Niapa", nonce))
+                .collect();
+
+            let block = (0_u32..)
+                .map(|nonce| {
+                    Block::new(
+                        U256::from(nonce),
+                        0,
+                        U256::ZERO,
+                        U256::ZERO,
+                        1_000,
+                        bits,
+                        String::from("Niapa"),
+                        transactions.clone(),
+                    )
+                })
+                .find(|block| target.gt(&block.hash))
+                .unwrap();
+
+            assert_eq!(
+                miner.import_block(block),
+                Err(InvalidBlock {
+                    index: 0,
+                    reason: InvalidBlockReason::GasLimitExceeded
+                })
+            );
         }
     }
 
-    mod third_section {
+    mod signed_transactions {
+        use std::sync::OnceLock;
 
-        mod first_block {
-            use crate::week_1::proof_of_work::{Miner, Transaction};
+        use k256::U256;
 
-            #[test]
-            fn should_add_to_the_blocks() {
-                let mut miner = Miner::new();
+        use crate::week_1::proof_of_work::{
+            bits_to_target,
+            tests::{funded_miner, transaction_to, vitalik, VITALIK_ADDRESS},
+            Block, InvalidBlock, InvalidBlockReason, Transaction, TransactionError,
+        };
 
-                let transaction = Transaction {
-                    to: String::from("Niapa"),
-                    sender: String::from("Vitalik"),
-                };
+        #[test]
+        fn should_recover_the_sender_from_the_signature() {
+            let transaction = Transaction {
+                sender: OnceLock::new(),
+                ..Transaction::sign(&vitalik(), String::from("Niapa"), 10, 1, 0)
+            };
 
-                miner.add_transaction(transaction);
+            assert_eq!(transaction.sender(), Ok(String::from(VITALIK_ADDRESS)));
+        }
 
-                miner.mine();
+        #[test]
+        fn should_not_attribute_a_tampered_transaction_to_the_signer() {
+            let transaction = Transaction {
+                value: 1_000_000,
+                sender: OnceLock::new(),
+                ..transaction_to("Niapa", 0)
+            };
 
-                assert_eq!(miner.get_block_height(), 1)
-            }
+            assert_ne!(transaction.sender(), Ok(String::from(VITALIK_ADDRESS)));
         }
 
-        mod second_block {
-            use crate::week_1::proof_of_work::{Miner, Transaction};
+        #[test]
+        fn should_reject_a_malformed_signature() {
+            let mut miner = funded_miner();
+            let transaction = Transaction {
+                signature: [0xff; 65],
+                sender: OnceLock::new(),
+                ..transaction_to("Niapa", 0)
+            };
+
+            assert_eq!(
+                miner.add_transaction(transaction),
+                Err(TransactionError::InvalidSignature)
+            );
+        }
 
-            #[test]
-            fn should_add_to_the_blocks() {
-                let mut miner = Miner::new();
+        #[test]
+        fn should_reject_a_nonce_gap() {
+            let mut miner = funded_miner();
+
+            assert_eq!(
+                miner.add_transaction(transaction_to("Niapa", 1)),
+                Err(TransactionError::NonceTooHigh {
+                    expected: 0,
+                    found: 1
+                })
+            );
+        }
 
-                let transaction = Transaction {
-                    to: String::from("Niapa"),
-                    sender: String::from("Vitalik"),
-                };
+        #[test]
+        fn should_count_pending_transactions_in_the_next_nonce() {
+            let mut miner = funded_miner();
 
-                let clone_transaction = transaction.clone();
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            miner.add_transaction(transaction_to("Niapa", 1)).unwrap();
 
-                miner.add_transaction(transaction);
+            assert_eq!(miner.get_next_nonce(VITALIK_ADDRESS), 2);
+        }
 
-                miner.mine();
+        #[test]
+        fn should_reject_replays() {
+            let mut miner = funded_miner();
 
-                miner.add_transaction(clone_transaction);
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
 
-                miner.mine();
+            assert_eq!(
+                miner.add_transaction(transaction_to("Niapa", 0)),
+                Err(TransactionError::AlreadyKnown)
+            );
 
-                assert_eq!(miner.get_block_height(), 2)
-            }
+            miner.mine();
+
+            assert_eq!(
+                miner.add_transaction(transaction_to("Niapa", 0)),
+                Err(TransactionError::AlreadyIncluded)
+            );
+            assert_eq!(
+                miner.add_transaction(transaction_to("Satoshi", 0)),
+                Err(TransactionError::NonceTooLow {
+                    expected: 1,
+                    found: 0
+                })
+            );
+        }
+
+        #[test]
+        fn should_reject_a_block_with_an_out_of_order_nonce() {
+            let mut miner = funded_miner();
+            let bits = miner.get_next_bits();
+            let target = bits_to_target(bits).unwrap();
+
+            let block = (0_u32..)
+                .map(|nonce| {
+                    Block::new(
+                        U256::from(nonce),
+                        0,
+                        U256::ZERO,
+                        U256::ZERO,
+                        1_000,
+                        bits,
+                        String::from("Niapa"),
+                        vec![transaction_to("Niapa", 1)],
+                    )
+                })
+                .find(|block| target.gt(&block.hash))
+                .unwrap();
+
+            assert_eq!(
+                miner.import_block(block),
+                Err(InvalidBlock {
+                    index: 0,
+                    reason: InvalidBlockReason::InvalidTransaction {
+                        index: 0,
+                        error: TransactionError::NonceTooHigh {
+                            expected: 0,
+                            found: 1
+                        }
+                    }
+                })
+            );
         }
     }
 
-    mod fourth_section {
+    mod fee_priority {
+        use k256::ecdsa::SigningKey;
 
-        mod with_5_mempool_transactions {
-            use crate::week_1::proof_of_work::{Miner, Transaction};
+        use crate::week_1::proof_of_work::{
+            tests::{funded_miner, funded_miner_with, vitalik},
+            Transaction, TransactionError,
+        };
 
-            #[test]
-            fn should_add_to_the_blocks() {
-                let mut miner = Miner::new();
+        fn satoshi() -> SigningKey {
+            SigningKey::from_bytes(&[7; 32]).unwrap()
+        }
 
-                for _ in 0..5 {
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+        #[test]
+        fn should_fill_the_block_with_the_best_paying_transactions_first() {
+            let satoshi_address = Transaction::sign(&satoshi(), String::new(), 0, 0, 0)
+                .sender()
+                .unwrap();
+            let mut miner = funded_miner_with(|config| {
+                config.allocations.insert(satoshi_address, 2_000_000);
+            });
+
+            miner
+                .add_transaction(Transaction::sign(
+                    &vitalik(),
+                    String::from("Niapa"),
+                    1,
+                    5,
+                    0,
+                ))
+                .unwrap();
+            miner
+                .add_transaction(Transaction::sign(
+                    &satoshi(),
+                    String::from("Niapa"),
+                    1,
+                    50,
+                    0,
+                ))
+                .unwrap();
+
+            miner.mine();
+
+            let fees: Vec<u128> = miner.blocks[0]
+                .transactions
+                .iter()
+                .map(|transaction| transaction.max_priority_fee_per_gas())
+                .collect();
+
+            assert_eq!(fees, vec![50, 5]);
+            assert_eq!(miner.get_mempool_size(), 0);
+        }
 
-                    miner.add_transaction(transaction);
-                }
+        #[test]
+        fn should_mine_the_replacement_of_a_pending_transaction() {
+            let mut miner = funded_miner();
+
+            miner
+                .add_transaction(Transaction::sign(
+                    &vitalik(),
+                    String::from("Niapa"),
+                    1,
+                    10,
+                    0,
+                ))
+                .unwrap();
+            miner
+                .add_transaction(Transaction::sign(
+                    &vitalik(),
+                    String::from("Satoshi"),
+                    1,
+                    20,
+                    0,
+                ))
+                .unwrap();
+
+            miner.mine();
+
+            assert_eq!(miner.blocks[0].transactions.len(), 1);
+            assert_eq!(miner.blocks[0].transactions[0].to, "Satoshi");
+            assert_eq!(miner.get_mempool_stats().replaced, 1);
+        }
 
-                miner.mine();
+        #[test]
+        fn should_reject_an_underpriced_replacement() {
+            let mut miner = funded_miner();
+
+            miner
+                .add_transaction(Transaction::sign(
+                    &vitalik(),
+                    String::from("Niapa"),
+                    1,
+                    10,
+                    0,
+                ))
+                .unwrap();
+
+            assert_eq!(
+                miner.add_transaction(Transaction::sign(
+                    &vitalik(),
+                    String::from("Satoshi"),
+                    1,
+                    10,
+                    0
+                )),
+                Err(TransactionError::ReplacementUnderpriced {
+                    minimum_max_fee: 11,
+                    minimum_priority_fee: 11
+                })
+            );
+        }
+    }
 
-                assert_eq!(miner.blocks.len(), 1);
-            }
+    mod ledger {
+        use crate::week_1::proof_of_work::{
+            tests::{funded_miner, transaction_to, vitalik, VITALIK_ADDRESS},
+            Transaction, TransactionError,
+        };
+
+        #[test]
+        fn should_pay_the_reward_and_fees_to_the_beneficiary() {
+            let mut miner = funded_miner();
+            miner.set_beneficiary(String::from("Satoshi"));
+
+            miner
+                .add_transaction(Transaction::sign(
+                    &vitalik(),
+                    String::from("Niapa"),
+                    100,
+                    7,
+                    0,
+                ))
+                .unwrap();
+            miner.mine();
+
+            assert_eq!(miner.get_balance("Satoshi"), 50 + 7 * 21_000);
+            assert_eq!(miner.get_balance("Niapa"), 100);
+            assert_eq!(
+                miner.get_balance(VITALIK_ADDRESS),
+                1_000_000 - 100 - 7 * 21_000
+            );
+        }
 
-            #[test]
-            fn should_store_the_transactions_on_the_block() {
-                let mut miner = Miner::new();
+        #[test]
+        fn should_query_the_state_at_any_height() {
+            let mut miner = funded_miner();
 
-                for _ in 0..5 {
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            miner.mine();
+            miner.add_transaction(transaction_to("Niapa", 1)).unwrap();
+            miner.mine();
 
-                    miner.add_transaction(transaction);
-                }
+            assert_eq!(miner.get_balance_at("Niapa", 0), Some(1));
+            assert_eq!(miner.get_balance_at("Niapa", 1), Some(2));
+            assert_eq!(miner.get_nonce_at(VITALIK_ADDRESS, 0), Some(1));
+            assert_eq!(miner.get_nonce(VITALIK_ADDRESS), 2);
+            assert_eq!(miner.get_state_at(2), None);
+        }
 
-                miner.mine();
+        #[test]
+        fn should_reject_an_overspend_counting_pending_transactions() {
+            let mut miner = funded_miner();
+
+            miner
+                .add_transaction(Transaction::sign(
+                    &vitalik(),
+                    String::from("Niapa"),
+                    600_000,
+                    1,
+                    0,
+                ))
+                .unwrap();
+
+            assert_eq!(
+                miner.add_transaction(Transaction::sign(
+                    &vitalik(),
+                    String::from("Niapa"),
+                    400_000,
+                    1,
+                    1
+                )),
+                Err(TransactionError::InsufficientBalance {
+                    balance: 1_000_000,
+                    required: 1_042_000
+                })
+            );
+        }
+    }
 
-                let block = miner.get_block_by_block_number(0).unwrap();
+    mod fee_market {
+        use crate::week_1::proof_of_work::{
+            tests::{funded_miner, funded_miner_with, transaction_to, vitalik, VITALIK_ADDRESS},
+            InvalidBlock, InvalidBlockReason, Miner, Transaction,
+        };
+
+        fn miner_with_base_fee(base_fee: u128) -> Miner {
+            funded_miner_with(|config| {
+                config.initial_base_fee = base_fee;
+                config
+                    .allocations
+                    .insert(String::from(VITALIK_ADDRESS), 100_000_000);
+            })
+        }
 
-                assert_eq!(block.transactions.len(), 5)
+        #[test]
+        fn should_burn_the_base_fee_and_raise_it_after_a_full_block() {
+            let mut miner = miner_with_base_fee(10);
+            miner.set_beneficiary(String::from("Satoshi"));
+
+            for nonce in 0..10 {
+                miner
+                    .add_transaction(Transaction::sign(
+                        &vitalik(),
+                        String::from("Niapa"),
+                        1,
+                        20,
+                        nonce,
+                    ))
+                    .unwrap();
             }
+            miner.mine();
 
-            #[test]
-            fn should_clear_the_mempool() {
-                let mut miner = Miner::new();
+            assert_eq!(miner.blocks[0].gas_used(), miner.ledger_config.gas_limit);
+            assert_eq!(miner.get_burned(), 10 * 10 * 21_000);
+            assert_eq!(miner.get_balance("Satoshi"), 50 + 10 * 10 * 21_000);
+            assert_eq!(miner.get_next_base_fee(), 11);
+        }
 
-                for _ in 0..5 {
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+        #[test]
+        fn should_lower_the_base_fee_after_an_empty_block() {
+            let mut miner = miner_with_base_fee(800);
 
-                    miner.add_transaction(transaction);
-                }
+            miner.mine();
 
-                miner.mine();
+            assert_eq!(miner.get_next_base_fee(), 700);
+        }
 
-                assert_eq!(miner.mempool.len(), 0)
-            }
+        #[test]
+        fn should_leave_transactions_below_the_base_fee_pooled() {
+            let mut miner = miner_with_base_fee(10);
+
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            miner.mine();
+
+            assert!(miner.blocks[0].transactions.is_empty());
+            assert_eq!(miner.get_mempool_size(), 1);
+        }
+
+        #[test]
+        fn should_reject_a_block_with_an_unexpected_base_fee() {
+            let mut miner = funded_miner();
+            let mut peer = miner_with_base_fee(10);
+
+            miner.mine_at(1_000);
+
+            assert_eq!(
+                peer.import_block(miner.blocks[0].clone()),
+                Err(InvalidBlock {
+                    index: 0,
+                    reason: InvalidBlockReason::UnexpectedBaseFee {
+                        expected: 10,
+                        found: 0
+                    }
+                })
+            );
         }
     }
 
-    mod fifth_section {
-        mod with_5_mempool_transactions {
-            use k256::U256;
+    mod merkle_proofs {
+        use crate::week_1::proof_of_work::{
+            tests::{funded_miner, transaction_to},
+            verify_transaction_proof, InvalidBlock, InvalidBlockReason,
+        };
 
-            use crate::week_1::proof_of_work::{Miner, Transaction, TARGET_DIFFICULTY};
+        fn mine_with_transactions(count: u64) -> crate::week_1::proof_of_work::Miner {
+            let mut miner = funded_miner();
 
-            #[test]
-            fn should_add_to_the_blocks() {
-                let mut miner = Miner::new();
+            for nonce in 0..count {
+                miner
+                    .add_transaction(transaction_to("Niapa", nonce))
+                    .unwrap();
+            }
 
-                for _ in 0..5 {
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+            miner.mine();
+            miner
+        }
 
-                    miner.add_transaction(transaction);
-                }
+        #[test]
+        fn should_prove_every_transaction_against_the_header() {
+            let miner = mine_with_transactions(5);
+            let header = miner.get_header(0).unwrap();
 
-                miner.mine();
+            for (idx, transaction) in miner.blocks[0].transactions.iter().enumerate() {
+                let proof = miner.get_transaction_proof(0, idx).unwrap();
 
-                assert_eq!(miner.blocks.len(), 1);
+                assert!(verify_transaction_proof(&header, transaction, &proof));
             }
+        }
 
-            #[test]
-            fn should_store_the_transactions_on_the_block() {
-                let mut miner = Miner::new();
+        #[test]
+        fn should_not_prove_a_transaction_outside_the_block() {
+            let miner = mine_with_transactions(3);
+            let header = miner.get_header(0).unwrap();
+            let proof = miner.get_transaction_proof(0, 0).unwrap();
+
+            assert!(!verify_transaction_proof(
+                &header,
+                &transaction_to("Satoshi", 0),
+                &proof
+            ));
+        }
 
-                for _ in 0..5 {
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+        #[test]
+        fn should_not_return_a_proof_for_a_missing_transaction() {
+            let miner = mine_with_transactions(3);
 
-                    miner.add_transaction(transaction);
-                }
+            assert!(miner.get_transaction_proof(0, 3).is_none());
+            assert!(miner.get_transaction_proof(1, 0).is_none());
+        }
 
-                miner.mine();
+        #[test]
+        fn should_reject_a_block_whose_transactions_do_not_match_the_root() {
+            let mut miner = mine_with_transactions(2);
+            miner.blocks[0].transactions.pop();
+
+            assert_eq!(
+                miner.validate_chain(),
+                Err(InvalidBlock {
+                    index: 0,
+                    reason: InvalidBlockReason::TransactionsRootMismatch
+                })
+            );
+        }
+    }
 
-                let block = miner.get_block_by_block_number(0).unwrap();
+    mod header_hashing {
+        use k256::{elliptic_curve::bigint::ArrayEncoding, U256};
 
-                assert_eq!(block.transactions.len(), 5)
+        use crate::week_1::proof_of_work::{
+            bits_to_target, BitcoinHeader, DifficultyConfig, HeaderHashing, InvalidBlock,
+            InvalidBlockReason, Miner,
+        };
+
+        const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+        const GENESIS_HASH: &str =
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+        // Block explorers display hashes in reverse byte order
+        fn from_display(hash: &str) -> [u8; 32] {
+            let mut bytes: [u8; 32] = hex::decode(hash).unwrap().try_into().unwrap();
+            bytes.reverse();
+
+            bytes
+        }
+
+        fn genesis() -> BitcoinHeader {
+            BitcoinHeader {
+                version: 1,
+                prev_block: [0; 32],
+                merkle_root: from_display(
+                    "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+                ),
+                time: 1231006505,
+                bits: 0x1d00ffff,
+                nonce: 2083236893,
             }
+        }
 
-            #[test]
-            fn should_clear_the_mempool() {
-                let mut miner = Miner::new();
+        #[test]
+        fn should_serialize_the_bitcoin_genesis_header() {
+            assert_eq!(hex::encode(genesis().to_bytes()), GENESIS_HEADER);
+        }
 
-                for _ in 0..5 {
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+        #[test]
+        fn should_hash_the_bitcoin_genesis_header() {
+            assert_eq!(genesis().hash(), from_display(GENESIS_HASH));
+        }
 
-                    miner.add_transaction(transaction);
-                }
+        #[test]
+        fn should_hash_the_first_bitcoin_block() {
+            let header = BitcoinHeader {
+                version: 1,
+                prev_block: from_display(GENESIS_HASH),
+                merkle_root: from_display(
+                    "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098",
+                ),
+                time: 1231469665,
+                bits: 0x1d00ffff,
+                nonce: 2573394689,
+            };
+
+            assert_eq!(
+                header.hash(),
+                from_display("00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048")
+            );
+        }
 
-                miner.mine();
+        #[test]
+        fn should_parse_the_bitcoin_genesis_header() {
+            let bytes: [u8; 80] = hex::decode(GENESIS_HEADER).unwrap().try_into().unwrap();
 
-                assert_eq!(miner.mempool.len(), 0)
-            }
+            assert_eq!(BitcoinHeader::from_bytes(&bytes), genesis());
+        }
 
-            #[test]
-            fn should_have_a_hash_smaller_than_the_target_difficulty() {
-                let mut miner = Miner::new();
+        #[test]
+        fn should_meet_the_target_of_the_bitcoin_genesis_bits() {
+            let header = genesis();
+            let target = bits_to_target(header.bits).unwrap();
+
+            assert!(U256::from_le_slice(&header.hash()) < target);
+        }
 
-                for _ in 0..5 {
-                    let transaction = Transaction {
-                        to: String::from("Niapa"),
-                        sender: String::from("Vitalik"),
-                    };
+        #[test]
+        fn should_mine_a_valid_chain_with_bitcoin_headers() {
+            let difficulty = DifficultyConfig {
+                header_hashing: HeaderHashing::DoubleSha256,
+                ..DifficultyConfig::new()
+            };
+            let mut miner = Miner::with_difficulty(difficulty);
+
+            miner.mine_at(1_000);
+            miner.mine_at(1_010);
+
+            let header = miner.get_header(1).unwrap();
+            let bitcoin_header = header.to_bitcoin_header();
+
+            assert_eq!(miner.validate_chain(), Ok(()));
+            assert_eq!(header.hash(), U256::from_le_slice(&bitcoin_header.hash()));
+            assert_eq!(
+                bitcoin_header.prev_block[..],
+                miner.blocks[0].hash.to_le_byte_array()[..]
+            );
+        }
 
-                    miner.add_transaction(transaction);
-                }
+        #[test]
+        fn should_reject_a_block_hashed_with_another_function() {
+            let mut miner = Miner::new();
+
+            miner.mine_at(1_000);
+            miner.blocks[0] = miner.blocks[0]
+                .clone()
+                .with_hashing(HeaderHashing::DoubleSha256);
+
+            assert_eq!(
+                miner.validate_chain(),
+                Err(InvalidBlock {
+                    index: 0,
+                    reason: InvalidBlockReason::UnexpectedHeaderHashing
+                })
+            );
+        }
+    }
 
-                let target_difficulty = U256::from_be_hex(TARGET_DIFFICULTY);
+    mod canonical_encoding {
+        use crate::week_1::{
+            encoding::{Decode, DecodeError, Encode},
+            proof_of_work::{
+                tests::{funded_miner, transaction_to, VITALIK_ADDRESS},
+                Block, Transaction,
+            },
+        };
 
-                miner.mine();
+        #[test]
+        fn should_round_trip_a_transaction() {
+            let transaction = transaction_to("Niapa", 3);
 
-                let block = miner.get_block_by_block_number(0).unwrap();
+            let decoded = Transaction::from_bytes(&transaction.to_bytes()).unwrap();
 
-                assert!(target_difficulty.ge(&block.hash))
-            }
+            assert_eq!(decoded.to_string(), transaction.to_string());
+            assert_eq!(decoded.sender(), Ok(String::from(VITALIK_ADDRESS)));
+        }
+
+        #[test]
+        fn should_round_trip_a_block() {
+            let mut miner = funded_miner();
+            miner.add_transaction(transaction_to("Niapa", 0)).unwrap();
+            miner.add_transaction(transaction_to("Satoshi", 1)).unwrap();
+            miner.mine();
+
+            let block = &miner.blocks[0];
+            let decoded = Block::from_bytes(&block.to_bytes()).unwrap();
+
+            assert_eq!(decoded.hash, block.hash);
+            assert_eq!(decoded.header(), block.header());
+            assert_eq!(decoded.to_bytes(), block.to_bytes());
+        }
+
+        #[test]
+        fn should_reject_a_truncated_signature() {
+            let bytes = transaction_to("Niapa", 0).to_bytes();
+
+            assert_eq!(
+                Transaction::from_bytes(&bytes[..bytes.len() - 1]).err(),
+                Some(DecodeError::UnexpectedEnd)
+            );
+        }
+
+        #[test]
+        fn should_reject_trailing_bytes() {
+            let mut bytes = transaction_to("Niapa", 0).to_bytes();
+            bytes.push(0);
+
+            assert_eq!(
+                Transaction::from_bytes(&bytes).err(),
+                Some(DecodeError::TrailingBytes)
+            );
+        }
+    }
+
+    mod block_tree {
+        use crate::week_1::{
+            block_tree::{BlockTree, InsertOutcome, TreeError},
+            proof_of_work::{tests::funded_miner, InvalidBlockReason},
+        };
+
+        #[test]
+        fn should_switch_to_a_heavier_fork_received_out_of_order() {
+            let mut miner = funded_miner();
+            let mut peer = funded_miner();
+
+            miner.mine();
+            peer.import_block(miner.blocks[0].clone()).unwrap();
+            peer.set_beneficiary(String::from("Niapa"));
+
+            miner.mine();
+            peer.mine();
+            peer.mine();
+
+            let mut tree =
+                BlockTree::with_state(miner.blocks[0].clone(), miner.get_state_at(0).unwrap());
+
+            tree.insert(miner.blocks[1].clone(), |branch, block| {
+                miner.check_block(branch, block)
+            })
+            .unwrap();
+
+            assert!(matches!(
+                tree.insert(peer.blocks[2].clone(), |branch, block| {
+                    miner.check_block(branch, block)
+                }),
+                Ok(InsertOutcome::Orphaned)
+            ));
+
+            let InsertOutcome::Connected {
+                blocks: 2,
+                reorg: Some(reorg),
+            } = tree
+                .insert(peer.blocks[1].clone(), |branch, block| {
+                    miner.check_block(branch, block)
+                })
+                .unwrap()
+            else {
+                panic!("the peer's fork should have become canonical");
+            };
+
+            assert_eq!(reorg.reverted[0].hash, miner.blocks[1].hash);
+            assert_eq!(reorg.applied.len(), 2);
+            assert_eq!(tree.head().hash, peer.blocks[2].hash);
+            assert_eq!(tree.stats().uncles, 1);
+        }
+
+        #[test]
+        fn should_not_store_an_invalid_block() {
+            let mut miner = funded_miner();
+            miner.mine();
+            miner.mine();
+
+            let mut tampered = miner.blocks[1].clone();
+            tampered.timestamp += 1;
+
+            let mut tree =
+                BlockTree::with_state(miner.blocks[0].clone(), miner.get_state_at(0).unwrap());
+            let result = tree.insert(tampered, |branch, block| miner.check_block(branch, block));
+
+            assert!(matches!(
+                result,
+                Err(TreeError::InvalidBlock(InvalidBlockReason::HashMismatch))
+            ));
+            assert_eq!(tree.stats().blocks, 1);
         }
     }
 }
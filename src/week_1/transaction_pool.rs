@@ -0,0 +1,727 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap},
+};
+
+use super::proof_of_work::{Transaction, TransactionError};
+
+// Transaction Pool
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PoolConfig {
+    // Upper bound for the summed size of the pooled transactions, in bytes
+    pub max_size: usize,
+    // Seconds a transaction can wait in the pool before it is evicted
+    pub max_age: u64,
+    // Percentage a replacement has to raise both fees of the transaction it replaces by
+    pub min_fee_bump: u128,
+}
+
+impl PoolConfig {
+    pub fn new() -> Self {
+        Self {
+            max_size: 1_000_000,
+            max_age: 3 * 60 * 60,
+            min_fee_bump: 10,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct PoolEntry {
+    transaction: Transaction,
+    size: usize,
+    added_at: u64,
+}
+
+impl PoolEntry {
+    fn new(transaction: Transaction, added_at: u64) -> Self {
+        Self {
+            size: transaction.size(),
+            transaction,
+            added_at,
+        }
+    }
+
+    fn priority_fee(&self) -> u128 {
+        self.transaction.max_priority_fee_per_gas()
+    }
+}
+
+// Smallest fee a replacement can offer
+fn bumped_fee(fee: u128, min_fee_bump: u128) -> u128 {
+    fee + (fee * min_fee_bump / 100).max(1)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct PoolStats {
+    pub transactions: usize,
+    pub senders: usize,
+    pub size: usize,
+    // Most the pooled transactions can pay in fees
+    pub total_fees: u128,
+    pub min_priority_fee: Option<u128>,
+    pub max_priority_fee: Option<u128>,
+    pub replaced: u64,
+    pub evicted: u64,
+}
+
+// Pending transactions grouped by sender. Each sender's transactions form a gapless nonce
+// sequence starting at its confirmed nonce, so anything in the pool can be mined once the
+// transactions before it are.
+#[derive(Serialize, Deserialize)]
+pub struct TransactionPool {
+    config: PoolConfig,
+    senders: BTreeMap<String, BTreeMap<u64, PoolEntry>>,
+    size: usize,
+    replaced: u64,
+    evicted: u64,
+}
+
+impl TransactionPool {
+    pub fn new(config: PoolConfig) -> Self {
+        Self {
+            config,
+            senders: BTreeMap::new(),
+            size: 0,
+            replaced: 0,
+            evicted: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.senders.values().map(|entries| entries.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+
+    pub fn contains(&self, transaction: &Transaction) -> bool {
+        let Ok(sender) = transaction.sender() else {
+            return false;
+        };
+
+        self.senders
+            .get(&sender)
+            .and_then(|entries| entries.get(&transaction.nonce()))
+            .is_some_and(|entry| entry.transaction.hash() == transaction.hash())
+    }
+
+    // Pooled transactions, by sender then nonce
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.entries().map(|entry| &entry.transaction)
+    }
+
+    // Nonce following the sender's last pooled transaction
+    pub fn pending_nonce(&self, sender: &str) -> Option<u64> {
+        self.senders
+            .get(sender)
+            .and_then(|entries| entries.keys().next_back())
+            .map(|nonce| nonce + 1)
+    }
+
+    // Most the sender's pooled transactions below the given nonce can spend
+    pub fn pending_cost(&self, sender: &str, nonce: u64) -> u128 {
+        self.senders.get(sender).map_or(0, |entries| {
+            entries
+                .range(..nonce)
+                .map(|(_, entry)| entry.transaction.max_cost())
+                .fold(0, u128::saturating_add)
+        })
+    }
+
+    // Adds a transaction that continues the sender's nonce sequence, or replaces the pooled one
+    // with the same nonce if it pays enough more. Returns the replaced transaction. If the new
+    // transaction is too cheap to fit, the pool is left as it was.
+    pub fn insert(
+        &mut self,
+        transaction: Transaction,
+        sender: String,
+        confirmed_nonce: u64,
+        now: u64,
+    ) -> Result<Option<Transaction>, TransactionError> {
+        // Expired transactions go first, so nothing can build on top of them
+        self.evict(now);
+
+        let nonce = transaction.nonce();
+
+        if nonce < confirmed_nonce {
+            return Err(TransactionError::NonceTooLow {
+                expected: confirmed_nonce,
+                found: nonce,
+            });
+        }
+
+        let entry = PoolEntry::new(transaction, now);
+        let hash = entry.transaction.hash();
+
+        match self
+            .senders
+            .get(&sender)
+            .and_then(|entries| entries.get(&nonce))
+        {
+            Some(existing) => {
+                if existing.transaction.hash() == hash {
+                    return Err(TransactionError::AlreadyKnown);
+                }
+
+                let minimum_max_fee = bumped_fee(
+                    existing.transaction.max_fee_per_gas(),
+                    self.config.min_fee_bump,
+                );
+                let minimum_priority_fee =
+                    bumped_fee(existing.priority_fee(), self.config.min_fee_bump);
+
+                if entry.transaction.max_fee_per_gas() < minimum_max_fee
+                    || entry.priority_fee() < minimum_priority_fee
+                {
+                    return Err(TransactionError::ReplacementUnderpriced {
+                        minimum_max_fee,
+                        minimum_priority_fee,
+                    });
+                }
+            }
+            None => {
+                let expected = self.pending_nonce(&sender).unwrap_or(confirmed_nonce);
+
+                if nonce != expected {
+                    return Err(TransactionError::NonceTooHigh {
+                        expected,
+                        found: nonce,
+                    });
+                }
+            }
+        }
+
+        self.size += entry.size;
+
+        let previous = self
+            .senders
+            .entry(sender.clone())
+            .or_default()
+            .insert(nonce, entry);
+
+        if let Some(previous) = &previous {
+            self.size -= previous.size;
+        }
+
+        let evicted = self.evict_to_size();
+
+        let is_evicted = evicted.iter().any(|(evicted_sender, entry)| {
+            *evicted_sender == sender && entry.transaction.hash() == hash
+        });
+
+        if is_evicted {
+            self.restore(evicted);
+            self.remove(&sender, nonce);
+
+            if let Some(previous) = previous {
+                self.size += previous.size;
+                self.senders
+                    .entry(sender)
+                    .or_default()
+                    .insert(nonce, previous);
+            }
+
+            return Err(TransactionError::PoolFull);
+        }
+
+        if previous.is_some() {
+            self.replaced += 1;
+        }
+
+        Ok(previous.map(|previous| previous.transaction))
+    }
+
+    // Drops transactions older than the configured age, then the cheapest ones until the pool
+    // fits its size cap. Only the last transaction of a sender can go without leaving a gap, so
+    // expiring one takes the sender's later transactions with it.
+    pub fn evict(&mut self, now: u64) {
+        self.expire(now);
+        self.evict_to_size();
+    }
+
+    fn expire(&mut self, now: u64) {
+        let max_age = self.config.max_age;
+
+        for entries in self.senders.values_mut() {
+            let expired = entries
+                .values()
+                .find(|entry| entry.added_at.saturating_add(max_age) < now)
+                .map(|entry| entry.transaction.nonce());
+
+            if let Some(nonce) = expired {
+                for (_, entry) in entries.split_off(&nonce) {
+                    self.size -= entry.size;
+                    self.evicted += 1;
+                }
+            }
+        }
+
+        self.senders.retain(|_, entries| !entries.is_empty());
+    }
+
+    // Drops the cheapest last transactions until the pool fits its size cap, returning them in
+    // the order they were dropped
+    fn evict_to_size(&mut self) -> Vec<(String, PoolEntry)> {
+        let mut evicted = Vec::new();
+
+        while self.size > self.config.max_size {
+            let cheapest = self
+                .senders
+                .iter()
+                .filter_map(|(sender, entries)| entries.last_key_value().map(|last| (sender, last)))
+                .min_by_key(|(_, (_, entry))| (entry.priority_fee(), Reverse(entry.added_at)))
+                .map(|(sender, (nonce, _))| (sender.clone(), *nonce));
+
+            let Some((sender, nonce)) = cheapest else {
+                break;
+            };
+
+            if let Some(entry) = self.remove(&sender, nonce) {
+                self.evicted += 1;
+                evicted.push((sender, entry));
+            }
+        }
+
+        evicted
+    }
+
+    // Puts back evicted transactions, the last evicted first so every sender stays gapless
+    fn restore(&mut self, evicted: Vec<(String, PoolEntry)>) {
+        for (sender, entry) in evicted.into_iter().rev() {
+            self.size += entry.size;
+            self.evicted -= 1;
+            self.senders
+                .entry(sender)
+                .or_default()
+                .insert(entry.transaction.nonce(), entry);
+        }
+    }
+
+    fn remove(&mut self, sender: &str, nonce: u64) -> Option<PoolEntry> {
+        let entries = self.senders.get_mut(sender)?;
+        let entry = entries.remove(&nonce)?;
+
+        if entries.is_empty() {
+            self.senders.remove(sender);
+        }
+
+        self.size -= entry.size;
+
+        Some(entry)
+    }
+
+    // Drops the transactions the chain has already used the nonce of
+    pub fn remove_confirmed(&mut self, confirmed_nonces: &HashMap<String, u64>) {
+        for (sender, entries) in self.senders.iter_mut() {
+            let confirmed = confirmed_nonces.get(sender).copied().unwrap_or_default();
+            let remaining = entries.split_off(&confirmed);
+
+            self.size -= entries.values().map(|entry| entry.size).sum::<usize>();
+            *entries = remaining;
+        }
+
+        self.senders.retain(|_, entries| !entries.is_empty());
+    }
+
+    // Empties the pool, returning its transactions
+    pub fn take_all(&mut self) -> Vec<Transaction> {
+        self.size = 0;
+
+        std::mem::take(&mut self.senders)
+            .into_values()
+            .flat_map(|entries| entries.into_values())
+            .map(|entry| entry.transaction)
+            .collect()
+    }
+
+    // Greedily picks the best tipping transaction that can be mined next, which is the first pooled
+    // one of each sender whose nonce the chain expects, until the gas limit is reached. Transactions
+    // whose max fee doesn't cover the base fee wait, and so do the sender's later ones.
+    pub fn best_transactions(
+        &self,
+        gas_limit: u64,
+        base_fee: u128,
+        confirmed_nonces: &HashMap<String, u64>,
+    ) -> Vec<Transaction> {
+        let mut candidates = BinaryHeap::new();
+
+        let candidate = |sender, nonce, entry: &PoolEntry| {
+            entry
+                .transaction
+                .effective_tip(base_fee)
+                .map(|tip| (tip, Reverse(entry.added_at), Reverse(sender), nonce))
+        };
+
+        for (sender, entries) in self.senders.iter() {
+            let confirmed = confirmed_nonces.get(sender).copied().unwrap_or_default();
+
+            if let Some(entry) = entries.get(&confirmed) {
+                candidates.extend(candidate(sender, confirmed, entry));
+            }
+        }
+
+        let mut transactions = Vec::new();
+        let mut gas_used: u64 = 0;
+
+        while let Some((_, _, Reverse(sender), nonce)) = candidates.pop() {
+            let entries = &self.senders[sender];
+            let transaction = &entries[&nonce].transaction;
+
+            // Every transaction uses the same gas, so once one doesn't fit none does
+            if gas_used.saturating_add(transaction.gas()) > gas_limit {
+                break;
+            }
+
+            gas_used += transaction.gas();
+            transactions.push(transaction.clone());
+
+            if let Some(next) = entries.get(&(nonce + 1)) {
+                candidates.extend(candidate(sender, nonce + 1, next));
+            }
+        }
+
+        transactions
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let priority_fees = || self.entries().map(PoolEntry::priority_fee);
+
+        PoolStats {
+            transactions: self.len(),
+            senders: self.senders.len(),
+            size: self.size,
+            total_fees: self
+                .entries()
+                .map(|entry| {
+                    let transaction = &entry.transaction;
+
+                    transaction.max_cost() - transaction.value()
+                })
+                .sum(),
+            min_priority_fee: priority_fees().min(),
+            max_priority_fee: priority_fees().max(),
+            replaced: self.replaced,
+            evicted: self.evicted,
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &PoolEntry> {
+        self.senders.values().flat_map(|entries| entries.values())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+
+    use crate::week_1::proof_of_work::Transaction;
+
+    pub fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32]).unwrap()
+    }
+
+    pub fn transaction(seed: u8, fee: u128, nonce: u64) -> (Transaction, String) {
+        let transaction = Transaction::sign(&key(seed), String::from("Satoshi"), 1, fee, nonce);
+        let sender = transaction.sender().unwrap();
+
+        (transaction, sender)
+    }
+
+    mod insert {
+        use super::{key, transaction};
+        use crate::week_1::{
+            proof_of_work::{Transaction, TransactionError},
+            transaction_pool::{PoolConfig, TransactionPool},
+        };
+
+        #[test]
+        fn should_accept_a_gapless_nonce_sequence() {
+            let mut pool = TransactionPool::new(PoolConfig::new());
+
+            for nonce in 0..3 {
+                let (transaction, sender) = transaction(1, 10, nonce);
+                pool.insert(transaction, sender, 0, 0).unwrap();
+            }
+
+            let (_, sender) = transaction(1, 10, 0);
+            assert_eq!(pool.len(), 3);
+            assert_eq!(pool.pending_nonce(&sender), Some(3));
+        }
+
+        #[test]
+        fn should_reject_nonce_gaps() {
+            let mut pool = TransactionPool::new(PoolConfig::new());
+            let (transaction, sender) = transaction(1, 10, 1);
+
+            let result = pool.insert(transaction, sender, 0, 0);
+
+            assert_eq!(
+                result.err(),
+                Some(TransactionError::NonceTooHigh {
+                    expected: 0,
+                    found: 1
+                })
+            );
+            assert!(pool.is_empty());
+        }
+
+        #[test]
+        fn should_replace_a_transaction_paying_enough_more() {
+            let mut pool = TransactionPool::new(PoolConfig::new());
+            let (original, sender) = transaction(1, 100, 0);
+            let (replacement, _) = transaction(1, 110, 0);
+
+            pool.insert(original.clone(), sender.clone(), 0, 0).unwrap();
+            let replaced = pool.insert(replacement, sender, 0, 0).unwrap();
+
+            assert_eq!(
+                replaced.map(|replaced| replaced.to_string()),
+                Some(original.to_string())
+            );
+            assert_eq!(pool.len(), 1);
+            assert_eq!(pool.transactions().next().unwrap().max_fee_per_gas(), 110);
+            assert_eq!(pool.stats().replaced, 1);
+        }
+
+        #[test]
+        fn should_reject_an_underpriced_replacement() {
+            let mut pool = TransactionPool::new(PoolConfig::new());
+            let (original, sender) = transaction(1, 100, 0);
+            let (replacement, _) = transaction(1, 109, 0);
+
+            pool.insert(original, sender.clone(), 0, 0).unwrap();
+            let result = pool.insert(replacement, sender, 0, 0);
+
+            assert_eq!(
+                result.err(),
+                Some(TransactionError::ReplacementUnderpriced {
+                    minimum_max_fee: 110,
+                    minimum_priority_fee: 110
+                })
+            );
+            assert_eq!(pool.transactions().next().unwrap().max_fee_per_gas(), 100);
+        }
+
+        #[test]
+        fn should_require_both_fees_to_be_bumped() {
+            let mut pool = TransactionPool::new(PoolConfig::new());
+            let (original, sender) = transaction(1, 100, 0);
+            let replacement =
+                Transaction::sign_with_fees(&key(1), String::from("Satoshi"), 1, 200, 100, 0);
+
+            pool.insert(original, sender.clone(), 0, 0).unwrap();
+            let result = pool.insert(replacement, sender, 0, 0);
+
+            assert_eq!(
+                result.err(),
+                Some(TransactionError::ReplacementUnderpriced {
+                    minimum_max_fee: 110,
+                    minimum_priority_fee: 110
+                })
+            );
+        }
+    }
+
+    mod evict {
+        use super::{key, transaction};
+        use crate::week_1::{
+            proof_of_work::{Transaction, TransactionError},
+            transaction_pool::{PoolConfig, TransactionPool},
+        };
+
+        #[test]
+        fn should_evict_the_cheapest_transaction_over_the_size_cap() {
+            let (cheap, cheap_sender) = transaction(1, 10, 0);
+            let (expensive, expensive_sender) = transaction(2, 50, 0);
+            let (middle, middle_sender) = transaction(3, 30, 0);
+
+            let config = PoolConfig {
+                max_size: cheap.size() * 2,
+                ..PoolConfig::new()
+            };
+            let mut pool = TransactionPool::new(config);
+
+            pool.insert(cheap, cheap_sender.clone(), 0, 0).unwrap();
+            pool.insert(expensive, expensive_sender, 0, 0).unwrap();
+            pool.insert(middle, middle_sender, 0, 0).unwrap();
+
+            assert_eq!(pool.len(), 2);
+            assert_eq!(pool.pending_nonce(&cheap_sender), None);
+            assert_eq!(pool.stats().evicted, 1);
+        }
+
+        #[test]
+        fn should_reject_a_transaction_too_cheap_to_fit() {
+            let (expensive, expensive_sender) = transaction(1, 50, 0);
+            let (cheap, cheap_sender) = transaction(2, 10, 0);
+
+            let config = PoolConfig {
+                max_size: expensive.size(),
+                ..PoolConfig::new()
+            };
+            let mut pool = TransactionPool::new(config);
+
+            pool.insert(expensive, expensive_sender, 0, 0).unwrap();
+            let result = pool.insert(cheap, cheap_sender, 0, 0);
+
+            assert_eq!(result.err(), Some(TransactionError::PoolFull));
+            assert_eq!(pool.len(), 1);
+        }
+
+        #[test]
+        fn should_keep_the_original_when_the_replacement_does_not_fit() {
+            let (original, sender) = transaction(1, 100, 0);
+            let (expensive, expensive_sender) = transaction(2, 500, 0);
+            // The longer recipient makes the replacement bigger than the original
+            let replacement =
+                Transaction::sign(&key(1), String::from("Satoshi Nakamoto"), 1, 110, 0);
+
+            let config = PoolConfig {
+                max_size: original.size() + expensive.size(),
+                ..PoolConfig::new()
+            };
+            let mut pool = TransactionPool::new(config);
+
+            pool.insert(original.clone(), sender.clone(), 0, 0).unwrap();
+            pool.insert(expensive, expensive_sender, 0, 0).unwrap();
+            let result = pool.insert(replacement, sender, 0, 0);
+
+            let stats = pool.stats();
+
+            assert_eq!(result.err(), Some(TransactionError::PoolFull));
+            assert!(pool.contains(&original));
+            assert_eq!(stats.transactions, 2);
+            assert_eq!(stats.size, config.max_size);
+            assert_eq!(stats.replaced, 0);
+            assert_eq!(stats.evicted, 0);
+        }
+
+        #[test]
+        fn should_expire_old_transactions_with_their_successors() {
+            let config = PoolConfig {
+                max_age: 100,
+                ..PoolConfig::new()
+            };
+            let mut pool = TransactionPool::new(config);
+
+            let (first, sender) = transaction(1, 10, 0);
+            let (second, _) = transaction(1, 10, 1);
+            let (other, other_sender) = transaction(2, 10, 0);
+
+            pool.insert(first, sender.clone(), 0, 0).unwrap();
+            pool.insert(second, sender.clone(), 0, 50).unwrap();
+            pool.insert(other, other_sender, 0, 50).unwrap();
+
+            pool.evict(101);
+
+            assert_eq!(pool.len(), 1);
+            assert_eq!(pool.pending_nonce(&sender), None);
+            assert_eq!(pool.stats().evicted, 2);
+        }
+    }
+
+    mod best_transactions {
+        use std::collections::HashMap;
+
+        use super::{key, transaction};
+        use crate::week_1::{
+            proof_of_work::{Transaction, TRANSFER_GAS},
+            transaction_pool::{PoolConfig, TransactionPool},
+        };
+
+        #[test]
+        fn should_order_by_tip_while_keeping_nonce_order() {
+            let mut pool = TransactionPool::new(PoolConfig::new());
+
+            let (low_first, alice) = transaction(1, 10, 0);
+            let (high_second, _) = transaction(1, 90, 1);
+            let (middle, bob) = transaction(2, 50, 0);
+
+            pool.insert(low_first, alice.clone(), 0, 0).unwrap();
+            pool.insert(high_second, alice, 0, 0).unwrap();
+            pool.insert(middle, bob, 0, 0).unwrap();
+
+            let fees: Vec<u128> = pool
+                .best_transactions(10 * TRANSFER_GAS, 0, &HashMap::new())
+                .iter()
+                .map(|transaction| transaction.max_fee_per_gas())
+                .collect();
+
+            assert_eq!(fees, vec![50, 10, 90]);
+        }
+
+        #[test]
+        fn should_stop_at_the_gas_limit() {
+            let mut pool = TransactionPool::new(PoolConfig::new());
+
+            for seed in 1..=3 {
+                let (transaction, sender) = transaction(seed, seed as u128, 0);
+                pool.insert(transaction, sender, 0, 0).unwrap();
+            }
+
+            let fees: Vec<u128> = pool
+                .best_transactions(2 * TRANSFER_GAS, 0, &HashMap::new())
+                .iter()
+                .map(|transaction| transaction.max_fee_per_gas())
+                .collect();
+
+            assert_eq!(fees, vec![3, 2]);
+        }
+
+        #[test]
+        fn should_rank_by_the_tip_left_above_the_base_fee() {
+            let mut pool = TransactionPool::new(PoolConfig::new());
+
+            // At a base fee of 8 the first one only tips 2, and the third can't be included
+            let capped = Transaction::sign_with_fees(&key(1), String::from("Satoshi"), 1, 10, 5, 0);
+            let (legacy, legacy_sender) = transaction(2, 12, 0);
+            let (underpriced, underpriced_sender) = transaction(3, 7, 0);
+            let (after_underpriced, _) = transaction(3, 50, 1);
+
+            pool.insert(capped.clone(), capped.sender().unwrap(), 0, 0)
+                .unwrap();
+            pool.insert(legacy, legacy_sender, 0, 0).unwrap();
+            pool.insert(underpriced, underpriced_sender.clone(), 0, 0)
+                .unwrap();
+            pool.insert(after_underpriced, underpriced_sender, 0, 0)
+                .unwrap();
+
+            let tips: Vec<Option<u128>> = pool
+                .best_transactions(10 * TRANSFER_GAS, 8, &HashMap::new())
+                .iter()
+                .map(|transaction| transaction.effective_tip(8))
+                .collect();
+
+            assert_eq!(tips, vec![Some(4), Some(2)]);
+        }
+    }
+
+    mod stats {
+        use super::transaction;
+        use crate::week_1::transaction_pool::{PoolConfig, TransactionPool};
+
+        #[test]
+        fn should_summarize_the_pool() {
+            let mut pool = TransactionPool::new(PoolConfig::new());
+
+            let (first, sender) = transaction(1, 10, 0);
+            let (second, _) = transaction(1, 30, 1);
+            let size = first.size() + second.size();
+
+            pool.insert(first, sender.clone(), 0, 0).unwrap();
+            pool.insert(second, sender, 0, 0).unwrap();
+
+            let stats = pool.stats();
+
+            assert_eq!(stats.transactions, 2);
+            assert_eq!(stats.senders, 1);
+            assert_eq!(stats.size, size);
+            assert_eq!(stats.total_fees, 40 * 21_000);
+            assert_eq!(stats.min_priority_fee, Some(10));
+            assert_eq!(stats.max_priority_fee, Some(30));
+        }
+    }
+}
@@ -0,0 +1,848 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::digital_signatures::{
+    hash_message, recover_hash_signer, verify_hash_signature, Address, KeyError, Keypair,
+};
+
+// EIP-712 Typed Structured Data
+//
+// A message is a JSON value along with the struct types describing it. Every struct is hashed
+// from the hash of its type followed by one 32 byte word per member: atomic values are padded,
+// strings and bytes are hashed, nested structs are hashed recursively, and arrays hash their
+// encoded elements. What gets signed is that hash bound to the domain it is meant for.
+
+// Struct types by name
+pub type Types = BTreeMap<String, Vec<TypedField>>;
+
+// One member of a struct type, e.g. `address wallet`
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct TypedField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+impl TypedField {
+    pub fn new(name: &str, kind: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: kind.to_string(),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum TypedDataError {
+    InvalidJson(String),
+    UnknownType(String),
+    MissingField { struct_name: String, field: String },
+    InvalidValue { kind: String, value: String },
+    Key(KeyError),
+}
+
+impl From<KeyError> for TypedDataError {
+    fn from(error: KeyError) -> Self {
+        TypedDataError::Key(error)
+    }
+}
+
+// Where a signature is valid. Only the fields that are set are part of the domain type.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Domain {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verifying_contract: Option<Address>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_salt",
+        deserialize_with = "deserialize_salt"
+    )]
+    pub salt: Option<[u8; 32]>,
+}
+
+impl Domain {
+    // hashStruct of the EIP712Domain made of the fields that are set
+    pub fn separator(&self) -> [u8; 32] {
+        let mut fields = Vec::new();
+        let mut words = Vec::new();
+
+        if let Some(name) = &self.name {
+            fields.push("string name");
+            words.push(keccak(name));
+        }
+
+        if let Some(version) = &self.version {
+            fields.push("string version");
+            words.push(keccak(version));
+        }
+
+        if let Some(chain_id) = self.chain_id {
+            let mut word = [0; 32];
+            word[24..].copy_from_slice(&chain_id.to_be_bytes());
+
+            fields.push("uint256 chainId");
+            words.push(word);
+        }
+
+        if let Some(verifying_contract) = self.verifying_contract {
+            fields.push("address verifyingContract");
+            words.push(address_word(verifying_contract));
+        }
+
+        if let Some(salt) = self.salt {
+            fields.push("bytes32 salt");
+            words.push(salt);
+        }
+
+        let mut encoded = keccak(format!("EIP712Domain({})", fields.join(","))).to_vec();
+        encoded.extend(words.concat());
+
+        keccak(encoded)
+    }
+}
+
+fn serialize_salt<S: Serializer>(
+    salt: &Option<[u8; 32]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match salt {
+        Some(salt) => serializer.serialize_str(&format!("0x{}", hex::encode(salt))),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_salt<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<[u8; 32]>, D::Error> {
+    let salt = String::deserialize(deserializer)?;
+
+    parse_bytes(&Value::String(salt))
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom("salt is not 32 bytes of 0x prefixed hex"))
+}
+
+// A Rust type signed as typed data. Its fields serialize to the members of its struct type.
+pub trait TypedStruct: Serialize {
+    const TYPE_NAME: &'static str;
+
+    // Its struct type and those of every struct it references
+    fn types() -> Types;
+}
+
+// The payload of eth_signTypedData_v4
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: Types,
+    pub primary_type: String,
+    pub domain: Domain,
+    pub message: Value,
+}
+
+impl TypedData {
+    pub fn from_json(json: &str) -> Result<Self, TypedDataError> {
+        serde_json::from_str(json).map_err(|error| TypedDataError::InvalidJson(error.to_string()))
+    }
+
+    pub fn from_struct<T: TypedStruct>(
+        domain: Domain,
+        message: &T,
+    ) -> Result<Self, TypedDataError> {
+        Ok(Self {
+            types: T::types(),
+            primary_type: T::TYPE_NAME.to_string(),
+            domain,
+            message: serde_json::to_value(message)
+                .map_err(|error| TypedDataError::InvalidJson(error.to_string()))?,
+        })
+    }
+
+    pub fn struct_hash(&self) -> Result<[u8; 32], TypedDataError> {
+        hash_struct(&self.primary_type, &self.message, &self.types)
+    }
+
+    // keccak256(0x19 || 0x01 || domainSeparator || hashStruct(message))
+    pub fn signing_hash(&self) -> Result<[u8; 32], TypedDataError> {
+        let mut encoded = vec![0x19, 0x01];
+        encoded.extend(self.domain.separator());
+        encoded.extend(self.struct_hash()?);
+
+        Ok(keccak(encoded))
+    }
+
+    pub fn sign(&self, keypair: &Keypair) -> Result<[u8; 65], TypedDataError> {
+        Ok(keypair.sign_hash(&self.signing_hash()?)?)
+    }
+
+    pub fn recover_signer(&self, signature: &[u8]) -> Result<Address, TypedDataError> {
+        Ok(recover_hash_signer(&self.signing_hash()?, signature)?)
+    }
+
+    pub fn verify(&self, signature: &[u8], address: Address) -> Result<(), TypedDataError> {
+        Ok(verify_hash_signature(
+            &self.signing_hash()?,
+            signature,
+            address,
+        )?)
+    }
+}
+
+// The primary type followed by every struct it references, in alphabetical order, e.g.
+// `Mail(Person from,Person to,string contents)Person(string name,address wallet)`
+pub fn encode_type(primary_type: &str, types: &Types) -> Result<String, TypedDataError> {
+    let mut dependencies = BTreeSet::new();
+    find_dependencies(primary_type, types, &mut dependencies);
+
+    if !dependencies.remove(primary_type) {
+        return Err(TypedDataError::UnknownType(primary_type.to_string()));
+    }
+
+    let encoded = std::iter::once(primary_type)
+        .chain(dependencies)
+        .map(|name| {
+            let members: Vec<String> = types[name]
+                .iter()
+                .map(|field| format!("{} {}", field.kind, field.name))
+                .collect();
+
+            format!("{}({})", name, members.join(","))
+        })
+        .collect();
+
+    Ok(encoded)
+}
+
+// Struct types reachable from the given type, itself included
+fn find_dependencies<'a>(kind: &'a str, types: &'a Types, found: &mut BTreeSet<&'a str>) {
+    let base = kind.split('[').next().unwrap_or(kind);
+
+    if let Some((name, fields)) = types.get_key_value(base) {
+        if found.insert(name) {
+            for field in fields {
+                find_dependencies(&field.kind, types, found);
+            }
+        }
+    }
+}
+
+pub fn type_hash(primary_type: &str, types: &Types) -> Result<[u8; 32], TypedDataError> {
+    encode_type(primary_type, types).map(keccak)
+}
+
+// typeHash || enc(member 1) || ... || enc(member n)
+pub fn encode_data(
+    primary_type: &str,
+    data: &Value,
+    types: &Types,
+) -> Result<Vec<u8>, TypedDataError> {
+    let fields = types
+        .get(primary_type)
+        .ok_or_else(|| TypedDataError::UnknownType(primary_type.to_string()))?;
+    let members = data
+        .as_object()
+        .ok_or_else(|| invalid(primary_type, data))?;
+
+    let mut encoded = type_hash(primary_type, types)?.to_vec();
+
+    for field in fields {
+        let value = members
+            .get(&field.name)
+            .ok_or_else(|| TypedDataError::MissingField {
+                struct_name: primary_type.to_string(),
+                field: field.name.clone(),
+            })?;
+
+        encoded.extend(encode_value(&field.kind, value, types)?);
+    }
+
+    Ok(encoded)
+}
+
+pub fn hash_struct(
+    primary_type: &str,
+    data: &Value,
+    types: &Types,
+) -> Result<[u8; 32], TypedDataError> {
+    encode_data(primary_type, data, types).map(keccak)
+}
+
+// The 32 byte word a member of the given type encodes to
+fn encode_value(kind: &str, value: &Value, types: &Types) -> Result<[u8; 32], TypedDataError> {
+    if let Some((element, length)) = kind
+        .strip_suffix(']')
+        .and_then(|kind| kind.rsplit_once('['))
+    {
+        let elements = value.as_array().ok_or_else(|| invalid(kind, value))?;
+
+        if !length.is_empty() && length.parse() != Ok(elements.len()) {
+            return Err(invalid(kind, value));
+        }
+
+        let mut encoded = Vec::new();
+
+        for element_value in elements {
+            encoded.extend(encode_value(element, element_value, types)?);
+        }
+
+        return Ok(keccak(encoded));
+    }
+
+    if types.contains_key(kind) {
+        return hash_struct(kind, value, types);
+    }
+
+    match kind {
+        "string" => value
+            .as_str()
+            .map(keccak)
+            .ok_or_else(|| invalid(kind, value)),
+        "bytes" => parse_bytes(value)
+            .map(keccak)
+            .ok_or_else(|| invalid(kind, value)),
+        "bool" => value
+            .as_bool()
+            .map(|flag| {
+                let mut word = [0; 32];
+                word[31] = flag as u8;
+                word
+            })
+            .ok_or_else(|| invalid(kind, value)),
+        "address" => value
+            .as_str()
+            .and_then(|address| address.parse().ok())
+            .map(address_word)
+            .ok_or_else(|| invalid(kind, value)),
+        _ => {
+            if let Some(size) = kind.strip_prefix("bytes") {
+                let size = parse_size(size, 1, 32, 1).ok_or_else(|| unknown(kind))?;
+
+                parse_bytes(value)
+                    .filter(|bytes| bytes.len() == size)
+                    .map(|bytes| {
+                        let mut word = [0; 32];
+                        word[..size].copy_from_slice(&bytes);
+                        word
+                    })
+                    .ok_or_else(|| invalid(kind, value))
+            } else if let Some(bits) = kind.strip_prefix("uint") {
+                let bits = parse_size(bits, 8, 256, 8).ok_or_else(|| unknown(kind))?;
+
+                parse_integer(value, bits, false).ok_or_else(|| invalid(kind, value))
+            } else if let Some(bits) = kind.strip_prefix("int") {
+                let bits = parse_size(bits, 8, 256, 8).ok_or_else(|| unknown(kind))?;
+
+                parse_integer(value, bits, true).ok_or_else(|| invalid(kind, value))
+            } else {
+                Err(unknown(kind))
+            }
+        }
+    }
+}
+
+// Size suffix of a bytesN, uintN or intN type
+fn parse_size(size: &str, min: usize, max: usize, step: usize) -> Option<usize> {
+    size.parse()
+        .ok()
+        .filter(|size| (min..=max).contains(size) && size % step == 0)
+}
+
+// Either 0x prefixed hex or an array of bytes, the way serde writes a Vec<u8>
+fn parse_bytes(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::String(hex) => hex::decode(hex.strip_prefix("0x")?).ok(),
+        Value::Array(bytes) => bytes
+            .iter()
+            .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+            .collect(),
+        _ => None,
+    }
+}
+
+// Two's complement word of a JSON number, or of a decimal or 0x prefixed hex string, as long as it
+// fits in the given number of bits
+fn parse_integer(value: &Value, bits: usize, signed: bool) -> Option<[u8; 32]> {
+    let digits = match value {
+        Value::Number(number) if number.is_u64() || number.is_i64() => number.to_string(),
+        Value::String(digits) => digits.clone(),
+        _ => return None,
+    };
+
+    let (negative, digits) = match digits.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, digits.as_str()),
+    };
+
+    let magnitude = parse_magnitude(digits)?;
+
+    if negative && magnitude != [0; 32] {
+        // -x is !x + 1
+        let mut word = magnitude.map(|byte| !byte);
+        for byte in word.iter_mut().rev() {
+            let (sum, overflow) = byte.overflowing_add(1);
+            *byte = sum;
+
+            if !overflow {
+                break;
+            }
+        }
+
+        return (signed && high_bits_are(&word, bits - 1, true)).then_some(word);
+    }
+
+    high_bits_are(&magnitude, if signed { bits - 1 } else { bits }, false).then_some(magnitude)
+}
+
+// Big endian 256 bit value of a decimal or 0x prefixed hex string
+fn parse_magnitude(digits: &str) -> Option<[u8; 32]> {
+    if let Some(hex) = digits.strip_prefix("0x") {
+        if hex.is_empty() || hex.len() > 64 {
+            return None;
+        }
+
+        return hex::decode(format!("{:0>64}", hex)).ok()?.try_into().ok();
+    }
+
+    if digits.is_empty() {
+        return None;
+    }
+
+    let mut word = [0u8; 32];
+
+    for digit in digits.chars() {
+        let mut carry = digit.to_digit(10)?;
+
+        for byte in word.iter_mut().rev() {
+            let product = *byte as u32 * 10 + carry;
+            *byte = product as u8;
+            carry = product >> 8;
+        }
+
+        if carry > 0 {
+            return None;
+        }
+    }
+
+    Some(word)
+}
+
+// Whether every bit from the given one up to the 256th is set, or every one is clear
+fn high_bits_are(word: &[u8; 32], from: usize, set: bool) -> bool {
+    (from..256).all(|bit| (word[31 - bit / 8] >> (bit % 8) & 1 == 1) == set)
+}
+
+fn address_word(address: Address) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[12..].copy_from_slice(&address.to_bytes());
+    word
+}
+
+fn keccak(data: impl AsRef<[u8]>) -> [u8; 32] {
+    hash_message(data).try_into().unwrap()
+}
+
+fn invalid(kind: &str, value: &Value) -> TypedDataError {
+    TypedDataError::InvalidValue {
+        kind: kind.to_string(),
+        value: value.to_string(),
+    }
+}
+
+fn unknown(kind: &str) -> TypedDataError {
+    TypedDataError::UnknownType(kind.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    // The example of the EIP, signed by the key keccak256("cow")
+    const MAIL: &str = r#"{
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" }
+            ],
+            "Person": [
+                { "name": "name", "type": "string" },
+                { "name": "wallet", "type": "address" }
+            ],
+            "Mail": [
+                { "name": "from", "type": "Person" },
+                { "name": "to", "type": "Person" },
+                { "name": "contents", "type": "string" }
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+            "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+
+    // Nested arrays of structs along with every atomic type
+    const ORDER: &str = r#"{
+        "types": {
+            "Asset": [
+                { "name": "token", "type": "address" },
+                { "name": "amount", "type": "uint256" },
+                { "name": "id", "type": "bytes32" }
+            ],
+            "Order": [
+                { "name": "maker", "type": "address" },
+                { "name": "offer", "type": "Asset[]" },
+                { "name": "consideration", "type": "Asset[2]" },
+                { "name": "tags", "type": "string[]" },
+                { "name": "partial", "type": "bool" },
+                { "name": "expiry", "type": "uint64" },
+                { "name": "data", "type": "bytes" }
+            ]
+        },
+        "primaryType": "Order",
+        "domain": {
+            "name": "Bootcamp Exchange",
+            "chainId": 31337,
+            "verifyingContract": "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+        },
+        "message": {
+            "maker": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266",
+            "offer": [
+                {
+                    "token": "0x70997970C51812dc3A010C7d01b50e0d17dc79C8",
+                    "amount": "1000000000000000000000",
+                    "id": "0x0101010101010101010101010101010101010101010101010101010101010101"
+                }
+            ],
+            "consideration": [
+                {
+                    "token": "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC",
+                    "amount": "0x2a",
+                    "id": "0x0202020202020202020202020202020202020202020202020202020202020202"
+                },
+                {
+                    "token": "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC",
+                    "amount": 7,
+                    "id": "0x0303030303030303030303030303030303030303030303030303030303030303"
+                }
+            ],
+            "tags": ["limit", "gtc"],
+            "partial": true,
+            "expiry": 1700000000,
+            "data": "0xdeadbeef"
+        }
+    }"#;
+
+    mod encode_type {
+        use crate::week_1::typed_data::{encode_type, TypedData, TypedDataError};
+
+        #[test]
+        fn should_list_the_referenced_structs_after_the_primary_type() {
+            let mail = TypedData::from_json(super::MAIL).unwrap();
+            let order = TypedData::from_json(super::ORDER).unwrap();
+
+            assert_eq!(
+                encode_type("Mail", &mail.types),
+                Ok(String::from(
+                    "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+                ))
+            );
+            assert_eq!(
+                encode_type("Order", &order.types).unwrap(),
+                "Order(address maker,Asset[] offer,Asset[2] consideration,string[] tags,bool partial,uint64 expiry,bytes data)Asset(address token,uint256 amount,bytes32 id)"
+            );
+        }
+
+        #[test]
+        fn should_reject_an_unknown_type() {
+            let mail = TypedData::from_json(super::MAIL).unwrap();
+
+            assert_eq!(
+                encode_type("Letter", &mail.types),
+                Err(TypedDataError::UnknownType(String::from("Letter")))
+            );
+        }
+    }
+
+    mod hash_struct {
+        use serde_json::json;
+
+        use crate::week_1::typed_data::{
+            hash_struct, Domain, TypedData, TypedDataError, TypedField, Types,
+        };
+
+        #[test]
+        fn should_hash_the_mail_example() {
+            let mail = TypedData::from_json(super::MAIL).unwrap();
+
+            assert_eq!(
+                hex::encode(mail.domain.separator()),
+                "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+            );
+            assert_eq!(
+                hex::encode(mail.struct_hash().unwrap()),
+                "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+            );
+            assert_eq!(
+                hex::encode(mail.signing_hash().unwrap()),
+                "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+            );
+        }
+
+        #[test]
+        fn should_encode_negative_integers_in_twos_complement() {
+            let types =
+                Types::from([(String::from("Move"), vec![TypedField::new("delta", "int8")])]);
+
+            let minus_one = hash_struct("Move", &json!({ "delta": -1 }), &types).unwrap();
+            let as_hex = hash_struct("Move", &json!({ "delta": "-0x01" }), &types).unwrap();
+
+            assert_eq!(minus_one, as_hex);
+            assert!(hash_struct("Move", &json!({ "delta": -128 }), &types).is_ok());
+            assert_eq!(
+                hash_struct("Move", &json!({ "delta": -129 }), &types),
+                Err(TypedDataError::InvalidValue {
+                    kind: String::from("int8"),
+                    value: String::from("-129")
+                })
+            );
+            assert!(hash_struct("Move", &json!({ "delta": 128 }), &types).is_err());
+        }
+
+        #[test]
+        fn should_reject_values_that_do_not_fit_their_type() {
+            let types = Types::from([(
+                String::from("Payment"),
+                vec![
+                    TypedField::new("amount", "uint8"),
+                    TypedField::new("ids", "bytes4[2]"),
+                ],
+            )]);
+
+            let payment = |amount: serde_json::Value, ids: serde_json::Value| {
+                hash_struct("Payment", &json!({ "amount": amount, "ids": ids }), &types)
+            };
+
+            assert!(payment(json!(255), json!(["0x01020304", "0x05060708"])).is_ok());
+            assert!(payment(json!(256), json!(["0x01020304", "0x05060708"])).is_err());
+            assert!(payment(json!(-1), json!(["0x01020304", "0x05060708"])).is_err());
+            assert!(payment(json!(1), json!(["0x01020304"])).is_err());
+            assert!(payment(json!(1), json!(["0x0102030405", "0x05060708"])).is_err());
+            assert_eq!(
+                hash_struct("Payment", &json!({ "amount": 1 }), &types),
+                Err(TypedDataError::MissingField {
+                    struct_name: String::from("Payment"),
+                    field: String::from("ids")
+                })
+            );
+        }
+
+        #[test]
+        fn should_bind_the_salt_to_the_domain() {
+            let json = r#"{ "name": "Ether Mail", "salt": "0x00000000000000000000000000000000000000000000000000000000000000ff" }"#;
+
+            let domain: Domain = serde_json::from_str(json).unwrap();
+            let unsalted = Domain {
+                salt: None,
+                ..domain.clone()
+            };
+
+            assert_eq!(domain.salt.unwrap()[31], 0xff);
+            assert_eq!(
+                serde_json::from_value::<Domain>(serde_json::to_value(&domain).unwrap()).unwrap(),
+                domain
+            );
+            assert_ne!(domain.separator(), unsalted.separator());
+        }
+
+        #[test]
+        fn should_hash_like_ethers() {
+            use ethers::types::transaction::eip712::{self, Eip712};
+
+            for json in [super::MAIL, super::ORDER] {
+                let expected = serde_json::from_str::<eip712::TypedData>(json)
+                    .unwrap()
+                    .encode_eip712()
+                    .unwrap();
+
+                assert_eq!(
+                    TypedData::from_json(json).unwrap().signing_hash(),
+                    Ok(expected)
+                );
+            }
+        }
+    }
+
+    mod from_struct {
+        use serde::Serialize;
+
+        use crate::week_1::{
+            digital_signatures::Address,
+            typed_data::{Domain, TypedData, TypedField, TypedStruct, Types},
+        };
+
+        #[derive(Serialize)]
+        struct Person {
+            name: String,
+            wallet: Address,
+        }
+
+        #[derive(Serialize)]
+        struct Mail {
+            from: Person,
+            to: Person,
+            contents: String,
+        }
+
+        impl TypedStruct for Person {
+            const TYPE_NAME: &'static str = "Person";
+
+            fn types() -> Types {
+                Types::from([(
+                    String::from("Person"),
+                    vec![
+                        TypedField::new("name", "string"),
+                        TypedField::new("wallet", "address"),
+                    ],
+                )])
+            }
+        }
+
+        impl TypedStruct for Mail {
+            const TYPE_NAME: &'static str = "Mail";
+
+            fn types() -> Types {
+                let mut types = Person::types();
+                types.insert(
+                    String::from("Mail"),
+                    vec![
+                        TypedField::new("from", "Person"),
+                        TypedField::new("to", "Person"),
+                        TypedField::new("contents", "string"),
+                    ],
+                );
+                types
+            }
+        }
+
+        #[test]
+        fn should_hash_a_rust_struct_like_its_json() {
+            let domain = Domain {
+                name: Some(String::from("Ether Mail")),
+                version: Some(String::from("1")),
+                chain_id: Some(1),
+                verifying_contract: Some(
+                    "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+                        .parse()
+                        .unwrap(),
+                ),
+                salt: None,
+            };
+            let mail = Mail {
+                from: Person {
+                    name: String::from("Cow"),
+                    wallet: "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"
+                        .parse()
+                        .unwrap(),
+                },
+                to: Person {
+                    name: String::from("Bob"),
+                    wallet: "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"
+                        .parse()
+                        .unwrap(),
+                },
+                contents: String::from("Hello, Bob!"),
+            };
+
+            let typed_data = TypedData::from_struct(domain, &mail).unwrap();
+
+            assert_eq!(
+                typed_data.signing_hash(),
+                TypedData::from_json(super::MAIL).unwrap().signing_hash()
+            );
+        }
+    }
+
+    mod sign {
+        use ethers::{
+            signers::{LocalWallet, Signer},
+            types::transaction::eip712,
+        };
+
+        use crate::week_1::{
+            digital_signatures::{hash_message, KeyError, Keypair},
+            typed_data::{TypedData, TypedDataError},
+        };
+
+        #[test]
+        fn should_sign_the_mail_example() {
+            let cow = Keypair::from_bytes(&hash_message("cow")).unwrap();
+            let mail = TypedData::from_json(super::MAIL).unwrap();
+
+            let signature = mail.sign(&cow).unwrap();
+
+            assert_eq!(
+                hex::encode(&signature[..32]),
+                "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d"
+            );
+            assert_eq!(
+                hex::encode(&signature[32..64]),
+                "07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562"
+            );
+            assert_eq!(signature[64], 28);
+            assert_eq!(
+                cow.address().to_string(),
+                "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"
+            );
+            assert_eq!(mail.recover_signer(&signature), Ok(cow.address()));
+        }
+
+        #[tokio::test]
+        async fn should_sign_like_an_ethers_wallet() {
+            let keypair = Keypair::from_seed(1);
+            let wallet = keypair.to_hex().parse::<LocalWallet>().unwrap();
+
+            let expected = wallet
+                .sign_typed_data(&serde_json::from_str::<eip712::TypedData>(super::ORDER).unwrap())
+                .await
+                .unwrap();
+            let signature = TypedData::from_json(super::ORDER)
+                .unwrap()
+                .sign(&keypair)
+                .unwrap();
+
+            assert_eq!(signature.to_vec(), expected.to_vec());
+        }
+
+        #[test]
+        fn should_reject_a_signature_over_another_message() {
+            let keypair = Keypair::from_seed(1);
+            let mail = TypedData::from_json(super::MAIL).unwrap();
+            let signature = mail.sign(&keypair).unwrap();
+
+            let mut tampered = mail.clone();
+            tampered.message["contents"] = serde_json::json!("Hello, Alice!");
+
+            assert_eq!(mail.verify(&signature, keypair.address()), Ok(()));
+            assert!(matches!(
+                tampered.verify(&signature, keypair.address()),
+                Err(TypedDataError::Key(KeyError::SignerMismatch { .. }))
+            ));
+        }
+    }
+}
@@ -1,4 +1,4 @@
 mod binary_search_tree;
-mod merkle_tree;
+pub mod merkle_tree;
 mod trie;
 mod utxo_model;
@@ -1,11 +1,11 @@
 use sha3::Digest;
 
 // Merkle Tree
-struct MerkleeTree {
+pub struct MerkleeTree {
     leaves: Vec<String>,
 }
 
-fn keccak256(data: impl AsRef<[u8]>) -> String {
+pub fn keccak256(data: impl AsRef<[u8]>) -> String {
     let mut hasher = sha3::Keccak256::new();
     hasher.update(data);
     let hash: Vec<u8> = hasher.finalize().into_iter().collect();
@@ -24,17 +24,38 @@ pub fn merge(left: impl AsRef<[u8]>, right: impl AsRef<[u8]>) -> String {
     hex::encode(hash)
 }
 
-struct MerkleeTreeProofNode {
-    data: String,
-    is_left: bool,
+#[derive(Clone, PartialEq, Debug)]
+pub struct MerkleeTreeProofNode {
+    pub data: String,
+    pub is_left: bool,
+}
+
+// Rebuilds the root from a leaf and its proof
+pub fn get_root_from_proof(leaf: impl AsRef<[u8]>, proof: &[MerkleeTreeProofNode]) -> String {
+    proof.iter().fold(keccak256(leaf), |acc, proof_node| {
+        if proof_node.is_left {
+            merge(&proof_node.data, acc)
+        } else {
+            merge(acc, &proof_node.data)
+        }
+    })
+}
+
+pub fn verify_proof(leaf: impl AsRef<[u8]>, proof: &[MerkleeTreeProofNode], root: &str) -> bool {
+    get_root_from_proof(leaf, proof) == root
 }
 
 impl MerkleeTree {
-    fn new(leaves: Vec<String>) -> Self {
+    pub fn new(leaves: Vec<String>) -> Self {
         Self { leaves }
     }
 
-    fn get_root(&self) -> String {
+    // An empty tree commits to the hash of no data
+    pub fn get_root(&self) -> String {
+        if self.leaves.is_empty() {
+            return keccak256([]);
+        }
+
         let hashed_leaves: Vec<String> = self.leaves.iter().map(keccak256).collect();
 
         MerkleeTree::build_root(&hashed_leaves)
@@ -44,17 +65,17 @@ impl MerkleeTree {
         let level_size = level.len();
 
         if level_size == 1 {
-            return level.get(0).unwrap().clone();
+            return level.first().unwrap().clone();
         }
 
         let new_level: Vec<String> = level
             .chunks(2)
             .map(|chunk| {
                 if chunk.len() == 1 {
-                    return chunk.get(0).unwrap().clone();
+                    return chunk.first().unwrap().clone();
                 }
 
-                let left = chunk.get(0).unwrap();
+                let left = chunk.first().unwrap();
                 let right = chunk.get(1).unwrap();
 
                 merge(left, right)
@@ -64,7 +85,7 @@ impl MerkleeTree {
         MerkleeTree::build_root(&new_level)
     }
 
-    fn get_proof(&self, idx: usize) -> Vec<MerkleeTreeProofNode> {
+    pub fn get_proof(&self, idx: usize) -> Vec<MerkleeTreeProofNode> {
         let hashed_leaves: Vec<String> = self.leaves.iter().map(keccak256).collect();
 
         MerkleeTree::build_proof(idx, &hashed_leaves)
@@ -83,14 +104,14 @@ impl MerkleeTree {
             .chunks(2)
             .map(|chunk| {
                 if chunk.len() == 1 {
-                    return chunk.get(0).unwrap().clone();
+                    return chunk.first().unwrap().clone();
                 }
 
-                let left = chunk.get(0).unwrap();
+                let left = chunk.first().unwrap();
                 let right = chunk.get(1).unwrap();
 
                 if counter == idx || counter == idx.saturating_sub(1) {
-                    let is_target_at_left = idx % 2 == 0;
+                    let is_target_at_left = idx.is_multiple_of(2);
 
                     let curr_proof_node = MerkleeTreeProofNode {
                         data: if is_target_at_left {
@@ -115,6 +136,7 @@ impl MerkleeTree {
     }
 }
 
+#[cfg(test)]
 mod test {
 
     mod get_root {
@@ -312,17 +334,7 @@ mod test {
     }
 
     mod get_proof {
-        use crate::week_2::merkle_tree::{keccak256, merge, MerkleeTree, MerkleeTreeProofNode};
-
-        fn build_root_from_proof(leaf: String, proof: Vec<MerkleeTreeProofNode>) -> String {
-            proof.iter().fold(keccak256(leaf), |acc, proof_node| {
-                if proof_node.is_left {
-                    merge(&proof_node.data, acc)
-                } else {
-                    merge(acc, &proof_node.data)
-                }
-            })
-        }
+        use crate::week_2::merkle_tree::{get_root_from_proof, MerkleeTree};
 
         #[test]
         fn should_correctly_build_the_proof_with_7_leaves() {
@@ -344,7 +356,7 @@ mod test {
                 let proof = tree.get_proof(idx);
 
                 // Assert
-                assert_eq!(build_root_from_proof(leaf.clone(), proof), root)
+                assert_eq!(get_root_from_proof(leaf, &proof), root)
             }
         }
 
@@ -369,7 +381,7 @@ mod test {
                 let proof = tree.get_proof(idx);
 
                 // Assert
-                assert_eq!(build_root_from_proof(leaf.clone(), proof), root)
+                assert_eq!(get_root_from_proof(leaf, &proof), root)
             }
         }
     }
@@ -1,10 +1,9 @@
 use std::error::Error;
 
 use ethers::{
-    abi::Address,
     prelude::SignerMiddleware,
     providers::{Http, Middleware, PendingTransaction, Provider},
-    signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer, Wallet},
+    signers::{LocalWallet, Signer, Wallet},
     types::{
         transaction::eip2718::TypedTransaction, BlockId, BlockNumber, TransactionRequest, U256,
     },
@@ -12,6 +11,11 @@ use ethers::{
 };
 use k256::ecdsa::SigningKey;
 
+use crate::{
+    utils::parse_address,
+    week_1::hd_wallet::{DerivationPath, Mnemonic},
+};
+
 const TO_ADDRESS: &str = "0xdD0DC6FB59E100ee4fA9900c2088053bBe14DE92";
 
 #[allow(dead_code)]
@@ -20,17 +24,20 @@ fn create_wallets() -> (Wallet<SigningKey>, Wallet<SigningKey>) {
         .parse::<LocalWallet>()
         .expect("Could not create wallet with given private key");
 
-    let mnemonic_wallet = MnemonicBuilder::<English>::default()
-        .phrase("plate lawn minor crouch bubble evidence palace fringe bamboo laptop dutch ice")
-        .build()
-        .unwrap();
+    // First account of the wallet, at m/44'/60'/0'/0/0
+    let mnemonic_wallet = Mnemonic::from_phrase(
+        "plate lawn minor crouch bubble evidence palace fringe bamboo laptop dutch ice",
+    )
+    .and_then(|mnemonic| mnemonic.derive_keypair("", &DerivationPath::ethereum(0)))
+    .map(|keypair| LocalWallet::from(keypair.signing_key().clone()))
+    .expect("Could not create wallet with given mnemonic");
 
     (private_key_wallet, mnemonic_wallet)
 }
 
 #[allow(dead_code)]
 async fn sign_transaction(wallet: &Wallet<SigningKey>) -> Result<TypedTransaction, Box<dyn Error>> {
-    let to = TO_ADDRESS.parse::<Address>()?;
+    let to = parse_address(TO_ADDRESS)?;
 
     let tx = TransactionRequest::new()
         .to(to)
@@ -59,7 +66,7 @@ async fn send_ether<'client>(
         .await?;
 
     let tx = TransactionRequest::new()
-        .to(to.parse::<Address>()?)
+        .to(parse_address(to)?)
         .value(value)
         .nonce(nonce);
 
@@ -70,7 +77,7 @@ async fn send_ether<'client>(
 
 #[allow(dead_code)]
 async fn find_my_balance(provider: &Provider<Http>, address: &str) -> Result<U256, Box<dyn Error>> {
-    let address = address.parse::<Address>()?;
+    let address = parse_address(address)?;
 
     let balance = provider.get_balance(address, None).await?;
 
@@ -95,7 +102,7 @@ mod tests {
     mod wallets {
         use ethers::signers::Signer;
 
-        use crate::week_3::intro_to_ethers::create_wallets;
+        use crate::{utils::checksum_address, week_3::intro_to_ethers::create_wallets};
 
         #[test]
         fn should_successfully_build_a_wallet_from_a_private_key() {
@@ -104,8 +111,8 @@ mod tests {
 
             // Assert
             assert_eq!(
-                hex::encode(wallet.address()),
-                "5409ED021D9299bf6814279A6A1411A7e866A631".to_lowercase()
+                checksum_address(wallet.address()).to_string(),
+                "0x5409ED021D9299bf6814279A6A1411A7e866A631"
             );
         }
 
@@ -116,8 +123,8 @@ mod tests {
 
             // Assert
             assert_eq!(
-                hex::encode(wallet.address()),
-                "88E9DD325BA8329dDD9825c1d24e8470b25575C1".to_lowercase()
+                checksum_address(wallet.address()).to_string(),
+                "0x88E9DD325BA8329dDD9825c1d24e8470b25575C1"
             );
         }
     }
@@ -127,7 +134,10 @@ mod tests {
 
         use ethers::types::U256;
 
-        use crate::week_3::intro_to_ethers::sign_transaction;
+        use crate::{
+            utils::{checksum_address, WalletSource},
+            week_3::intro_to_ethers::sign_transaction,
+        };
 
         const PRIVATE_KEY: &str =
             "f2f48ee19680706196e2e339e5da3491186e0c4c5030670656b0e0164837257d";
@@ -135,15 +145,15 @@ mod tests {
         #[tokio::test]
         async fn should_sign_the_transaction() -> Result<(), Box<dyn Error>> {
             // Arrange
-            let wallet = crate::utils::get_wallet(Some(PRIVATE_KEY));
+            let wallet = crate::utils::get_wallet(Some(WalletSource::PrivateKey(PRIVATE_KEY)));
 
             // Act
             let tx = sign_transaction(&wallet).await?;
 
             // Assert
             assert_eq!(
-                hex::encode(tx.to().unwrap().as_address().unwrap()),
-                "dD0DC6FB59E100ee4fA9900c2088053bBe14DE92".to_lowercase()
+                checksum_address(*tx.to().unwrap().as_address().unwrap()).to_string(),
+                "0xdD0DC6FB59E100ee4fA9900c2088053bBe14DE92"
             );
             Ok(())
         }
@@ -151,7 +161,7 @@ mod tests {
         #[tokio::test]
         async fn should_have_the_value_field_set_to_1_eth() -> Result<(), Box<dyn Error>> {
             // Arrange
-            let wallet = crate::utils::get_wallet(Some(PRIVATE_KEY));
+            let wallet = crate::utils::get_wallet(Some(WalletSource::PrivateKey(PRIVATE_KEY)));
 
             // Act
             let tx = sign_transaction(&wallet).await?;
@@ -164,9 +174,9 @@ mod tests {
         #[tokio::test]
         async fn should_have_the_gas_limit_set_to_21000() -> Result<(), Box<dyn Error>> {
             // Arrange
-            let wallet = crate::utils::get_wallet(Some(
+            let wallet = crate::utils::get_wallet(Some(WalletSource::PrivateKey(
                 "f2f48ee19680706196e2e339e5da3491186e0c4c5030670656b0e0164837257d",
-            ));
+            )));
 
             // Act
             let tx = sign_transaction(&wallet).await?;
@@ -179,15 +189,15 @@ mod tests {
         #[tokio::test]
         async fn should_set_the_from_address() -> Result<(), Box<dyn Error>> {
             // Arrange
-            let wallet = crate::utils::get_wallet(Some(PRIVATE_KEY));
+            let wallet = crate::utils::get_wallet(Some(WalletSource::PrivateKey(PRIVATE_KEY)));
 
             // Act
             let tx = sign_transaction(&wallet).await?;
 
             // Assert
             assert_eq!(
-                hex::encode(tx.from().unwrap()),
-                "5409ED021D9299bf6814279A6A1411A7e866A631".to_lowercase()
+                checksum_address(*tx.from().unwrap()).to_string(),
+                "0x5409ED021D9299bf6814279A6A1411A7e866A631"
             );
             Ok(())
         }
@@ -198,7 +208,10 @@ mod tests {
 
         use ethers::providers::Middleware;
 
-        use crate::week_3::intro_to_ethers::{send_ether, TO_ADDRESS};
+        use crate::{
+            utils::checksum_address,
+            week_3::intro_to_ethers::{send_ether, TO_ADDRESS},
+        };
 
         #[ignore]
         #[tokio::test]
@@ -218,10 +231,10 @@ mod tests {
 
             // Assert
             assert_eq!(
-                hex::encode(receipt.to.unwrap()),
-                "dD0DC6FB59E100ee4fA9900c2088053bBe14DE92".to_lowercase()
+                checksum_address(receipt.to.unwrap()).to_string(),
+                TO_ADDRESS
             );
-            assert_eq!(hex::encode(receipt.from), hex::encode(expected_from));
+            assert_eq!(receipt.from, expected_from);
             Ok(())
         }
 
@@ -5,12 +5,14 @@ use ethers::{
     types::U64,
 };
 
+use crate::{utils::checksum_address, week_1::digital_signatures::Address};
+
 #[allow(dead_code)]
 async fn find_ether(
     provider: &Provider<Http>,
-    address_from: &str,
-) -> Result<Vec<String>, Box<dyn Error>> {
-    let mut res = Vec::<String>::new();
+    address_from: Address,
+) -> Result<Vec<Address>, Box<dyn Error>> {
+    let mut res = Vec::<Address>::new();
 
     let current_block_number = provider.get_block_number().await?;
 
@@ -21,8 +23,8 @@ async fn find_ether(
         block
             .transactions
             .iter()
-            .filter(|transaction| hex::encode(transaction.from) == address_from)
-            .for_each(|transaction| res.push(hex::encode(transaction.to.unwrap())));
+            .filter(|transaction| checksum_address(transaction.from) == address_from)
+            .for_each(|transaction| res.push(checksum_address(transaction.to.unwrap())));
 
         block_number += U64::from(1);
     }
@@ -39,17 +41,18 @@ mod tests {
     use ethers::signers::Wallet;
     use k256::ecdsa::SigningKey;
 
-    use crate::utils::send_ether;
+    use crate::utils::{checksum_address, send_ether, DEFAULT_ACCOUNT_ADDRESS};
+    use crate::week_1::digital_signatures::Address;
     use crate::week_3::where_is_the_ether::find_ether;
 
     async fn dispatch_ether_n_times(
         client: &SignerMiddleware<Provider<Http>, Wallet<SigningKey>>,
-        addresses: &mut Vec<String>,
+        addresses: &mut Vec<Address>,
         times: i32,
     ) -> Result<(), Box<dyn Error>> {
         for _ in 0..times {
-            let address = hex::encode(crate::utils::generate_fake_random_address());
-            send_ether(client, 5 * (10 ^ 17), Some(&address)).await?;
+            let address = checksum_address(crate::utils::generate_fake_random_address());
+            send_ether(client, 5 * (10 ^ 17), Some(&address.to_string())).await?;
             addresses.push(address);
         }
 
@@ -72,7 +75,7 @@ mod tests {
 
         // Act
         let mut found_addresses =
-            find_ether(provider, "f39fd6e51aad88f6f4ce6ab8827279cfffb92266").await?;
+            find_ether(provider, DEFAULT_ACCOUNT_ADDRESS.parse::<Address>()?).await?;
 
         // Assert
         found_addresses.sort();
@@ -1,14 +1,16 @@
-use std::{convert::TryFrom, error::Error};
+use std::{convert::TryFrom, error::Error, path::Path};
 
 use ethers::{
     contract::Contract,
     prelude::{ContractFactory, SignerMiddleware},
     providers::{Http, Middleware, Provider},
-    signers::{LocalWallet, Signer, Wallet},
+    signers::{Signer, Wallet},
     solc::Solc,
 };
 use k256::ecdsa::SigningKey;
 
+use crate::utils::{get_wallet, PasswordSource, WalletSource};
+
 #[allow(dead_code)]
 async fn deploy(
     path: &str,
@@ -16,12 +18,18 @@ async fn deploy(
 ) -> Result<Contract<SignerMiddleware<Provider<Http>, Wallet<SigningKey>>>, Box<dyn Error>> {
     let url = std::env::var("RPC_URL").unwrap_or_else(|_| "http://localhost:8545".to_string());
 
-    let private_key = std::env::var("PRIVATE_KEY").expect("Private key required");
+    // Prefer an encrypted keystore, falling back on a raw private key
+    let wallet = match std::env::var("KEYSTORE_PATH") {
+        Ok(path) => get_wallet(Some(WalletSource::Keystore {
+            path: Path::new(&path),
+            password: PasswordSource::Env("KEYSTORE_PASSWORD"),
+        })),
+        Err(_) => {
+            let private_key = std::env::var("PRIVATE_KEY").expect("Private key required");
 
-    // Build the wallet with the given private key if valid
-    let wallet = private_key
-        .parse::<LocalWallet>()
-        .expect("Could not create wallet with given private key");
+            get_wallet(Some(WalletSource::PrivateKey(&private_key)))
+        }
+    };
 
     let provider = Provider::<Http>::try_from(url).expect("Could not create rpc provider");
 